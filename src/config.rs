@@ -1,17 +1,10 @@
-use std::sync::{Arc, RwLock};
-use std::collections::HashMap;
+use std::sync::Arc;
 use std::fmt;
+use std::env;
 use std::fs;
-use std::time::Instant;
+use crate::forge::{ForgeKind, MergeMethod};
 
 static CONFIG_FILE_NAME: &str = "docsbot.toml";
-// const REFRESH_EVERY: Duration = Duration::from_secs(2 * 60); // Every two minutes
-
-lazy_static::lazy_static! {
-    static ref CONFIG_CACHE:
-        RwLock<HashMap<String, (Result<Arc<Config>, ConfigurationError>, Instant)>> =
-        RwLock::new(HashMap::new());
-}
 
 #[derive(PartialEq, Eq, Debug, serde::Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -22,7 +15,14 @@ pub struct Config {
 #[derive(PartialEq, Eq, Clone, Debug, serde::Deserialize)]
 pub struct RepoConfig {
     pub name: String,
+    /// Where the repository is hosted, GitHub unless stated otherwise.
+    #[serde(default)]
+    pub forge: ForgeKind,
     pub labels: Vec<LabelConfig>,
+    /// Translations of the docs, tracked to tell which fall behind the docs
+    /// they translate.
+    #[serde(default)]
+    pub translations: Vec<TranslationConfig>,
 }
 
 #[derive(PartialEq, Eq, Clone, Debug, serde::Deserialize)]
@@ -30,6 +30,34 @@ pub struct LabelConfig {
     pub label: String,
     pub base_branch: String,
     pub sync_paths: Vec<SyncPath>,
+    /// Collect the syncs into one pull request kept open on
+    /// `docsbot/sync-<label>`, a commit per merged change request, instead
+    /// of opening a pull request for each.
+    #[serde(default)]
+    pub batch: bool,
+    /// Replay the commits of the change request one by one, keeping their
+    /// authors and dates, instead of squashing them into one commit.
+    #[serde(default)]
+    pub preserve_commits: bool,
+    #[serde(default)]
+    pub mode: SyncMode,
+    /// Merge the sync pull requests with this method once their required
+    /// checks pass.
+    #[serde(default)]
+    pub auto_merge: Option<MergeMethod>,
+}
+
+/// How the synced changes reach the base branch.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SyncMode {
+    /// Through a pull request
+    #[default]
+    Pr,
+    /// Pushed onto the base branch directly, unless they do not apply
+    /// cleanly or the push is rejected, in which case a pull request is
+    /// opened after all
+    Push,
 }
 
 #[derive(PartialEq, Eq, Clone, Debug, serde::Deserialize)]
@@ -38,14 +66,55 @@ pub struct SyncPath {
     pub source_sidebars: String,
     pub target_directory: String,
     pub target_sidebars: String,
+    /// Version the target directory holds, e.g. `2.0.4`. Links to the
+    /// unreleased docs (`/docs/next/...`) are pointed at it, and
+    /// `{{version}}` placeholders filled in with it.
+    #[serde(default)]
+    pub version: Option<String>,
+    /// Applied in order to every line copied to the target directory, after
+    /// the rewrites `version` brings.
+    #[serde(default)]
+    pub rewrites: Vec<RewriteRule>,
+    /// Front-matter keys that belong to the target directory, like `slug`
+    /// or `custom_edit_url`: the docs synced there keep the values they
+    /// have, or go without if they have none.
+    #[serde(default)]
+    pub pinned_front_matter: Vec<String>,
+}
+
+/// Where the translations of the docs to one language are.
+#[derive(PartialEq, Eq, Clone, Debug, serde::Deserialize)]
+pub struct TranslationConfig {
+    /// Language code, e.g. `zh`
+    pub language: String,
+    /// The docs translated, e.g. `docs`
+    pub source_directory: String,
+    /// Their translations, file for file, e.g.
+    /// `i18n/zh/docusaurus-plugin-content-docs/current`
+    pub translation_directory: String,
+    /// Added to the pull requests that leave translations stale, on top of
+    /// listing them in the tracking issue.
+    #[serde(default)]
+    pub stale_label: Option<String>,
+}
+
+/// Replaces what `pattern`, a regular expression, matches with
+/// `replacement`, which can refer to capture groups as `$1` or `$name`.
+#[derive(PartialEq, Eq, Clone, Debug, serde::Deserialize)]
+pub struct RewriteRule {
+    pub pattern: String,
+    pub replacement: String,
 }
 
-pub async fn get_repo_config(repo: &str) -> Result<Arc<RepoConfig>, ConfigurationError> {
+pub async fn get_repo_config(
+    forge: ForgeKind,
+    repo: &str,
+) -> Result<Arc<RepoConfig>, ConfigurationError> {
     let config = parse_config_file()?;
 
     for repo_config in config.repos.iter() {
         let real_repo = repo_config.clone();
-        if real_repo.name.eq(repo) {
+        if real_repo.forge == forge && real_repo.name.eq(repo) {
             return Ok(Arc::new(real_repo));
         }
     }
@@ -53,39 +122,19 @@ pub async fn get_repo_config(repo: &str) -> Result<Arc<RepoConfig>, Configuratio
     Err(ConfigurationError::Missing)
 }
 
+pub fn get_config() -> Result<Arc<Config>, ConfigurationError> {
+    parse_config_file()
+}
+
 fn parse_config_file() -> Result<Arc<Config>, ConfigurationError> {
-    let contents = fs::read_to_string(CONFIG_FILE_NAME).unwrap();
+    let path = env::var("DOCSBOT_CONFIG").unwrap_or_else(|_| CONFIG_FILE_NAME.to_string());
+    let contents = fs::read_to_string(path).map_err(|_| ConfigurationError::NotFound)?;
 
     let config = Arc::new(toml::from_str::<Config>(contents.as_str()).map_err(ConfigurationError::Toml)?);
     log::debug!("parse config {:?}", config);
     Ok(config)
 }
 
-// fn get_cached_config(repo: &str) -> Option<Result<Arc<Config>, ConfigurationError>> {
-//     let cache = CONFIG_CACHE.read().unwrap();
-//     cache.get(repo).and_then(|(config, fetch_time)| {
-//         if fetch_time.elapsed() < REFRESH_EVERY {
-//             Some(config.clone())
-//         } else {
-//             None
-//         }
-//     })
-// }
-
-// async fn get_fresh_config(
-//     gh: &GithubClient,
-//     repo: &str,
-// ) -> Result<Arc<Config>, ConfigurationError> {
-//     let contents = gh
-//         .raw_file(repo, "master", CONFIG_FILE_NAME)
-//         .await
-//         .map_err(|e| ConfigurationError::Http(Arc::new(e)))?
-//         .ok_or(ConfigurationError::Missing)?;
-//     let config = Arc::new(toml::from_slice::<Config>(&contents).map_err(ConfigurationError::Toml)?);
-//     log::debug!("fresh configuration for {}: {:?}", repo, config);
-//     Ok(config)
-// }
-
 #[derive(Clone, Debug)]
 pub enum ConfigurationError {
     Missing,
@@ -1,8 +1,126 @@
-use rusqlite::Connection;
+use rusqlite::{params, Connection};
+
+pub mod branches;
+pub mod conflicts;
+pub mod deliveries;
+pub mod merges;
+pub mod pending;
+pub mod synced;
+pub mod translations;
+
+/// Schema migrations, applied in order. The index of the last applied
+/// migration is tracked through SQLite's `user_version` pragma, so entries
+/// must never be edited or reordered once released; append new ones instead.
+static MIGRATIONS: &[&str] = &["
+CREATE TABLE deliveries (
+    id TEXT PRIMARY KEY,
+    event TEXT NOT NULL,
+    headers TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    received_at TEXT NOT NULL,
+    -- 0 until handled, 2 while being handled, 1 once handled
+    processed INTEGER NOT NULL DEFAULT 0
+);
+", "
+CREATE TABLE pending_jobs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    request TEXT NOT NULL,
+    label TEXT NOT NULL,
+    queued_at TEXT NOT NULL
+);
+", "
+CREATE TABLE synced_changes (
+    forge TEXT NOT NULL,
+    repo_name TEXT NOT NULL,
+    number INTEGER NOT NULL,
+    label TEXT NOT NULL,
+    synced_at TEXT NOT NULL,
+    PRIMARY KEY (forge, repo_name, number, label)
+);
+", "
+CREATE TABLE pending_merges (
+    forge TEXT NOT NULL,
+    repo_name TEXT NOT NULL,
+    number INTEGER NOT NULL,
+    merge_method TEXT NOT NULL,
+    queued_at TEXT NOT NULL,
+    PRIMARY KEY (forge, repo_name, number)
+);
+", "
+CREATE TABLE translation_alignments (
+    forge TEXT NOT NULL,
+    repo_name TEXT NOT NULL,
+    path TEXT NOT NULL,
+    source_commit TEXT NOT NULL,
+    aligned_at TEXT NOT NULL,
+    PRIMARY KEY (forge, repo_name, path)
+);
+CREATE TABLE translation_changes (
+    forge TEXT NOT NULL,
+    repo_name TEXT NOT NULL,
+    language TEXT NOT NULL,
+    path TEXT NOT NULL,
+    source_path TEXT NOT NULL,
+    number INTEGER NOT NULL,
+    patch TEXT,
+    changed_at TEXT NOT NULL,
+    PRIMARY KEY (forge, repo_name, path, number)
+);
+CREATE TABLE translation_issues (
+    forge TEXT NOT NULL,
+    repo_name TEXT NOT NULL,
+    language TEXT NOT NULL,
+    number INTEGER NOT NULL,
+    PRIMARY KEY (forge, repo_name, language)
+);
+", "
+CREATE TABLE batch_conflicts (
+    forge TEXT NOT NULL,
+    repo_name TEXT NOT NULL,
+    branch TEXT NOT NULL,
+    head TEXT NOT NULL,
+    reported_at TEXT NOT NULL,
+    PRIMARY KEY (forge, repo_name, branch, head)
+);
+", "
+CREATE TABLE sync_branches (
+    forge TEXT NOT NULL,
+    repo_name TEXT NOT NULL,
+    branch TEXT NOT NULL,
+    head TEXT NOT NULL,
+    pushed_at TEXT NOT NULL,
+    PRIMARY KEY (forge, repo_name, branch)
+);
+"];
 
 pub fn make_db_conn() -> anyhow::Result<Connection> {
-    let db_url = std::env::var("DATABASE").unwrap_or("docsbot.store".to_string());
-    let conn = Connection::open(db_url).expect("failed to open database");
+    let db_url = std::env::var("DATABASE").unwrap_or_else(|_| "docsbot.store".to_string());
+    let conn = Connection::open(db_url)?;
 
     Ok(conn)
-}
\ No newline at end of file
+}
+
+/// Brings the schema of the database up to date.
+pub fn run_migrations(conn: &Connection) -> anyhow::Result<()> {
+    let version: usize =
+        conn.query_row("PRAGMA user_version", params![], |row| row.get::<_, i64>(0))? as usize;
+
+    for (idx, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        log::info!("applying database migration {}", idx + 1);
+        conn.execute_batch(&format!(
+            "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+            migration,
+            idx + 1
+        ))?;
+    }
+
+    Ok(())
+}
+
+/// Checks that the database can be opened and queried.
+pub fn ping() -> anyhow::Result<()> {
+    let conn = make_db_conn()?;
+    conn.query_row("SELECT 1", params![], |_| Ok(()))?;
+
+    Ok(())
+}
@@ -0,0 +1,28 @@
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::forge::ForgeKind;
+
+/// Records that docsbot pushed `head` onto the sync branch `branch`.
+pub fn record(conn: &Connection, forge: ForgeKind, repo_name: &str, branch: &str, head: &str) -> anyhow::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO sync_branches (forge, repo_name, branch, head, pushed_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![forge.to_string(), repo_name, branch, head, Utc::now().to_rfc3339()],
+    )?;
+
+    Ok(())
+}
+
+/// The commit docsbot last pushed onto the sync branch `branch`, if any.
+pub fn head(conn: &Connection, forge: ForgeKind, repo_name: &str, branch: &str) -> anyhow::Result<Option<String>> {
+    let head = conn
+        .query_row(
+            "SELECT head FROM sync_branches WHERE forge = ?1 AND repo_name = ?2 AND branch = ?3",
+            params![forge.to_string(), repo_name, branch],
+            |row| row.get(0),
+        )
+        .optional()?;
+
+    Ok(head)
+}
@@ -0,0 +1,17 @@
+use chrono::Utc;
+use rusqlite::{params, Connection};
+
+use crate::forge::ForgeKind;
+
+/// Records that `branch` could not be rebased while it pointed at `head`.
+/// Returns false if that was recorded already, in which case the conflict
+/// has been reported.
+pub fn record(conn: &Connection, forge: ForgeKind, repo_name: &str, branch: &str, head: &str) -> anyhow::Result<bool> {
+    let inserted = conn.execute(
+        "INSERT OR IGNORE INTO batch_conflicts (forge, repo_name, branch, head, reported_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![forge.to_string(), repo_name, branch, head, Utc::now().to_rfc3339()],
+    )?;
+
+    Ok(inserted == 1)
+}
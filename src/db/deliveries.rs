@@ -0,0 +1,126 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+
+/// A webhook delivery as received from GitHub, kept so that it can be
+/// deduplicated and replayed later.
+#[derive(Debug, Clone)]
+pub struct Delivery {
+    /// Value of the `X-GitHub-Delivery` header.
+    pub id: String,
+    /// Value of the `X-GitHub-Event` header.
+    pub event: String,
+    pub headers: BTreeMap<String, String>,
+    pub payload: String,
+    pub received_at: DateTime<Utc>,
+}
+
+/// Whether a delivery is for the caller to handle, see [`claim`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Claim {
+    /// New, or handling it failed before: the caller handles it now
+    Claimed,
+    /// Being handled by another request
+    InFlight,
+    Processed,
+}
+
+/// Stores a delivery, unless one with the same ID was stored already, and
+/// claims it for handling. Only one request gets the claim, which lasts
+/// until the delivery is marked processed or released again, so that
+/// GitHub redelivering a failed delivery gets it handled after all.
+pub fn claim(conn: &Connection, delivery: &Delivery) -> anyhow::Result<Claim> {
+    let inserted = conn.execute(
+        "INSERT OR IGNORE INTO deliveries (id, event, headers, payload, received_at, processed)
+         VALUES (?1, ?2, ?3, ?4, ?5, 2)",
+        params![
+            delivery.id,
+            delivery.event,
+            serde_json::to_string(&delivery.headers)?,
+            delivery.payload,
+            delivery.received_at.to_rfc3339(),
+        ],
+    )?;
+    if inserted == 1 {
+        return Ok(Claim::Claimed);
+    }
+
+    let claimed = conn.execute(
+        "UPDATE deliveries SET processed = 2 WHERE id = ?1 AND processed = 0",
+        params![delivery.id],
+    )?;
+    if claimed == 1 {
+        return Ok(Claim::Claimed);
+    }
+
+    let processed: i64 = conn.query_row(
+        "SELECT processed FROM deliveries WHERE id = ?1",
+        params![delivery.id],
+        |row| row.get(0),
+    )?;
+    Ok(if processed == 1 { Claim::Processed } else { Claim::InFlight })
+}
+
+/// Records that a delivery was handled, so that it is not handled again.
+pub fn mark_processed(conn: &Connection, id: &str) -> anyhow::Result<()> {
+    conn.execute("UPDATE deliveries SET processed = 1 WHERE id = ?1", params![id])?;
+
+    Ok(())
+}
+
+/// Gives up the claim on a delivery that could not be handled, for a
+/// redelivery to claim.
+pub fn release(conn: &Connection, id: &str) -> anyhow::Result<()> {
+    conn.execute("UPDATE deliveries SET processed = 0 WHERE id = ?1 AND processed = 2", params![id])?;
+
+    Ok(())
+}
+
+/// Gives up the claims left by a process that stopped halfway through
+/// handling deliveries.
+pub fn release_all(conn: &Connection) -> anyhow::Result<()> {
+    conn.execute("UPDATE deliveries SET processed = 0 WHERE processed = 2", params![])?;
+
+    Ok(())
+}
+
+/// Deletes the deliveries received before `cutoff` that are not being
+/// handled, returning how many.
+pub fn prune(conn: &Connection, cutoff: DateTime<Utc>) -> anyhow::Result<usize> {
+    let pruned = conn.execute(
+        "DELETE FROM deliveries WHERE received_at < ?1 AND processed != 2",
+        params![cutoff.to_rfc3339()],
+    )?;
+
+    Ok(pruned)
+}
+
+pub fn get(conn: &Connection, id: &str) -> anyhow::Result<Option<Delivery>> {
+    let row = conn
+        .query_row(
+            "SELECT id, event, headers, payload, received_at FROM deliveries WHERE id = ?1",
+            params![id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, String>(4)?,
+                ))
+            },
+        )
+        .optional()?;
+
+    match row {
+        Some((id, event, headers, payload, received_at)) => Ok(Some(Delivery {
+            id,
+            event,
+            headers: serde_json::from_str(&headers)?,
+            payload,
+            received_at: DateTime::parse_from_rfc3339(&received_at)?.with_timezone(&Utc),
+        })),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,49 @@
+use chrono::Utc;
+use rusqlite::{params, Connection};
+
+use crate::forge::{ForgeKind, MergeMethod};
+
+/// Records that docsbot is to merge a change request itself once its checks
+/// pass, the forge not doing it.
+pub fn add(conn: &Connection, forge: ForgeKind, repo_name: &str, number: u64, method: MergeMethod) -> anyhow::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO pending_merges (forge, repo_name, number, merge_method, queued_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            forge.to_string(),
+            repo_name,
+            number as i64,
+            method.to_string(),
+            Utc::now().to_rfc3339()
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// The change requests of a repository waiting to be merged, oldest first.
+pub fn list(conn: &Connection, forge: ForgeKind, repo_name: &str) -> anyhow::Result<Vec<(u64, MergeMethod)>> {
+    let rows = conn
+        .prepare(
+            "SELECT number, merge_method FROM pending_merges
+             WHERE forge = ?1 AND repo_name = ?2
+             ORDER BY queued_at",
+        )?
+        .query_map(params![forge.to_string(), repo_name], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    rows.into_iter()
+        .map(|(number, method)| Ok((number as u64, method.parse()?)))
+        .collect()
+}
+
+pub fn remove(conn: &Connection, forge: ForgeKind, repo_name: &str, number: u64) -> anyhow::Result<()> {
+    conn.execute(
+        "DELETE FROM pending_merges WHERE forge = ?1 AND repo_name = ?2 AND number = ?3",
+        params![forge.to_string(), repo_name, number as i64],
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use chrono::Utc;
+use rusqlite::{params, Connection};
+
+use crate::forge::ChangeRequest;
+
+/// Keeps a sync job that could not run before shutdown, identified by the
+/// change request and the label it syncs to, for the next start.
+pub fn save(conn: &Connection, request: &ChangeRequest, label: &str) -> anyhow::Result<()> {
+    conn.execute(
+        "INSERT INTO pending_jobs (request, label, queued_at) VALUES (?1, ?2, ?3)",
+        params![serde_json::to_string(request)?, label, Utc::now().to_rfc3339()],
+    )?;
+
+    Ok(())
+}
+
+/// Removes and returns all pending jobs, oldest first.
+pub fn take_all(conn: &Connection) -> anyhow::Result<Vec<(ChangeRequest, String)>> {
+    let tx = conn.unchecked_transaction()?;
+    let rows = tx
+        .prepare("SELECT request, label FROM pending_jobs ORDER BY id")?
+        .query_map(params![], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    tx.execute("DELETE FROM pending_jobs", params![])?;
+    tx.commit()?;
+
+    rows.into_iter()
+        .map(|(request, label)| Ok((serde_json::from_str(&request)?, label)))
+        .collect()
+}
@@ -0,0 +1,35 @@
+use chrono::Utc;
+use rusqlite::{params, Connection};
+
+use crate::forge::{ChangeRequest, ForgeKind};
+
+/// Records that a change request was synced to a label.
+pub fn record(conn: &Connection, request: &ChangeRequest, label: &str) -> anyhow::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO synced_changes (forge, repo_name, number, label, synced_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            request.forge.to_string(),
+            request.repo_name,
+            request.number as i64,
+            label,
+            Utc::now().to_rfc3339()
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// The labels a change request was synced to.
+pub fn labels(conn: &Connection, forge: ForgeKind, repo_name: &str, number: u64) -> anyhow::Result<Vec<String>> {
+    let labels = conn
+        .prepare(
+            "SELECT label FROM synced_changes
+             WHERE forge = ?1 AND repo_name = ?2 AND number = ?3
+             ORDER BY synced_at",
+        )?
+        .query_map(params![forge.to_string(), repo_name, number as i64], |row| row.get(0))?
+        .collect::<Result<Vec<String>, _>>()?;
+
+    Ok(labels)
+}
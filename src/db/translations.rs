@@ -0,0 +1,128 @@
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension};
+
+use crate::forge::ForgeKind;
+
+/// A translation the docs it translates changed under.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StaleTranslation {
+    pub path: String,
+    /// Commit of the source the translation was last updated along with,
+    /// if it was since docsbot tracks it
+    pub aligned_with: Option<String>,
+    /// The changes to the source since, oldest first
+    pub changes: Vec<SourceChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceChange {
+    pub source_path: String,
+    /// Change request that made it
+    pub number: u64,
+    pub patch: Option<String>,
+}
+
+/// Records that the translation at `path` was updated in `source_commit`,
+/// catching up with the changes to its source.
+pub fn align(conn: &Connection, forge: ForgeKind, repo_name: &str, path: &str, source_commit: &str) -> anyhow::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO translation_alignments (forge, repo_name, path, source_commit, aligned_at)
+         VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![forge.to_string(), repo_name, path, source_commit, Utc::now().to_rfc3339()],
+    )?;
+    conn.execute(
+        "DELETE FROM translation_changes WHERE forge = ?1 AND repo_name = ?2 AND path = ?3",
+        params![forge.to_string(), repo_name, path],
+    )?;
+
+    Ok(())
+}
+
+/// Records a change to the source of the translation at `path` the
+/// translation did not follow.
+pub fn add_change(
+    conn: &Connection,
+    forge: ForgeKind,
+    repo_name: &str,
+    language: &str,
+    path: &str,
+    change: &SourceChange,
+) -> anyhow::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO translation_changes
+         (forge, repo_name, language, path, source_path, number, patch, changed_at)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            forge.to_string(),
+            repo_name,
+            language,
+            path,
+            change.source_path,
+            change.number as i64,
+            change.patch,
+            Utc::now().to_rfc3339()
+        ],
+    )?;
+
+    Ok(())
+}
+
+/// The stale translations to `language`, by path.
+pub fn stale(conn: &Connection, forge: ForgeKind, repo_name: &str, language: &str) -> anyhow::Result<Vec<StaleTranslation>> {
+    let rows = conn
+        .prepare(
+            "SELECT c.path, a.source_commit, c.source_path, c.number, c.patch
+             FROM translation_changes c
+             LEFT JOIN translation_alignments a
+                 ON a.forge = c.forge AND a.repo_name = c.repo_name AND a.path = c.path
+             WHERE c.forge = ?1 AND c.repo_name = ?2 AND c.language = ?3
+             ORDER BY c.path, c.changed_at, c.number",
+        )?
+        .query_map(params![forge.to_string(), repo_name, language], |row| {
+            let change = SourceChange {
+                source_path: row.get(2)?,
+                number: row.get::<_, i64>(3)? as u64,
+                patch: row.get(4)?,
+            };
+            Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?, change))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut stale: Vec<StaleTranslation> = Vec::new();
+    for (path, aligned_with, change) in rows {
+        match stale.last_mut() {
+            Some(last) if last.path == path => last.changes.push(change),
+            _ => stale.push(StaleTranslation {
+                path,
+                aligned_with,
+                changes: vec![change],
+            }),
+        }
+    }
+
+    Ok(stale)
+}
+
+/// The issue tracking the stale translations to `language`, if one was
+/// opened.
+pub fn issue(conn: &Connection, forge: ForgeKind, repo_name: &str, language: &str) -> anyhow::Result<Option<u64>> {
+    let number = conn
+        .query_row(
+            "SELECT number FROM translation_issues WHERE forge = ?1 AND repo_name = ?2 AND language = ?3",
+            params![forge.to_string(), repo_name, language],
+            |row| row.get::<_, i64>(0),
+        )
+        .optional()?;
+
+    Ok(number.map(|n| n as u64))
+}
+
+pub fn set_issue(conn: &Connection, forge: ForgeKind, repo_name: &str, language: &str, number: u64) -> anyhow::Result<()> {
+    conn.execute(
+        "INSERT OR REPLACE INTO translation_issues (forge, repo_name, language, number)
+         VALUES (?1, ?2, ?3, ?4)",
+        params![forge.to_string(), repo_name, language, number as i64],
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,262 @@
+//! Abstraction over the code hosting services docsbot can sync docs on.
+//!
+//! Everything the sync pipeline needs from a forge goes through [`Forge`], and
+//! webhook payloads are turned into forge-neutral [`ChangeRequest`]s as soon
+//! as they are parsed.
+
+use std::fmt;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, FixedOffset};
+
+use crate::git::GitCredential;
+
+/// Which code hosting service a repository lives on.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForgeKind {
+    #[default]
+    Github,
+    Gitlab,
+    Gitea,
+}
+
+impl fmt::Display for ForgeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ForgeKind::Github => write!(f, "github"),
+            ForgeKind::Gitlab => write!(f, "gitlab"),
+            ForgeKind::Gitea => write!(f, "gitea"),
+        }
+    }
+}
+
+/// How a change request is merged.
+#[derive(PartialEq, Eq, Clone, Copy, Debug, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeMethod {
+    Merge,
+    Squash,
+    Rebase,
+}
+
+impl fmt::Display for MergeMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MergeMethod::Merge => write!(f, "merge"),
+            MergeMethod::Squash => write!(f, "squash"),
+            MergeMethod::Rebase => write!(f, "rebase"),
+        }
+    }
+}
+
+impl std::str::FromStr for MergeMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<MergeMethod> {
+        match s {
+            "merge" => Ok(MergeMethod::Merge),
+            "squash" => Ok(MergeMethod::Squash),
+            "rebase" => Ok(MergeMethod::Rebase),
+            other => Err(anyhow::anyhow!("unknown merge method `{}`", other)),
+        }
+    }
+}
+
+/// A pull request (GitHub, Gitea) or merge request (GitLab).
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ChangeRequest {
+    pub forge: ForgeKind,
+    /// `owner/name` on GitHub and Gitea, the full project path on GitLab
+    pub repo_name: String,
+    /// Pull request number, or merge request IID
+    pub number: u64,
+    pub title: String,
+    pub body: Option<String>,
+    pub html_url: String,
+    pub author: String,
+    pub labels: Vec<String>,
+    pub merged: bool,
+    pub merge_commit_sha: Option<String>,
+}
+
+impl ChangeRequest {
+    /// The number of the change request this one reverts, going by what
+    /// GitHub's "Revert" button writes: a `Revert "<title>"` title and a
+    /// `Reverts <repo>#<number>` description.
+    pub fn reverted_number(&self) -> Option<u64> {
+        if !self.title.starts_with("Revert \"") {
+            return None;
+        }
+
+        self.body.as_deref()?.lines().find_map(|line| {
+            let reference = line.trim().strip_prefix("Reverts ")?;
+            let (repo, number) = reference.split_once('#')?;
+            if !repo.is_empty() && repo != self.repo_name {
+                return None;
+            }
+            number.trim().parse().ok()
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileStatus {
+    Added,
+    Removed,
+    Modified,
+    Renamed,
+}
+
+/// A file touched by a change request, with its patch in unified diff
+/// format (hunks only, without the `---`/`+++` header).
+#[derive(Debug, Clone)]
+pub struct ChangedFile {
+    pub filename: String,
+    pub previous_filename: Option<String>,
+    pub status: FileStatus,
+    /// Absent for binary files and very large diffs
+    pub patch: Option<String>,
+}
+
+/// A commit of a change request, as the author made it.
+#[derive(Debug, Clone)]
+pub struct ChangeCommit {
+    pub sha: String,
+    pub message: String,
+    pub author_name: String,
+    pub author_email: String,
+    pub author_date: DateTime<FixedOffset>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NewChangeRequest<'a> {
+    pub title: &'a str,
+    pub body: &'a str,
+    /// Branch holding the changes
+    pub head: &'a str,
+    /// Branch the changes should be merged into
+    pub base: &'a str,
+}
+
+/// An open change request, as found by its source branch or just opened.
+#[derive(Debug, Clone)]
+pub struct OpenChangeRequest {
+    /// Pull request number, or merge request IID
+    pub number: u64,
+    pub html_url: String,
+    pub body: Option<String>,
+}
+
+#[async_trait::async_trait]
+pub trait Forge: Send + Sync {
+    fn kind(&self) -> ForgeKind;
+
+    /// URL git clones and pushes `repo_name` through.
+    fn clone_url(&self, repo_name: &str) -> String;
+
+    /// What git authenticates to [`Forge::clone_url`] with.
+    fn git_credentials(&self) -> anyhow::Result<GitCredential>;
+
+    /// Parses a webhook payload, returning the change request it is about if
+    /// it reports one being merged.
+    fn parse_merge_event(&self, event: &str, payload: &str)
+        -> anyhow::Result<Option<ChangeRequest>>;
+
+    /// Lists the files changed by a change request.
+    async fn changed_files(&self, repo_name: &str, number: u64) -> anyhow::Result<Vec<ChangedFile>>;
+
+    /// Lists the commits of a change request, in no particular order.
+    async fn change_request_commits(&self, repo_name: &str, number: u64) -> anyhow::Result<Vec<ChangeCommit>>;
+
+    /// Ref the commits of a change request can be fetched from, even after
+    /// its branch is gone.
+    fn change_request_ref(&self, number: u64) -> String;
+
+    /// Tells whether `branch` exists, as far as the API can see.
+    async fn branch_exists(&self, repo_name: &str, branch: &str) -> anyhow::Result<bool>;
+
+    /// Lists the branches whose name starts with `prefix`.
+    async fn list_branches(&self, repo_name: &str, prefix: &str) -> anyhow::Result<Vec<String>>;
+
+    async fn delete_branch(&self, repo_name: &str, branch: &str) -> anyhow::Result<()>;
+
+    /// Finds the open change request that has `head` as its source branch.
+    async fn find_open_change_request(
+        &self,
+        repo_name: &str,
+        head: &str,
+    ) -> anyhow::Result<Option<OpenChangeRequest>>;
+
+    async fn open_change_request(
+        &self,
+        repo_name: &str,
+        request: &NewChangeRequest<'_>,
+    ) -> anyhow::Result<OpenChangeRequest>;
+
+    /// Replaces the title and description of a change request.
+    async fn update_change_request(
+        &self,
+        repo_name: &str,
+        number: u64,
+        title: &str,
+        body: &str,
+    ) -> anyhow::Result<()>;
+
+    /// Has the forge merge a change request with `method` once its required
+    /// checks pass.
+    async fn enable_auto_merge(&self, repo_name: &str, number: u64, method: MergeMethod) -> anyhow::Result<()>;
+
+    async fn post_comment(&self, repo_name: &str, number: u64, body: &str) -> anyhow::Result<()>;
+
+    async fn add_labels(&self, repo_name: &str, number: u64, labels: &[String]) -> anyhow::Result<()>;
+}
+
+/// An API call the forge answered with an error status, along with what
+/// the response said about trying again.
+#[derive(thiserror::Error, Debug)]
+#[error("the forge API answered {status}")]
+pub struct ApiError {
+    pub status: reqwest::StatusCode,
+    /// Whether the rate limit ran out, which GitHub also answers with a 403
+    pub rate_limited: bool,
+    /// How long the forge asked to wait before trying again
+    pub retry_after: Option<Duration>,
+    source: reqwest::Error,
+}
+
+/// Turns an error status of `resp` into an [`ApiError`].
+pub(crate) fn error_for_status(resp: reqwest::Response) -> Result<reqwest::Response, ApiError> {
+    let source = match resp.error_for_status_ref() {
+        Ok(_) => return Ok(resp),
+        Err(source) => source,
+    };
+
+    let header = |name: &str| {
+        resp.headers()
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+    };
+    let exhausted = header("X-RateLimit-Remaining") == Some(0);
+    let retry_after = match header("Retry-After") {
+        Some(secs) => Some(Duration::from_secs(secs)),
+        None if exhausted => header("X-RateLimit-Reset").map(|reset| {
+            let now = SystemTime::UNIX_EPOCH.elapsed().unwrap_or_default().as_secs();
+            Duration::from_secs(reset.saturating_sub(now))
+        }),
+        None => None,
+    };
+
+    Err(ApiError {
+        status: resp.status(),
+        rate_limited: exhausted || retry_after.is_some(),
+        retry_after,
+        source,
+    })
+}
+
+/// Whether an API call failed because the resource does not exist.
+pub(crate) fn is_not_found(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<ApiError>().map(|e| e.status) == Some(reqwest::StatusCode::NOT_FOUND)
+}
@@ -1,5 +1,9 @@
-use std::path::PathBuf;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::os::raw::{c_char, c_int};
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use anyhow::Context;
 use dialoguer::PasswordInput;
 use git2::Error;
 use git2::{Repository, Branch, BranchType, Index, Tree, Commit};
@@ -49,6 +53,19 @@ pub struct CloneError {
     pub remote_url: String,
 }
 
+/// Why [`Git::push_branch`] failed.
+#[derive(thiserror::Error, Debug)]
+pub enum PushError {
+    /// The branch does not point at the lease on the remote
+    #[error("{0} on the remote is not at the expected commit")]
+    StaleLease(String),
+    #[error("pushing {branch} was rejected: {message}")]
+    Rejected { branch: String, message: String },
+    /// The remote could not be reached, or git failed on its own
+    #[error("failed to push {branch}: {message}")]
+    Failed { branch: String, message: String },
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct GitCredential {
     username: String,
@@ -137,30 +154,171 @@ impl Git {
         new_branch: &str,
         base_branch: &str,
     ) -> anyhow::Result<Branch<'a>, Error> {
+        let base_branch_name = base_branch;
         let base_branch = repo.find_branch(base_branch, BranchType::Local)?;
 
-        let oid = base_branch.get().target().unwrap();
+        let oid = base_branch.get().target().ok_or_else(|| {
+            Error::from_str(&format!("branch {} is a symbolic reference", base_branch_name))
+        })?;
         let commit = repo.find_commit(oid)?;
 
         repo.branch(new_branch, &commit, false)
     }
 
+    /// Creates `branch` from the branch of the same name on `remote_name`,
+    /// as it was when cloning.
+    pub fn create_branch_from_remote<'a>(
+        &self,
+        repo: &'a Repository,
+        branch: &str,
+        remote_name: &str,
+    ) -> anyhow::Result<Branch<'a>, Error> {
+        let commit = repo
+            .find_reference(&format!("refs/remotes/{}/{}", remote_name, branch))?
+            .peel_to_commit()?;
+
+        repo.branch(branch, &commit, false)
+    }
+
+    /// Rebases the checked out branch onto `onto`, leaving it as it was if
+    /// that does not go through cleanly.
+    pub fn rebase_by_command(&self, repo_dir: &Path, onto: &str) -> anyhow::Result<()> {
+        let output = Command::new("git")
+            .current_dir(repo_dir)
+            .args(["rebase", onto])
+            .output()
+            .context("failed to execute git rebase")?;
+        if !output.status.success() {
+            let _ = Command::new("git")
+                .current_dir(repo_dir)
+                .args(["rebase", "--abort"])
+                .output();
+            // The conflicts are listed on stdout, why git gave up on stderr.
+            anyhow::bail!(
+                "git rebase exited with {}: {} {}",
+                output.status,
+                String::from_utf8_lossy(&output.stdout).trim(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        Ok(())
+    }
+
+    /// Pushes `branch`, replacing whatever the remote has under that name as
+    /// long as it points at `lease` (None if the branch must not exist).
+    /// Otherwise the push fails with [`PushError::StaleLease`].
     pub fn push_branch(
+        &self,
+        repo_dir: &Path,
+        branch: &str,
+        remote_name: &str,
+        lease: Option<git2::Oid>,
+    ) -> anyhow::Result<(), PushError> {
+        // libgit2 has no `--force-with-lease`, so git itself does the push.
+        let refname = format!("refs/heads/{}", branch);
+        let lease = format!(
+            "--force-with-lease={}:{}",
+            refname,
+            lease.map(|oid| oid.to_string()).unwrap_or_default()
+        );
+        let mut command = Command::new("git");
+        // The certificates of `add_ca_certificates` only reach libgit2.
+        if let Ok(path) = std::env::var("GITHUB_CA_CERT") {
+            command.arg("-c").arg(format!("http.sslCAInfo={}", path));
+        }
+        let output = command
+            .current_dir(repo_dir)
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .env("DOCSBOT_GIT_USERNAME", &self.cred.username)
+            .env("DOCSBOT_GIT_PASSWORD", &self.cred.password)
+            .args([
+                "-c",
+                "credential.helper=",
+                "-c",
+                "credential.helper=!f() { echo \"username=$DOCSBOT_GIT_USERNAME\"; \
+                 echo \"password=$DOCSBOT_GIT_PASSWORD\"; }; f",
+                "push",
+                "--porcelain",
+                &lease,
+                remote_name,
+                &ref_by_branch(branch),
+            ])
+            .output()
+            .map_err(|source| PushError::Failed {
+                branch: branch.to_string(),
+                message: format!("failed to execute git push: {}", source),
+            })?;
+        if output.status.success() {
+            return Ok(());
+        }
+
+        // `--porcelain` reports each reference on a line of its own, a
+        // rejected one starting with `!`.
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let rejection = stdout
+            .lines()
+            .find(|line| line.starts_with('!') && line.contains(&refname));
+        Err(match rejection {
+            Some(line) if line.contains("stale info") => PushError::StaleLease(branch.to_string()),
+            Some(line) => PushError::Rejected {
+                branch: branch.to_string(),
+                message: line.trim_start_matches('!').trim().to_string(),
+            },
+            None => PushError::Failed {
+                branch: branch.to_string(),
+                message: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            },
+        })
+    }
+
+    /// Pushes `branch` onto `remote_branch`, as long as that is a fast
+    /// forward.
+    pub fn push_to(
         &self,
         repo: &Repository,
         branch: &str,
         remote_name: &str,
+        remote_branch: &str,
     ) -> anyhow::Result<(), Error> {
-        let mut origin = repo.find_remote(remote_name).unwrap();
+        let mut origin = repo.find_remote(remote_name)?;
+        self.push_refspec(
+            &mut origin,
+            &format!("refs/heads/{}:refs/heads/{}", branch, remote_branch),
+        )
+    }
 
-        let remote_callbacks = self.create_remote_callback().unwrap();
+    /// Pushes `refspec`. A rejected push fails with an error of class
+    /// `Reference`, with code `NotFastForward` if the remote moved.
+    fn push_refspec(&self, origin: &mut git2::Remote, refspec: &str) -> anyhow::Result<(), Error> {
+        // Rejections are reported per reference rather than as an error.
+        let rejection = RefCell::new(None);
+        let mut remote_callbacks = self.create_remote_callback()?;
+        remote_callbacks.push_update_reference(|refname, status| {
+            if let Some(status) = status {
+                *rejection.borrow_mut() = Some(format!("{} was rejected: {}", refname, status));
+            }
+            Ok(())
+        });
 
         let mut po = git2::PushOptions::new();
         po.remote_callbacks(remote_callbacks);
 
-        origin.push(&[&ref_by_branch(branch)], Some(&mut po)).unwrap();
-
-        Ok(())
+        origin.push(&[refspec], Some(&mut po))?;
+        drop(po);
+
+        match rejection.into_inner() {
+            Some(message) => {
+                // `non-fast-forward` and `fetch first` mean the remote moved
+                // under us, which another attempt may get past.
+                let code = if message.contains("fast-forward") || message.contains("fetch first") {
+                    git2::ErrorCode::NotFastForward
+                } else {
+                    git2::ErrorCode::GenericError
+                };
+                Err(Error::new(code, git2::ErrorClass::Reference, message))
+            }
+            None => Ok(()),
+        }
     }
 
     pub fn commit_index(
@@ -169,36 +327,49 @@ impl Git {
         index: &mut Index,
         msg: &str,
     ) -> anyhow::Result<(), Error> {
-        let tree_id = index.write_tree().unwrap();
-        let result_tree = repo.find_tree(tree_id).unwrap();
+        let tree_id = index.write_tree()?;
+        let result_tree = repo.find_tree(tree_id)?;
 
-        let head_oid = repo.
-            head()
-            .unwrap()
-            .target()
-            .expect("Head needs oid");
-        let head_commit = repo.find_commit(head_oid).unwrap();
+        let head_commit = repo.head()?.peel_to_commit()?;
 
         log::info!("{}", head_commit.author());
         log::info!("{}", head_commit.id());
-        log::info!("{}", head_commit.message().unwrap());
+        log::info!("{}", head_commit.message().unwrap_or_default());
         log::info!("{}", result_tree.id());
 
-        self.commit_tree(&repo, &result_tree, msg, &[&head_commit]).unwrap();
+        self.commit_tree(repo, &result_tree, msg, &[&head_commit])?;
 
         Ok(())
     }
 
     pub fn commit_by_command(
         &self,
-        repo_dir: &str,
+        repo_dir: &Path,
         msg: &str,
     ) -> anyhow::Result<()> {
-        Command::new("git")
-            .current_dir(repo_dir)
-            .args(&["commit", "-m", msg, "-s"])
-            .output().expect("failed to execute git commit");
-        Ok(())
+        run_commit(repo_dir, &["commit", "-m", msg, "-s"])
+    }
+
+    /// Like [`Git::commit_by_command`], but with the author and author date
+    /// of the commit the changes are copied from.
+    pub fn commit_as_by_command(
+        &self,
+        repo_dir: &Path,
+        msg: &str,
+        author: &str,
+        date: &str,
+    ) -> anyhow::Result<()> {
+        run_commit(repo_dir, &["commit", "-m", msg, "-s", "--author", author, "--date", date])
+    }
+
+    /// Fetches `refspec` from `remote_name`, for objects the clone did not
+    /// bring along.
+    pub fn fetch_ref(&self, repo: &Repository, remote_name: &str, refspec: &str) -> anyhow::Result<(), Error> {
+        let mut fo = git2::FetchOptions::new();
+        fo.remote_callbacks(self.create_remote_callback()?);
+
+        repo.find_remote(remote_name)?
+            .fetch(&[refspec], Some(&mut fo), None)
     }
 
     pub fn commit_first(
@@ -207,10 +378,10 @@ impl Git {
         index: &mut Index,
         msg: &str,
     ) -> anyhow::Result<(), Error> {
-        let tree_id = index.write_tree().unwrap();
-        let result_tree = repo.find_tree(tree_id).unwrap();
+        let tree_id = index.write_tree()?;
+        let result_tree = repo.find_tree(tree_id)?;
 
-        self.commit_tree(&repo, &result_tree, msg, &[]).unwrap();
+        self.commit_tree(repo, &result_tree, msg, &[])?;
 
         Ok(())
     }
@@ -222,13 +393,13 @@ impl Git {
         msg: &str,
         parents: &[&Commit],
     ) -> anyhow::Result<(), Error> {
-        let sig = repo.signature().unwrap();
-        let _merge_commit = repo.commit(Some("HEAD"), &sig, &sig, msg, tree, parents).unwrap();
-        repo.checkout_head(Some(git2::build::CheckoutBuilder::default().force())).unwrap();
+        let sig = repo.signature()?;
+        let _merge_commit = repo.commit(Some("HEAD"), &sig, &sig, msg, tree, parents)?;
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::default().force()))?;
         Ok(())
     }
 
-    pub fn create_remote_callback(&self) -> anyhow::Result<git2::RemoteCallbacks, Error> {
+    pub fn create_remote_callback(&self) -> anyhow::Result<git2::RemoteCallbacks<'_>, Error> {
         let mut cb = git2::RemoteCallbacks::new();
         let git_config = git2::Config::open_default()?;
         let credential_ui: Box<dyn CredentialUI> = Box::new(self.cred.clone());
@@ -241,6 +412,48 @@ impl Git {
     }
 }
 
+fn run_commit(repo_dir: &Path, args: &[&str]) -> anyhow::Result<()> {
+    let output = Command::new("git")
+        .current_dir(repo_dir)
+        .args(args)
+        .output()
+        .context("failed to execute git commit")?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "git commit exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
 pub fn ref_by_branch(branch: &str) -> String {
     format!("refs/heads/{}:refs/heads/{}", branch, branch)
-}
\ No newline at end of file
+}
+/// Makes libgit2 trust the PEM certificates in `path` in addition to the
+/// system ones, e.g. for a GitHub Enterprise Server behind a private CA.
+/// Applies to every repository operation of the process, pushes made with
+/// git itself get `GITHUB_CA_CERT` in [`Git::push_branch`].
+pub fn add_ca_certificates(path: &Path) -> anyhow::Result<()> {
+    let file = CString::new(
+        path.to_str()
+            .ok_or_else(|| anyhow::anyhow!("{:?} is not valid UTF-8", path))?,
+    )?;
+
+    libgit2_sys::init();
+    let rc = unsafe {
+        libgit2_sys::git_libgit2_opts(
+            libgit2_sys::GIT_OPT_SET_SSL_CERT_LOCATIONS as c_int,
+            file.as_ptr(),
+            std::ptr::null::<c_char>(),
+        )
+    };
+    if rc < 0 {
+        return Err(Error::last_error(rc)
+            .map(anyhow::Error::from)
+            .unwrap_or_else(|| anyhow::anyhow!("failed to load {:?}", path)));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,429 @@
+use anyhow::Context;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, RequestBuilder};
+use sha2::Sha256;
+
+use crate::forge::{
+    error_for_status, is_not_found, ChangeCommit, ChangeRequest, ChangedFile, FileStatus, Forge, ForgeKind, MergeMethod,
+    NewChangeRequest, OpenChangeRequest,
+};
+use crate::git::GitCredential;
+use crate::metrics;
+use crate::webhook::deserialize_payload;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct User {
+    pub login: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Label {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Repository {
+    pub full_name: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PullRequest {
+    pub number: u64,
+    pub title: String,
+    #[serde(default)]
+    pub body: Option<String>,
+    pub html_url: String,
+    pub user: User,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+    #[serde(default)]
+    pub merged: bool,
+    #[serde(default)]
+    pub merge_commit_sha: Option<String>,
+}
+
+/// Payload of a Gitea (or Forgejo) `pull_request` webhook.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PullRequestEvent {
+    /// opened, closed, reopened, edited, label_updated, synchronized, ...
+    pub action: String,
+    pub number: u64,
+    pub pull_request: PullRequest,
+    pub repository: Repository,
+}
+
+impl PullRequestEvent {
+    /// Gitea reports merges as `closed` with `merged` set, like GitHub.
+    pub fn is_closed_and_merged(&self) -> bool {
+        self.action == "closed" && self.pull_request.merged
+    }
+
+    pub fn to_change_request(&self) -> ChangeRequest {
+        let pr = &self.pull_request;
+        ChangeRequest {
+            forge: ForgeKind::Gitea,
+            repo_name: self.repository.full_name.clone(),
+            number: pr.number,
+            title: pr.title.clone(),
+            body: pr.body.clone(),
+            html_url: pr.html_url.clone(),
+            author: pr.user.login.clone(),
+            labels: pr.labels.iter().map(|l| l.name.clone()).collect(),
+            merged: pr.merged,
+            merge_commit_sha: pr.merge_commit_sha.clone(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct CreatedPullRequest {
+    number: u64,
+    html_url: String,
+    body: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct GiteaClient {
+    token: String,
+    client: Client,
+    api_url: String,
+    git_url: String,
+}
+
+impl GiteaClient {
+    /// Talks to the Gitea instance at `url` (e.g. `https://gitea.example.com`).
+    pub fn new(client: Client, token: String, url: &str) -> Self {
+        let url = url.trim_end_matches('/');
+        GiteaClient {
+            client,
+            token,
+            api_url: format!("{}/api/v1", url),
+            git_url: url.to_string(),
+        }
+    }
+
+    /// Builds a client from `GITEA_API_TOKEN` and `GITEA_URL`. Returns None
+    /// unless both are set, as there is no public Gitea instance to default to.
+    pub fn from_env(client: Client) -> Option<Self> {
+        let token = std::env::var("GITEA_API_TOKEN").ok()?;
+        let url = std::env::var("GITEA_URL").ok()?;
+        Some(GiteaClient::new(client, token, &url))
+    }
+
+    async fn send(&self, req: RequestBuilder) -> anyhow::Result<reqwest::Response> {
+        let req = req
+            .header("Authorization", format!("token {}", self.token))
+            .build()?;
+        let req_dbg = format!("{} {}", req.method(), req.url());
+        let resp = self.client.execute(req).await.context(req_dbg.clone())?;
+        metrics::GITEA_API_CALLS
+            .with_label_values(&[resp.status().as_str()])
+            .inc();
+
+        error_for_status(resp).context(req_dbg)
+    }
+}
+
+/// Checks the `X-Gitea-Signature` header of a webhook, the hex encoded
+/// HMAC-SHA256 of the payload keyed with `GITEA_WEBHOOK_SECRET`.
+pub fn verify_signature(signature: Option<&str>, payload: &[u8]) -> bool {
+    let secret = match std::env::var("GITEA_WEBHOOK_SECRET") {
+        Ok(secret) => secret,
+        Err(_) => return false,
+    };
+    let signature = match signature.and_then(|s| hex::decode(s).ok()) {
+        Some(signature) => signature,
+        None => return false,
+    };
+
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+    mac.update(payload);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Splits a multi-file unified diff, as served by `/pulls/{index}.diff`,
+/// into one entry per file with the hunks as its patch.
+fn split_diff(diff: &str) -> Vec<ChangedFile> {
+    let mut files = Vec::new();
+
+    for section in diff.split("\ndiff --git ") {
+        let section = section.strip_prefix("diff --git ").unwrap_or(section);
+        let header = match section.lines().next() {
+            Some(header) => header,
+            None => continue,
+        };
+        // `a/<old> b/<new>`; paths with spaces are not quoted, so split on
+        // the last ` b/`.
+        let filename = match header.rfind(" b/") {
+            Some(idx) => header[idx + 3..].to_string(),
+            None => continue,
+        };
+
+        let mut status = FileStatus::Modified;
+        let mut previous_filename = None;
+        let mut patch: Option<String> = None;
+        for line in section.lines().skip(1) {
+            if let Some(p) = patch.as_mut() {
+                p.push_str(line);
+                p.push('\n');
+            } else if line.starts_with("@@") {
+                patch = Some(format!("{}\n", line));
+            } else if line.starts_with("new file mode") {
+                status = FileStatus::Added;
+            } else if line.starts_with("deleted file mode") {
+                status = FileStatus::Removed;
+            } else if let Some(from) = line.strip_prefix("rename from ") {
+                status = FileStatus::Renamed;
+                previous_filename = Some(from.to_string());
+            }
+        }
+
+        files.push(ChangedFile {
+            filename,
+            previous_filename,
+            status,
+            patch,
+        });
+    }
+
+    files
+}
+
+#[async_trait::async_trait]
+impl Forge for GiteaClient {
+    fn kind(&self) -> ForgeKind {
+        ForgeKind::Gitea
+    }
+
+    fn clone_url(&self, repo_name: &str) -> String {
+        format!("{}/{}.git", self.git_url, repo_name)
+    }
+
+    /// The API token, which Gitea takes in place of a username.
+    fn git_credentials(&self) -> anyhow::Result<GitCredential> {
+        Ok(GitCredential::new(self.token.clone(), "x-oauth-basic".to_string()))
+    }
+
+    fn parse_merge_event(&self, event: &str, payload: &str) -> anyhow::Result<Option<ChangeRequest>> {
+        if event != "pull_request" {
+            return Ok(None);
+        }
+
+        let event = deserialize_payload::<PullRequestEvent>(payload)?;
+        if !event.is_closed_and_merged() {
+            return Ok(None);
+        }
+
+        Ok(Some(event.to_change_request()))
+    }
+
+    async fn changed_files(&self, repo_name: &str, number: u64) -> anyhow::Result<Vec<ChangedFile>> {
+        // The files endpoint does not include patches, the raw diff does.
+        let url = format!("{}/repos/{}/pulls/{}.diff", self.api_url, repo_name, number);
+        let diff = self.send(self.client.get(&url)).await?.text().await?;
+
+        Ok(split_diff(&diff))
+    }
+
+    async fn change_request_commits(&self, repo_name: &str, number: u64) -> anyhow::Result<Vec<ChangeCommit>> {
+        #[derive(serde::Deserialize)]
+        struct Author {
+            name: String,
+            email: String,
+            date: chrono::DateTime<chrono::FixedOffset>,
+        }
+        #[derive(serde::Deserialize)]
+        struct GitCommit {
+            message: String,
+            author: Author,
+        }
+        #[derive(serde::Deserialize)]
+        struct Commit {
+            sha: String,
+            commit: GitCommit,
+        }
+
+        const LIMIT: usize = 50;
+        let mut commits = Vec::new();
+        for page in 1.. {
+            let url = format!(
+                "{}/repos/{}/pulls/{}/commits?limit={}&page={}",
+                self.api_url, repo_name, number, LIMIT, page
+            );
+            let batch: Vec<Commit> = self.send(self.client.get(&url)).await?.json().await?;
+            let done = batch.len() < LIMIT;
+            commits.extend(batch.into_iter().map(|c| ChangeCommit {
+                sha: c.sha,
+                message: c.commit.message,
+                author_name: c.commit.author.name,
+                author_email: c.commit.author.email,
+                author_date: c.commit.author.date,
+            }));
+            if done {
+                break;
+            }
+        }
+
+        Ok(commits)
+    }
+
+    fn change_request_ref(&self, number: u64) -> String {
+        format!("refs/pull/{}/head", number)
+    }
+
+    async fn branch_exists(&self, repo_name: &str, branch: &str) -> anyhow::Result<bool> {
+        let url = format!("{}/repos/{}/branches/{}", self.api_url, repo_name, branch);
+        match self.send(self.client.get(&url)).await {
+            Ok(_) => Ok(true),
+            Err(err) if is_not_found(&err) => Ok(false),
+            Err(err) => Err(err.context("failed to look up branch")),
+        }
+    }
+
+    async fn list_branches(&self, repo_name: &str, prefix: &str) -> anyhow::Result<Vec<String>> {
+        #[derive(serde::Deserialize)]
+        struct Branch {
+            name: String,
+        }
+
+        // The branches endpoint cannot filter by name.
+        const LIMIT: usize = 50;
+        let mut branches = Vec::new();
+        for page in 1.. {
+            let url = format!("{}/repos/{}/branches?limit={}&page={}", self.api_url, repo_name, LIMIT, page);
+            let batch: Vec<Branch> = self.send(self.client.get(&url)).await?.json().await?;
+            let done = batch.len() < LIMIT;
+            branches.extend(batch.into_iter().map(|b| b.name).filter(|name| name.starts_with(prefix)));
+            if done {
+                break;
+            }
+        }
+
+        Ok(branches)
+    }
+
+    async fn delete_branch(&self, repo_name: &str, branch: &str) -> anyhow::Result<()> {
+        let url = format!("{}/repos/{}/branches/{}", self.api_url, repo_name, branch);
+        self.send(self.client.delete(&url))
+            .await
+            .context("failed to delete branch")?;
+
+        Ok(())
+    }
+
+    async fn find_open_change_request(
+        &self,
+        repo_name: &str,
+        head: &str,
+    ) -> anyhow::Result<Option<OpenChangeRequest>> {
+        #[derive(serde::Deserialize)]
+        struct Head {
+            #[serde(rename = "ref")]
+            name: String,
+        }
+        #[derive(serde::Deserialize)]
+        struct Pull {
+            head: Head,
+            #[serde(flatten)]
+            pull: CreatedPullRequest,
+        }
+
+        const LIMIT: usize = 50;
+        for page in 1.. {
+            let url = format!("{}/repos/{}/pulls?state=open&limit={}&page={}", self.api_url, repo_name, LIMIT, page);
+            let batch: Vec<Pull> = self.send(self.client.get(&url)).await?.json().await?;
+            let last_page = batch.len() < LIMIT;
+            if let Some(found) = batch.into_iter().find(|pull| pull.head.name == head) {
+                return Ok(Some(OpenChangeRequest {
+                    number: found.pull.number,
+                    html_url: found.pull.html_url,
+                    body: found.pull.body,
+                }));
+            }
+            if last_page {
+                break;
+            }
+        }
+
+        Ok(None)
+    }
+
+    async fn open_change_request(
+        &self,
+        repo_name: &str,
+        request: &NewChangeRequest<'_>,
+    ) -> anyhow::Result<OpenChangeRequest> {
+        let url = format!("{}/repos/{}/pulls", self.api_url, repo_name);
+        let body = serde_json::json!({
+            "title": request.title,
+            "body": request.body,
+            "head": request.head,
+            "base": request.base,
+        });
+
+        let created: CreatedPullRequest = self
+            .send(self.client.post(&url).json(&body))
+            .await
+            .context("failed to create pull request")?
+            .json()
+            .await?;
+
+        Ok(OpenChangeRequest {
+            number: created.number,
+            html_url: created.html_url,
+            body: created.body,
+        })
+    }
+
+    async fn update_change_request(
+        &self,
+        repo_name: &str,
+        number: u64,
+        title: &str,
+        body: &str,
+    ) -> anyhow::Result<()> {
+        let url = format!("{}/repos/{}/pulls/{}", self.api_url, repo_name, number);
+        self.send(
+            self.client
+                .patch(&url)
+                .json(&serde_json::json!({ "title": title, "body": body })),
+        )
+        .await
+        .context("failed to update pull request")?;
+
+        Ok(())
+    }
+
+    async fn enable_auto_merge(&self, repo_name: &str, number: u64, method: MergeMethod) -> anyhow::Result<()> {
+        let url = format!("{}/repos/{}/pulls/{}/merge", self.api_url, repo_name, number);
+        let body = serde_json::json!({
+            "Do": method.to_string(),
+            "merge_when_checks_succeed": true,
+        });
+        self.send(self.client.post(&url).json(&body))
+            .await
+            .context("failed to schedule the pull request to merge")?;
+
+        Ok(())
+    }
+
+    async fn post_comment(&self, repo_name: &str, number: u64, body: &str) -> anyhow::Result<()> {
+        let url = format!("{}/repos/{}/issues/{}/comments", self.api_url, repo_name, number);
+        self.send(self.client.post(&url).json(&serde_json::json!({ "body": body })))
+            .await
+            .context("failed to post comment")?;
+
+        Ok(())
+    }
+
+    async fn add_labels(&self, repo_name: &str, number: u64, labels: &[String]) -> anyhow::Result<()> {
+        // Label names are accepted in place of IDs since Gitea 1.19.
+        let url = format!("{}/repos/{}/issues/{}/labels", self.api_url, repo_name, number);
+        self.send(self.client.post(&url).json(&serde_json::json!({ "labels": labels })))
+            .await
+            .context("failed to add labels")?;
+
+        Ok(())
+    }
+}
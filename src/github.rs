@@ -11,6 +11,12 @@ use std::{
     time::{Duration, SystemTime},
 };
 use dotenv::Error;
+use crate::forge::{
+    ChangeCommit, ChangeRequest, ChangedFile, FileStatus, Forge, ForgeKind, MergeMethod, NewChangeRequest,
+    OpenChangeRequest,
+};
+use crate::git::GitCredential;
+use crate::metrics;
 
 
 #[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize)]
@@ -29,21 +35,37 @@ impl GithubClient {
             .with_context(|| format!("building reqwest {}", req_dbg))?;
 
         let mut resp = self.client.execute(req.try_clone().unwrap()).await?;
+        Self::record_call(&resp);
         if let Some(sleep) = Self::needs_retry(&resp).await {
             resp = self.retry(req, sleep, MAX_ATTEMPTS).await?;
         }
 
         log::debug!("resp {:?}", resp);
 
-        resp.error_for_status_ref()?;
+        let resp = crate::forge::error_for_status(resp)?;
 
         Ok((resp, req_dbg))
     }
 
+    fn record_call(resp: &Response) {
+        metrics::GITHUB_API_CALLS
+            .with_label_values(&[resp.status().as_str()])
+            .inc();
+    }
+
     async fn needs_retry(resp: &Response) -> Option<Duration> {
         const REMAINING: &str = "X-RateLimit-Remaining";
         const RESET: &str = "X-RateLimit-Reset";
 
+        if let Some(remaining) = resp
+            .headers()
+            .get(REMAINING)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<i64>().ok())
+        {
+            metrics::GITHUB_RATE_LIMIT_REMAINING.set(remaining);
+        }
+
         if resp.status().is_success() {
             return None;
         }
@@ -73,7 +95,7 @@ impl GithubClient {
         req: Request,
         sleep: Duration,
         remaining_attempts: usize,
-    ) -> BoxFuture<Result<Response, reqwest::Error>> {
+    ) -> BoxFuture<'_, Result<Response, reqwest::Error>> {
         #[derive(Debug, serde::Deserialize)]
         struct RateLimit {
             pub limit: u64,
@@ -108,12 +130,13 @@ impl GithubClient {
                 .client
                 .execute(
                     self.client
-                        .get("https://api.github.com/rate_limit")
+                        .get(format!("{}/rate_limit", self.api_url))
                         .configure(self)
                         .build()
                         .unwrap(),
                 )
                 .await?;
+            Self::record_call(&rate_resp);
             let rate_limit_response = rate_resp.json::<RateLimitResponse>().await?;
 
             // Check url for search path because github has different rate limits for the search api
@@ -138,6 +161,7 @@ impl GithubClient {
             }
 
             let resp = self.client.execute(req.try_clone().unwrap()).await?;
+            Self::record_call(&resp);
             if let Some(sleep) = Self::needs_retry(&resp).await {
                 if remaining_attempts > 0 {
                     return self.retry(req, sleep, remaining_attempts - 1).await;
@@ -156,7 +180,6 @@ impl GithubClient {
         while let Some(chunk) = resp.chunk().await.transpose() {
             let chunk = chunk
                 .context("reading stream failed")
-                .map_err(anyhow::Error::from)
                 .context(req_dbg.clone())?;
             body.extend_from_slice(&chunk);
         }
@@ -169,13 +192,13 @@ impl GithubClient {
             T: serde::de::DeserializeOwned,
     {
         let (resp, req_dbg) = self._send_req(req).await?;
-        Ok(resp.json().await.context(req_dbg)?)
+        resp.json().await.context(req_dbg)
     }
 }
 
 impl User {
     pub async fn current(client: &GithubClient) -> anyhow::Result<Self> {
-        client.json(client.get("https://api.github.com/user")).await
+        client.json(client.get(&format!("{}/user", client.api_url))).await
     }
 }
 
@@ -186,13 +209,9 @@ pub struct Label {
 
 impl Label {
     async fn exists<'a>(&'a self, repo_api_prefix: &'a str, client: &'a GithubClient) -> bool {
-        #[allow(clippy::redundant_pattern_matching)]
-            let url = format!("{}/labels/{}", repo_api_prefix, self.name);
-        match client.send_req(client.get(&url)).await {
-            Ok(_) => true,
-            // XXX: Error handling if the request failed for reasons beyond 'label didn't exist'
-            Err(_) => false,
-        }
+        let url = format!("{}/labels/{}", repo_api_prefix, self.name);
+        // XXX: Error handling if the request failed for reasons beyond 'label didn't exist'
+        client.send_req(client.get(&url)).await.is_ok()
     }
 }
 
@@ -212,6 +231,12 @@ pub struct PullRequest {
     pub assignees: Vec<User>,
     #[serde(default)]
     pub merged: bool,
+    /// open or closed
+    #[serde(default)]
+    pub state: Option<String>,
+    /// Where the base branch was when the pull request was last updated
+    #[serde(default)]
+    pub base: Option<PullRequestBranch>,
     // API URL
     comments_url: String,
     #[serde(skip)]
@@ -220,6 +245,8 @@ pub struct PullRequest {
 
 #[derive(Debug, serde::Deserialize)]
 pub struct Comment {
+    #[serde(default)]
+    pub id: u64,
     #[serde(deserialize_with = "opt_string")]
     pub body: String,
     pub html_url: String,
@@ -300,6 +327,66 @@ impl PullRequest {
     pub fn labels(&self) -> &[Label] {
         &self.labels
     }
+
+    pub fn to_change_request(&self, repo_name: &str) -> ChangeRequest {
+        ChangeRequest {
+            forge: ForgeKind::Github,
+            repo_name: repo_name.to_string(),
+            number: self.number,
+            title: self.title.clone(),
+            body: self.body.clone(),
+            html_url: self.html_url.clone(),
+            author: self.user.login.clone(),
+            labels: self.labels.iter().map(|l| l.name.clone()).collect(),
+            merged: self.merged,
+            merge_commit_sha: self.merge_commit_sha.clone(),
+        }
+    }
+}
+
+/// A pull request as returned when asked for on its own, with what decides
+/// whether it can be merged.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MergeablePullRequest {
+    pub number: u64,
+    /// GraphQL ID
+    pub node_id: String,
+    /// open or closed
+    pub state: String,
+    /// None while GitHub is still working it out
+    #[serde(default)]
+    pub mergeable: Option<bool>,
+    /// clean, unstable, has_hooks, blocked, behind, dirty, draft or unknown
+    #[serde(default)]
+    pub mergeable_state: String,
+    pub head: PullRequestBranch,
+}
+
+impl MergeablePullRequest {
+    /// Whether the pull request is free of conflicts and its required checks
+    /// passed. Failing checks that are not required do not stand in the way.
+    pub fn is_ready_to_merge(&self) -> bool {
+        self.mergeable == Some(true)
+            && matches!(self.mergeable_state.as_str(), "clean" | "unstable" | "has_hooks")
+    }
+}
+
+/// The head or base of a pull request.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PullRequestBranch {
+    pub sha: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PullRequestFile {
+    pub filename: String,
+    /// added, removed, modified, renamed, copied, changed or unchanged
+    pub status: String,
+    /// Absent for binary files and very large diffs
+    #[serde(default)]
+    pub patch: Option<String>,
+    #[serde(default)]
+    pub previous_filename: Option<String>,
 }
 
 #[derive(serde::Serialize)]
@@ -412,7 +499,15 @@ impl PullRequestEvent {
             return false;
         }
 
-        return true;
+        true
+    }
+
+    /// Whether the event may change what merging the pull request would
+    /// sync: a label added to it or new commits, while it is open.
+    pub fn changes_sync(&self) -> bool {
+        matches!(self.action, PullRequestAction::Labeled | PullRequestAction::Synchronize)
+            && !self.pull_request.merged
+            && self.pull_request.state.as_deref() != Some("closed")
     }
 }
 
@@ -428,10 +523,6 @@ pub struct Repository {
     pub full_name: String,
 }
 
-impl Repository {
-    // const GITHUB_API_URL: &'static str = "https://api.github.com";
-}
-
 pub struct Query<'a> {
     pub kind: QueryKind,
     // key/value filter
@@ -467,21 +558,87 @@ pub struct PushEvent {
     sender: User,
 }
 
+/// Sent when all the check runs of a commit are done.
+#[derive(Debug, serde::Deserialize)]
+pub struct CheckSuiteEvent {
+    pub action: String,
+    pub check_suite: CheckSuite,
+    repository: Repository,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct CheckSuite {
+    pub head_sha: String,
+    /// None until the suite completes
+    pub conclusion: Option<String>,
+}
+
+impl CheckSuiteEvent {
+    pub fn passed(&self) -> bool {
+        self.action == "completed" && self.check_suite.conclusion.as_deref() == Some("success")
+    }
+}
+
+/// Sent when a commit status changes.
+#[derive(Debug, serde::Deserialize)]
+pub struct StatusEvent {
+    pub sha: String,
+    /// pending, success, failure or error
+    pub state: String,
+    repository: Repository,
+}
+
+impl StatusEvent {
+    pub fn passed(&self) -> bool {
+        self.state == "success"
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct HookConfig {
+    pub content_type: Option<String>,
+    pub url: Option<String>,
+    pub insecure_ssl: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct Hook {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub active: bool,
+    pub events: Vec<String>,
+    pub config: HookConfig,
+}
+
+/// Sent by GitHub when a webhook is first configured.
+#[derive(Debug, serde::Deserialize)]
+pub struct PingEvent {
+    pub zen: String,
+    pub hook_id: u64,
+    pub hook: Hook,
+    /// None for organization webhooks
+    pub repository: Option<Repository>,
+}
+
 #[derive(Debug)]
 pub enum Event {
+    CheckSuite(CheckSuiteEvent),
     Create(CreateEvent),
     PullRequestComment(PullRequestCommentEvent),
     PullRequest(PullRequestEvent),
     Push(PushEvent),
+    Status(StatusEvent),
 }
 
 impl Event {
     pub fn repo_name(&self) -> &str {
         match self {
+            Event::CheckSuite(event) => &event.repository.full_name,
             Event::Create(event) => &event.repository.full_name,
             Event::PullRequestComment(event) => &event.repository.full_name,
             Event::PullRequest(event) => &event.repository.full_name,
             Event::Push(event) => &event.repository.full_name,
+            Event::Status(event) => &event.repository.full_name,
         }
     }
 }
@@ -499,20 +656,18 @@ impl RequestSend for RequestBuilder {
     }
 }
 
-/// Finds the token in the user's environment, panicking if no suitable token
+/// Finds the token in the user's environment, failing if no suitable token
 /// can be found.
-pub fn default_token_from_env() -> String {
-    match std::env::var("GITHUB_API_TOKEN") {
-        Ok(v) => return v,
-        Err(_) => (),
+pub fn default_token_from_env() -> anyhow::Result<String> {
+    if let Ok(v) = std::env::var("GITHUB_API_TOKEN") {
+        return Ok(v);
     }
 
-    match get_token_from_git_config() {
-        Ok(v) => return v,
-        Err(_) => (),
+    if let Ok(v) = get_token_from_git_config() {
+        return Ok(v);
     }
 
-    panic!("could not find token in GITHUB_API_TOKEN or .gitconfig/github.oath-token")
+    anyhow::bail!("could not find token in GITHUB_API_TOKEN or .gitconfig/github.oauth-token")
 }
 
 fn get_token_from_git_config() -> anyhow::Result<String> {
@@ -528,19 +683,95 @@ fn get_token_from_git_config() -> anyhow::Result<String> {
     Ok(git_token)
 }
 
+const GITHUB_API_URL: &str = "https://api.github.com";
+const GITHUB_URL: &str = "https://github.com";
+
+/// Returned by the API when a pull request is created.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct CreatedPullRequest {
+    pub number: u64,
+    pub html_url: String,
+    #[serde(default)]
+    pub body: Option<String>,
+}
+
+/// Base URL of the web and git host, `https://github.com` unless `GITHUB_URL`
+/// points at a GitHub Enterprise Server instance.
+pub fn git_url_from_env() -> String {
+    std::env::var("GITHUB_URL")
+        .map(|url| url.trim_end_matches('/').to_string())
+        .unwrap_or_else(|_| GITHUB_URL.to_string())
+}
+
+/// Base URL of the REST API. Taken from `GITHUB_API_URL` if set, otherwise
+/// derived from `GITHUB_URL` the way GitHub Enterprise Server lays it out
+/// (`https://ghes.example.com/api/v3`).
+pub fn api_url_from_env() -> String {
+    if let Ok(url) = std::env::var("GITHUB_API_URL") {
+        return url.trim_end_matches('/').to_string();
+    }
+
+    match std::env::var("GITHUB_URL") {
+        Ok(url) => format!("{}/api/v3", url.trim_end_matches('/')),
+        Err(_) => GITHUB_API_URL.to_string(),
+    }
+}
+
+/// Builds the HTTP client used to talk to the API, trusting the PEM
+/// certificates in `GITHUB_CA_CERT` on top of the system ones.
+pub fn http_client_from_env() -> anyhow::Result<Client> {
+    let mut builder = Client::builder();
+
+    if let Ok(path) = std::env::var("GITHUB_CA_CERT") {
+        let pem = std::fs::read(&path).with_context(|| format!("reading {}", path))?;
+        for cert in reqwest::Certificate::from_pem_bundle(&pem)
+            .with_context(|| format!("parsing certificates in {}", path))?
+        {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    Ok(builder.build()?)
+}
+
 #[derive(Clone)]
 pub struct GithubClient {
     token: String,
     client: Client,
+    api_url: String,
+    git_url: String,
 }
 
 impl GithubClient {
     pub fn new(client: Client, token: String) -> Self {
-        GithubClient { client, token }
+        GithubClient {
+            client,
+            token,
+            api_url: GITHUB_API_URL.to_string(),
+            git_url: GITHUB_URL.to_string(),
+        }
+    }
+
+    /// Clones and pushes repositories from `git_url` instead of
+    /// `https://github.com`. Any base git understands works, including a
+    /// local directory holding `<owner>/<repo>` bare repositories.
+    pub fn with_git_url(mut self, git_url: &str) -> Self {
+        self.git_url = git_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Sends API requests to `api_url` instead of `https://api.github.com`.
+    pub fn with_api_url(mut self, api_url: &str) -> Self {
+        self.api_url = api_url.trim_end_matches('/').to_string();
+        self
     }
 
-    pub fn new_with_default_token(client: Client) -> Self {
-        Self::new(client, default_token_from_env())
+    pub fn api_url(&self) -> &str {
+        &self.api_url
+    }
+
+    pub fn new_with_default_token(client: Client) -> anyhow::Result<Self> {
+        Ok(Self::new(client, default_token_from_env()?))
     }
 
     pub fn raw(&self) -> &Client {
@@ -551,23 +782,306 @@ impl GithubClient {
         &self,
         repo_name: &str,
         body: String,
-    ) ->anyhow::Result<()> {
+    ) -> anyhow::Result<CreatedPullRequest> {
         log::trace!("body {:?}", body);
 
-        let res = self._send_req(self.post(&format!(
-            "https://api.github.com/repos/{}/pulls",
-            repo_name))
+        let res = self.json(self.post(&format!(
+            "{}/repos/{}/pulls",
+            self.api_url, repo_name))
             .body(body)
             .timeout(Duration::from_secs(2)))
             .await
-            .context("failed to post comment")
-            .unwrap();
+            .context("failed to create pull request")?;
 
         log::trace!("resp {:?}", res);
 
+        Ok(res)
+    }
+
+    pub async fn update_pull_request(
+        &self,
+        repo_name: &str,
+        number: u64,
+        title: &str,
+        body: &str,
+    ) -> anyhow::Result<()> {
+        self._send_req(
+            self.patch(&format!("{}/repos/{}/pulls/{}", self.api_url, repo_name, number))
+                .json(&serde_json::json!({ "title": title, "body": body })),
+        )
+        .await
+        .context("failed to update pull request")?;
+
         Ok(())
     }
 
+    pub async fn pull_request(&self, repo_name: &str, number: u64) -> anyhow::Result<MergeablePullRequest> {
+        let url = format!("{}/repos/{}/pulls/{}", self.api_url, repo_name, number);
+        self.json(self.get(&url))
+            .await
+            .context("failed to look up pull request")
+    }
+
+    /// Merges a pull request, as long as its head is still `sha`.
+    pub async fn merge_pull_request(
+        &self,
+        repo_name: &str,
+        number: u64,
+        method: MergeMethod,
+        sha: &str,
+    ) -> anyhow::Result<()> {
+        self._send_req(
+            self.put(&format!("{}/repos/{}/pulls/{}/merge", self.api_url, repo_name, number))
+                .json(&serde_json::json!({ "merge_method": method.to_string(), "sha": sha })),
+        )
+        .await
+        .context("failed to merge pull request")?;
+
+        Ok(())
+    }
+
+    /// Sets a status on the commit `sha`, replacing the one with the same
+    /// context.
+    pub async fn create_commit_status(&self, repo_name: &str, sha: &str, status: &serde_json::Value) -> anyhow::Result<()> {
+        let url = format!("{}/repos/{}/statuses/{}", self.api_url, repo_name, sha);
+        self._send_req(self.post(&url).json(status))
+            .await
+            .context("failed to set commit status")?;
+
+        Ok(())
+    }
+
+    /// Opens an issue, returning its number.
+    pub async fn create_issue(&self, repo_name: &str, title: &str, body: &str) -> anyhow::Result<u64> {
+        #[derive(serde::Deserialize)]
+        struct Issue {
+            number: u64,
+        }
+
+        let url = format!("{}/repos/{}/issues", self.api_url, repo_name);
+        let created: Issue = self
+            .json(self.post(&url).json(&serde_json::json!({ "title": title, "body": body })))
+            .await
+            .context("failed to open issue")?;
+
+        Ok(created.number)
+    }
+
+    /// Replaces the description of an issue, reopening or closing it.
+    pub async fn update_issue(&self, repo_name: &str, number: u64, body: &str, open: bool) -> anyhow::Result<()> {
+        let url = format!("{}/repos/{}/issues/{}", self.api_url, repo_name, number);
+        let state = if open { "open" } else { "closed" };
+        self._send_req(self.patch(&url).json(&serde_json::json!({ "body": body, "state": state })))
+            .await
+            .context("failed to update issue")?;
+
+        Ok(())
+    }
+
+    /// Runs a GraphQL query, failing with the errors it reports.
+    pub async fn graphql<T>(&self, query: &str, variables: serde_json::Value) -> anyhow::Result<T>
+        where
+            T: serde::de::DeserializeOwned,
+    {
+        #[derive(serde::Deserialize)]
+        struct GraphqlResponse<T> {
+            data: Option<T>,
+            #[serde(default)]
+            errors: Vec<GraphqlError>,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct GraphqlError {
+            message: String,
+        }
+
+        // GitHub Enterprise Server serves it next to the REST API, at
+        // `/api/graphql` rather than `/api/v3/graphql`.
+        let url = format!("{}/graphql", self.api_url.strip_suffix("/v3").unwrap_or(&self.api_url));
+        let response: GraphqlResponse<T> = self
+            .json(self.post(&url).json(&serde_json::json!({ "query": query, "variables": variables })))
+            .await?;
+
+        if !response.errors.is_empty() {
+            let messages: Vec<_> = response.errors.into_iter().map(|e| e.message).collect();
+            anyhow::bail!("{}", messages.join("; "));
+        }
+        response
+            .data
+            .ok_or_else(|| anyhow::anyhow!("GraphQL response without data"))
+    }
+
+    /// Lists the comments on an issue or pull request, oldest first.
+    pub async fn issue_comments(&self, repo_name: &str, number: u64) -> anyhow::Result<Vec<Comment>> {
+        const PER_PAGE: usize = 100;
+        let mut comments = Vec::new();
+
+        for page in 1.. {
+            let url = format!(
+                "{}/repos/{}/issues/{}/comments?per_page={}&page={}",
+                self.api_url, repo_name, number, PER_PAGE, page
+            );
+            let batch: Vec<Comment> = self.json(self.get(&url)).await?;
+            let done = batch.len() < PER_PAGE;
+            comments.extend(batch);
+            if done {
+                break;
+            }
+        }
+
+        Ok(comments)
+    }
+
+    pub async fn update_comment(&self, repo_name: &str, id: u64, body: &str) -> anyhow::Result<()> {
+        self._send_req(
+            self.patch(&format!("{}/repos/{}/issues/comments/{}", self.api_url, repo_name, id))
+                .json(&serde_json::json!({ "body": body })),
+        )
+        .await
+        .context("failed to update comment")?;
+
+        Ok(())
+    }
+
+    /// Reads `path` at `reference`, None if there is no such file.
+    pub async fn file_contents(&self, repo_name: &str, path: &str, reference: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let url = format!("{}/repos/{}/contents/{}?ref={}", self.api_url, repo_name, path, reference);
+        match self
+            .send_req(self.get(&url).header(reqwest::header::ACCEPT, "application/vnd.github.raw"))
+            .await
+        {
+            Ok(contents) => Ok(Some(contents)),
+            Err(err) if crate::forge::is_not_found(&err) => Ok(None),
+            Err(err) => Err(err.context(format!("failed to read {}", path))),
+        }
+    }
+
+    pub async fn post_comment(&self, repo_name: &str, number: u64, body: &str) -> anyhow::Result<()> {
+        self._send_req(
+            self.post(&format!(
+                "{}/repos/{}/issues/{}/comments",
+                self.api_url, repo_name, number
+            ))
+            .json(&serde_json::json!({ "body": body })),
+        )
+        .await
+        .context("failed to post comment")?;
+
+        Ok(())
+    }
+
+    pub async fn add_labels(&self, repo_name: &str, number: u64, labels: &[String]) -> anyhow::Result<()> {
+        self._send_req(
+            self.post(&format!(
+                "{}/repos/{}/issues/{}/labels",
+                self.api_url, repo_name, number
+            ))
+            .json(&serde_json::json!({ "labels": labels })),
+        )
+        .await
+        .context("failed to add labels")?;
+
+        Ok(())
+    }
+
+    pub async fn branch_exists(&self, repo_name: &str, branch: &str) -> anyhow::Result<bool> {
+        let url = format!("{}/repos/{}/branches/{}", self.api_url, repo_name, branch);
+        match self._send_req(self.get(&url)).await {
+            Ok(_) => Ok(true),
+            Err(err) if crate::forge::is_not_found(&err) => Ok(false),
+            Err(err) => Err(err.context("failed to look up branch")),
+        }
+    }
+
+    /// Lists the branches whose name starts with `prefix`.
+    pub async fn branches_with_prefix(&self, repo_name: &str, prefix: &str) -> anyhow::Result<Vec<String>> {
+        #[derive(serde::Deserialize)]
+        struct Ref {
+            #[serde(rename = "ref")]
+            name: String,
+        }
+
+        let url = format!("{}/repos/{}/git/matching-refs/heads/{}", self.api_url, repo_name, prefix);
+        let refs: Vec<Ref> = self.json(self.get(&url)).await?;
+
+        Ok(refs
+            .into_iter()
+            .filter_map(|r| r.name.strip_prefix("refs/heads/").map(str::to_string))
+            .collect())
+    }
+
+    pub async fn delete_branch(&self, repo_name: &str, branch: &str) -> anyhow::Result<()> {
+        let url = format!("{}/repos/{}/git/refs/heads/{}", self.api_url, repo_name, branch);
+        self._send_req(self.delete(&url))
+            .await
+            .context("failed to delete branch")?;
+
+        Ok(())
+    }
+
+    /// Lists the open pull requests from `head`, a branch of the repository
+    /// itself.
+    pub async fn open_pull_requests_from(
+        &self,
+        repo_name: &str,
+        head: &str,
+    ) -> anyhow::Result<Vec<CreatedPullRequest>> {
+        let owner = repo_name.split('/').next().unwrap_or_default();
+        let url = format!(
+            "{}/repos/{}/pulls?state=open&head={}:{}",
+            self.api_url, repo_name, owner, head
+        );
+
+        self.json(self.get(&url)).await
+    }
+
+    /// Lists the files changed by a pull request, with their patches.
+    pub async fn pull_request_files(
+        &self,
+        repo_name: &str,
+        number: u64,
+    ) -> anyhow::Result<Vec<PullRequestFile>> {
+        const PER_PAGE: usize = 100;
+        let mut files = Vec::new();
+
+        for page in 1.. {
+            let url = format!(
+                "{}/repos/{}/pulls/{}/files?per_page={}&page={}",
+                self.api_url, repo_name, number, PER_PAGE, page
+            );
+            let batch: Vec<PullRequestFile> = self.json(self.get(&url)).await?;
+            let done = batch.len() < PER_PAGE;
+            files.extend(batch);
+            if done {
+                break;
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Lists the commits of a pull request, oldest first. GitHub stops at
+    /// 250 of them.
+    pub async fn pull_request_commits(&self, repo_name: &str, number: u64) -> anyhow::Result<Vec<GithubCommit>> {
+        const PER_PAGE: usize = 100;
+        let mut commits = Vec::new();
+
+        for page in 1.. {
+            let url = format!(
+                "{}/repos/{}/pulls/{}/commits?per_page={}&page={}",
+                self.api_url, repo_name, number, PER_PAGE, page
+            );
+            let batch: Vec<GithubCommit> = self.json(self.get(&url)).await?;
+            let done = batch.len() < PER_PAGE;
+            commits.extend(batch);
+            if done {
+                break;
+            }
+        }
+
+        Ok(commits)
+    }
+
     fn get(&self, url: &str) -> RequestBuilder {
         log::trace!("get {:?}", url);
         self.client.get(url).configure(self)
@@ -605,15 +1119,208 @@ pub struct GithubCommit {
 
 #[derive(Debug, serde::Deserialize)]
 pub struct GitCommit {
+    #[serde(default)]
+    pub message: String,
     pub author: GitUser,
 }
 
 #[derive(Debug, serde::Deserialize)]
 pub struct GitUser {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub email: String,
     pub date: DateTime<FixedOffset>,
 }
 
+impl From<GithubCommit> for ChangeCommit {
+    fn from(commit: GithubCommit) -> ChangeCommit {
+        let GitCommit { message, author } = commit.commit;
+        ChangeCommit {
+            sha: commit.sha,
+            message,
+            author_name: author.name,
+            author_email: author.email,
+            author_date: author.date,
+        }
+    }
+}
+
+/// `GITHUB_USERNAME` and `GITHUB_PASSWORD`, which only local remotes, like
+/// those replayed payloads are synced to, do without.
+pub(crate) fn git_credentials_from_env(git_url: &str) -> anyhow::Result<GitCredential> {
+    let needs_auth = git_url.starts_with("https://") || git_url.starts_with("http://");
+    let var = |name: &str| match std::env::var(name) {
+        Ok(value) if !value.is_empty() => Ok(value),
+        _ if !needs_auth => Ok(String::new()),
+        _ => Err(anyhow::anyhow!("{} is not set", name)),
+    };
+
+    Ok(GitCredential::new(var("GITHUB_USERNAME")?, var("GITHUB_PASSWORD")?))
+}
+
 #[derive(Debug, serde::Deserialize)]
 pub struct Parent {
     pub sha: String,
 }
+
+#[async_trait::async_trait]
+impl Forge for GithubClient {
+    fn kind(&self) -> ForgeKind {
+        ForgeKind::Github
+    }
+
+    fn clone_url(&self, repo_name: &str) -> String {
+        format!("{}/{}", self.git_url, repo_name)
+    }
+
+    fn git_credentials(&self) -> anyhow::Result<GitCredential> {
+        git_credentials_from_env(&self.git_url)
+    }
+
+    fn parse_merge_event(&self, event: &str, payload: &str) -> anyhow::Result<Option<ChangeRequest>> {
+        if event != "pull_request" {
+            return Ok(None);
+        }
+
+        let event = crate::webhook::deserialize_payload::<PullRequestEvent>(payload)?;
+        if !event.is_closed_and_merged() {
+            return Ok(None);
+        }
+
+        Ok(Some(event.pull_request.to_change_request(&event.repository.full_name)))
+    }
+
+    async fn changed_files(&self, repo_name: &str, number: u64) -> anyhow::Result<Vec<ChangedFile>> {
+        Ok(self
+            .pull_request_files(repo_name, number)
+            .await?
+            .into_iter()
+            .map(|f| ChangedFile {
+                status: match f.status.as_str() {
+                    "added" => FileStatus::Added,
+                    "removed" => FileStatus::Removed,
+                    "renamed" => FileStatus::Renamed,
+                    _ => FileStatus::Modified,
+                },
+                filename: f.filename,
+                previous_filename: f.previous_filename,
+                patch: f.patch,
+            })
+            .collect())
+    }
+
+    async fn change_request_commits(&self, repo_name: &str, number: u64) -> anyhow::Result<Vec<ChangeCommit>> {
+        let commits = self.pull_request_commits(repo_name, number).await?;
+        Ok(commits.into_iter().map(ChangeCommit::from).collect())
+    }
+
+    fn change_request_ref(&self, number: u64) -> String {
+        format!("refs/pull/{}/head", number)
+    }
+
+    async fn branch_exists(&self, repo_name: &str, branch: &str) -> anyhow::Result<bool> {
+        GithubClient::branch_exists(self, repo_name, branch).await
+    }
+
+    async fn list_branches(&self, repo_name: &str, prefix: &str) -> anyhow::Result<Vec<String>> {
+        self.branches_with_prefix(repo_name, prefix).await
+    }
+
+    async fn delete_branch(&self, repo_name: &str, branch: &str) -> anyhow::Result<()> {
+        GithubClient::delete_branch(self, repo_name, branch).await
+    }
+
+    async fn find_open_change_request(
+        &self,
+        repo_name: &str,
+        head: &str,
+    ) -> anyhow::Result<Option<OpenChangeRequest>> {
+        Ok(self
+            .open_pull_requests_from(repo_name, head)
+            .await?
+            .into_iter()
+            .next()
+            .map(|pr| OpenChangeRequest {
+                number: pr.number,
+                html_url: pr.html_url,
+                body: pr.body,
+            }))
+    }
+
+    async fn open_change_request(
+        &self,
+        repo_name: &str,
+        request: &NewChangeRequest<'_>,
+    ) -> anyhow::Result<OpenChangeRequest> {
+        let body = serde_json::json!({
+            "title": request.title,
+            "body": request.body,
+            "head": request.head,
+            "base": request.base,
+            "maintainer_can_modify": true,
+        });
+
+        let created = self.create_pull_request(repo_name, body.to_string()).await?;
+        Ok(OpenChangeRequest {
+            number: created.number,
+            html_url: created.html_url,
+            body: created.body,
+        })
+    }
+
+    async fn update_change_request(
+        &self,
+        repo_name: &str,
+        number: u64,
+        title: &str,
+        body: &str,
+    ) -> anyhow::Result<()> {
+        GithubClient::update_pull_request(self, repo_name, number, title, body).await
+    }
+
+    /// Fails if auto-merge is not allowed in the repository, or if the pull
+    /// request can be merged right away.
+    async fn enable_auto_merge(&self, repo_name: &str, number: u64, method: MergeMethod) -> anyhow::Result<()> {
+        const ENABLE_AUTO_MERGE: &str = "
+            mutation($pullRequestId: ID!, $mergeMethod: PullRequestMergeMethod!) {
+                enablePullRequestAutoMerge(input: { pullRequestId: $pullRequestId, mergeMethod: $mergeMethod }) {
+                    clientMutationId
+                }
+            }";
+
+        let pr = self.pull_request(repo_name, number).await?;
+        let variables = serde_json::json!({
+            "pullRequestId": pr.node_id,
+            "mergeMethod": method.to_string().to_uppercase(),
+        });
+        self.graphql::<serde_json::Value>(ENABLE_AUTO_MERGE, variables)
+            .await
+            .context("failed to enable auto-merge")?;
+
+        Ok(())
+    }
+
+    async fn post_comment(&self, repo_name: &str, number: u64, body: &str) -> anyhow::Result<()> {
+        GithubClient::post_comment(self, repo_name, number, body).await
+    }
+
+    async fn add_labels(&self, repo_name: &str, number: u64, labels: &[String]) -> anyhow::Result<()> {
+        GithubClient::add_labels(self, repo_name, number, labels).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http_remotes_need_credentials() {
+        std::env::remove_var("GITHUB_USERNAME");
+        std::env::remove_var("GITHUB_PASSWORD");
+
+        let err = git_credentials_from_env("https://github.com").unwrap_err();
+        assert_eq!(err.to_string(), "GITHUB_USERNAME is not set");
+        assert!(git_credentials_from_env("/tmp/remotes").is_ok());
+    }
+}
@@ -0,0 +1,411 @@
+use anyhow::Context;
+use reqwest::{Client, RequestBuilder};
+use subtle::ConstantTimeEq;
+
+use crate::forge::{
+    error_for_status, is_not_found, ChangeCommit, ChangeRequest, ChangedFile, FileStatus, Forge, ForgeKind, MergeMethod,
+    NewChangeRequest, OpenChangeRequest,
+};
+use crate::git::GitCredential;
+use crate::metrics;
+use crate::webhook::deserialize_payload;
+
+const GITLAB_URL: &str = "https://gitlab.com";
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Project {
+    pub id: u64,
+    pub path_with_namespace: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Label {
+    pub title: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct User {
+    pub username: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MergeRequestAttributes {
+    pub iid: u64,
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub url: String,
+    pub state: String,
+    /// open, close, reopen, update, approved, unapproved, merge
+    #[serde(default)]
+    pub action: Option<String>,
+    #[serde(default)]
+    pub merge_commit_sha: Option<String>,
+    pub target_branch: String,
+}
+
+/// Payload of a `Merge Request Hook` webhook.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MergeRequestEvent {
+    pub object_kind: String,
+    pub user: User,
+    pub project: Project,
+    pub object_attributes: MergeRequestAttributes,
+    #[serde(default)]
+    pub labels: Vec<Label>,
+}
+
+impl MergeRequestEvent {
+    pub fn is_merged(&self) -> bool {
+        self.object_attributes.action.as_deref() == Some("merge")
+    }
+
+    pub fn to_change_request(&self) -> ChangeRequest {
+        let mr = &self.object_attributes;
+        ChangeRequest {
+            forge: ForgeKind::Gitlab,
+            repo_name: self.project.path_with_namespace.clone(),
+            number: mr.iid,
+            title: mr.title.clone(),
+            body: mr.description.clone(),
+            html_url: mr.url.clone(),
+            author: self.user.username.clone(),
+            labels: self.labels.iter().map(|l| l.title.clone()).collect(),
+            merged: self.is_merged(),
+            merge_commit_sha: mr.merge_commit_sha.clone(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct MergeRequestChange {
+    old_path: String,
+    new_path: String,
+    new_file: bool,
+    renamed_file: bool,
+    deleted_file: bool,
+    diff: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Commit {
+    id: String,
+    message: String,
+    author_name: String,
+    author_email: String,
+    authored_date: chrono::DateTime<chrono::FixedOffset>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct MergeRequestChanges {
+    changes: Vec<MergeRequestChange>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct CreatedMergeRequest {
+    iid: u64,
+    web_url: String,
+    description: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct GitlabClient {
+    token: String,
+    client: Client,
+    api_url: String,
+    git_url: String,
+}
+
+impl GitlabClient {
+    pub fn new(client: Client, token: String) -> Self {
+        GitlabClient {
+            client,
+            token,
+            api_url: format!("{}/api/v4", GITLAB_URL),
+            git_url: GITLAB_URL.to_string(),
+        }
+    }
+
+    /// Talks to the GitLab instance at `url` (e.g. `https://gitlab.example.com`).
+    pub fn with_url(mut self, url: &str) -> Self {
+        let url = url.trim_end_matches('/');
+        self.api_url = format!("{}/api/v4", url);
+        self.git_url = url.to_string();
+        self
+    }
+
+    /// Builds a client from `GITLAB_API_TOKEN` and, for self-managed
+    /// instances, `GITLAB_URL`. Returns None when no token is configured.
+    pub fn from_env(client: Client) -> Option<Self> {
+        let token = std::env::var("GITLAB_API_TOKEN").ok()?;
+        let gl = GitlabClient::new(client, token);
+
+        Some(match std::env::var("GITLAB_URL") {
+            Ok(url) => gl.with_url(&url),
+            Err(_) => gl,
+        })
+    }
+
+    fn project_url(&self, repo_name: &str) -> String {
+        let id: String = url::form_urlencoded::byte_serialize(repo_name.as_bytes()).collect();
+        format!("{}/projects/{}", self.api_url, id)
+    }
+
+    async fn send(&self, req: RequestBuilder) -> anyhow::Result<reqwest::Response> {
+        let req = req.header("PRIVATE-TOKEN", &self.token).build()?;
+        let req_dbg = format!("{} {}", req.method(), req.url());
+        let resp = self.client.execute(req).await.context(req_dbg.clone())?;
+        metrics::GITLAB_API_CALLS
+            .with_label_values(&[resp.status().as_str()])
+            .inc();
+
+        error_for_status(resp).context(req_dbg)
+    }
+}
+
+/// Checks the `X-Gitlab-Token` header of a webhook against
+/// `GITLAB_WEBHOOK_TOKEN`.
+pub fn verify_token(token: Option<&str>) -> bool {
+    match std::env::var("GITLAB_WEBHOOK_TOKEN") {
+        Ok(expected) => token.is_some_and(|token| bool::from(token.as_bytes().ct_eq(expected.as_bytes()))),
+        Err(_) => false,
+    }
+}
+
+#[async_trait::async_trait]
+impl Forge for GitlabClient {
+    fn kind(&self) -> ForgeKind {
+        ForgeKind::Gitlab
+    }
+
+    fn clone_url(&self, repo_name: &str) -> String {
+        format!("{}/{}.git", self.git_url, repo_name)
+    }
+
+    /// The API token, which GitLab takes as the password of `oauth2`.
+    fn git_credentials(&self) -> anyhow::Result<GitCredential> {
+        Ok(GitCredential::new("oauth2".to_string(), self.token.clone()))
+    }
+
+    fn parse_merge_event(&self, event: &str, payload: &str) -> anyhow::Result<Option<ChangeRequest>> {
+        if event != "Merge Request Hook" {
+            return Ok(None);
+        }
+
+        let event = deserialize_payload::<MergeRequestEvent>(payload)?;
+        if !event.is_merged() {
+            return Ok(None);
+        }
+
+        Ok(Some(event.to_change_request()))
+    }
+
+    async fn changed_files(&self, repo_name: &str, number: u64) -> anyhow::Result<Vec<ChangedFile>> {
+        let url = format!("{}/merge_requests/{}/changes", self.project_url(repo_name), number);
+        let changes: MergeRequestChanges = self.send(self.client.get(&url)).await?.json().await?;
+
+        Ok(changes
+            .changes
+            .into_iter()
+            .map(|c| ChangedFile {
+                status: if c.new_file {
+                    FileStatus::Added
+                } else if c.deleted_file {
+                    FileStatus::Removed
+                } else if c.renamed_file {
+                    FileStatus::Renamed
+                } else {
+                    FileStatus::Modified
+                },
+                previous_filename: if c.renamed_file { Some(c.old_path) } else { None },
+                filename: c.new_path,
+                patch: if c.diff.is_empty() { None } else { Some(c.diff) },
+            })
+            .collect())
+    }
+
+    async fn change_request_commits(&self, repo_name: &str, number: u64) -> anyhow::Result<Vec<ChangeCommit>> {
+        const PER_PAGE: usize = 100;
+        let mut commits = Vec::new();
+        for page in 1.. {
+            let url = format!(
+                "{}/merge_requests/{}/commits?per_page={}&page={}",
+                self.project_url(repo_name),
+                number,
+                PER_PAGE,
+                page
+            );
+            let batch: Vec<Commit> = self.send(self.client.get(&url)).await?.json().await?;
+            let done = batch.len() < PER_PAGE;
+            commits.extend(batch.into_iter().map(|c| ChangeCommit {
+                sha: c.id,
+                message: c.message,
+                author_name: c.author_name,
+                author_email: c.author_email,
+                author_date: c.authored_date,
+            }));
+            if done {
+                break;
+            }
+        }
+
+        Ok(commits)
+    }
+
+    fn change_request_ref(&self, number: u64) -> String {
+        format!("refs/merge-requests/{}/head", number)
+    }
+
+    async fn branch_exists(&self, repo_name: &str, branch: &str) -> anyhow::Result<bool> {
+        let branch: String = url::form_urlencoded::byte_serialize(branch.as_bytes()).collect();
+        let url = format!("{}/repository/branches/{}", self.project_url(repo_name), branch);
+        match self.send(self.client.get(&url)).await {
+            Ok(_) => Ok(true),
+            Err(err) if is_not_found(&err) => Ok(false),
+            Err(err) => Err(err.context("failed to look up branch")),
+        }
+    }
+
+    async fn list_branches(&self, repo_name: &str, prefix: &str) -> anyhow::Result<Vec<String>> {
+        #[derive(serde::Deserialize)]
+        struct Branch {
+            name: String,
+        }
+
+        const PER_PAGE: usize = 100;
+        let search: String = url::form_urlencoded::byte_serialize(format!("^{}", prefix).as_bytes()).collect();
+        let mut branches = Vec::new();
+        for page in 1.. {
+            let url = format!(
+                "{}/repository/branches?search={}&per_page={}&page={}",
+                self.project_url(repo_name),
+                search,
+                PER_PAGE,
+                page
+            );
+            let batch: Vec<Branch> = self.send(self.client.get(&url)).await?.json().await?;
+            let done = batch.len() < PER_PAGE;
+            branches.extend(batch.into_iter().map(|b| b.name));
+            if done {
+                break;
+            }
+        }
+
+        Ok(branches)
+    }
+
+    async fn delete_branch(&self, repo_name: &str, branch: &str) -> anyhow::Result<()> {
+        let branch: String = url::form_urlencoded::byte_serialize(branch.as_bytes()).collect();
+        let url = format!("{}/repository/branches/{}", self.project_url(repo_name), branch);
+        self.send(self.client.delete(&url))
+            .await
+            .context("failed to delete branch")?;
+
+        Ok(())
+    }
+
+    async fn find_open_change_request(
+        &self,
+        repo_name: &str,
+        head: &str,
+    ) -> anyhow::Result<Option<OpenChangeRequest>> {
+        let head: String = url::form_urlencoded::byte_serialize(head.as_bytes()).collect();
+        let url = format!(
+            "{}/merge_requests?state=opened&source_branch={}",
+            self.project_url(repo_name),
+            head
+        );
+        let open: Vec<CreatedMergeRequest> = self.send(self.client.get(&url)).await?.json().await?;
+
+        Ok(open.into_iter().next().map(|mr| OpenChangeRequest {
+            number: mr.iid,
+            html_url: mr.web_url,
+            body: mr.description,
+        }))
+    }
+
+    async fn open_change_request(
+        &self,
+        repo_name: &str,
+        request: &NewChangeRequest<'_>,
+    ) -> anyhow::Result<OpenChangeRequest> {
+        let url = format!("{}/merge_requests", self.project_url(repo_name));
+        let body = serde_json::json!({
+            "title": request.title,
+            "description": request.body,
+            "source_branch": request.head,
+            "target_branch": request.base,
+            "allow_collaboration": true,
+        });
+
+        let created: CreatedMergeRequest = self
+            .send(self.client.post(&url).json(&body))
+            .await
+            .context("failed to create merge request")?
+            .json()
+            .await?;
+
+        Ok(OpenChangeRequest {
+            number: created.iid,
+            html_url: created.web_url,
+            body: created.description,
+        })
+    }
+
+    async fn update_change_request(
+        &self,
+        repo_name: &str,
+        number: u64,
+        title: &str,
+        body: &str,
+    ) -> anyhow::Result<()> {
+        let url = format!("{}/merge_requests/{}", self.project_url(repo_name), number);
+        self.send(
+            self.client
+                .put(&url)
+                .json(&serde_json::json!({ "title": title, "description": body })),
+        )
+        .await
+        .context("failed to update merge request")?;
+
+        Ok(())
+    }
+
+    /// Sets the merge request to merge when its pipeline succeeds. GitLab
+    /// rebases or not as the project says, only squashing is up to the
+    /// merge request.
+    async fn enable_auto_merge(&self, repo_name: &str, number: u64, method: MergeMethod) -> anyhow::Result<()> {
+        let url = format!("{}/merge_requests/{}/merge", self.project_url(repo_name), number);
+        let body = serde_json::json!({
+            "merge_when_pipeline_succeeds": true,
+            "squash": method == MergeMethod::Squash,
+        });
+        self.send(self.client.put(&url).json(&body))
+            .await
+            .context("failed to set the merge request to merge when the pipeline succeeds")?;
+
+        Ok(())
+    }
+
+    async fn post_comment(&self, repo_name: &str, number: u64, body: &str) -> anyhow::Result<()> {
+        let url = format!("{}/merge_requests/{}/notes", self.project_url(repo_name), number);
+        self.send(self.client.post(&url).json(&serde_json::json!({ "body": body })))
+            .await
+            .context("failed to post note")?;
+
+        Ok(())
+    }
+
+    async fn add_labels(&self, repo_name: &str, number: u64, labels: &[String]) -> anyhow::Result<()> {
+        let url = format!("{}/merge_requests/{}", self.project_url(repo_name), number);
+        self.send(
+            self.client
+                .put(&url)
+                .json(&serde_json::json!({ "add_labels": labels.join(",") })),
+        )
+        .await
+        .context("failed to add labels")?;
+
+        Ok(())
+    }
+}
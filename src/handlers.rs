@@ -1,9 +1,31 @@
 use std::fmt;
-use std::sync::{mpsc, Arc};
-use crate::github::{Event, GithubClient, PullRequestEvent};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use rusqlite::Connection;
+use tokio::sync::mpsc;
+use crate::github::{Event, GithubClient};
+use crate::gitlab::GitlabClient;
+use crate::gitea::GiteaClient;
+use crate::forge::{ChangeRequest, Forge, ForgeKind};
 use crate::config;
+use background::Background;
+use retry::RetryPolicy;
 
+mod auto_merge;
+pub mod background;
 mod cherry_pick;
+mod front_matter;
+pub mod janitor;
+pub mod ping;
+mod preview;
+mod rewrite;
+mod statuses;
+mod translations;
+mod pool;
+pub mod retry;
+
+pub use cherry_pick::SyncError;
+pub use pool::{handle_pr_task, shutdown_requested, Job, WorkerOptions};
 
 #[derive(Debug)]
 pub enum HandlerError {
@@ -24,20 +46,40 @@ impl fmt::Display for HandlerError {
 
 #[warn(unused_mut)]
 pub async fn handle(
-    _ctx: &Context,
+    ctx: &Arc<Context>,
     event: &Event,
-    sender: mpsc::Sender<PullRequestEvent>,
+    sender: mpsc::UnboundedSender<ChangeRequest>,
 ) -> Vec<HandlerError> {
-    let config = config::get_repo_config(event.repo_name()).await;
+    let config = config::get_repo_config(ForgeKind::Github, event.repo_name()).await;
     let mut errors = Vec::new();
 
     match config {
-        Ok(_c)   => {
+        Ok(c)   => {
             match event {
                 Event::PullRequest( e) => {
                     log::info!("send event {:?}", e);
                     if e.is_closed_and_merged() {
-                        sender.send(e.clone()).unwrap();
+                        let request = e.pull_request.to_change_request(&e.repository.full_name);
+                        if sender.send(request).is_err() {
+                            errors.push(HandlerError::Other(anyhow::anyhow!("the sync queue is closed")));
+                        }
+                        let what = format!("to track the translations of {}#{}", e.repository.full_name, e.pull_request.number);
+                        let (task_ctx, config, event) = (ctx.clone(), c.clone(), e.clone());
+                        ctx.background.spawn(what, async move { translations::handle(&task_ctx, &config, &event).await });
+                    } else if e.changes_sync() {
+                        let what = format!("to preview the sync of {}#{}", e.repository.full_name, e.pull_request.number);
+                        let (task_ctx, config, event) = (ctx.clone(), c.clone(), e.clone());
+                        ctx.background.spawn(what, async move { preview::handle(&task_ctx, &config, &event).await });
+                    }
+                }
+                Event::CheckSuite(e) if e.passed() => {
+                    if let Err(err) = auto_merge::merge_ready(ctx, event.repo_name()).await {
+                        errors.push(HandlerError::Other(err));
+                    }
+                }
+                Event::Status(e) if e.passed() => {
+                    if let Err(err) = auto_merge::merge_ready(ctx, event.repo_name()).await {
+                        errors.push(HandlerError::Other(err));
                     }
                 }
                 _ => {
@@ -54,32 +96,57 @@ pub async fn handle(
     errors
 }
 
+/// Queues a change request another forge reported as merged.
+pub async fn handle_merged(
+    request: ChangeRequest,
+    sender: mpsc::UnboundedSender<ChangeRequest>,
+) -> Vec<HandlerError> {
+    let mut errors = Vec::new();
+
+    match config::get_repo_config(request.forge, &request.repo_name).await {
+        Ok(_c) => {
+            log::info!("send {} change request {}#{}", request.forge, request.repo_name, request.number);
+            if sender.send(request).is_err() {
+                errors.push(HandlerError::Other(anyhow::anyhow!("the sync queue is closed")));
+            }
+        }
+        Err(err) => {
+            errors.push(HandlerError::Message(err.to_string()));
+            log::error!("failed to get repo config, {}", err);
+        }
+    }
+    errors
+}
+
 pub struct Context {
     pub github: GithubClient,
-    // pub db_conn: Connection,
+    /// Set when GitLab credentials are configured.
+    pub gitlab: Option<GitlabClient>,
+    /// Set when Gitea credentials are configured.
+    pub gitea: Option<GiteaClient>,
+    pub db: Mutex<Connection>,
     pub username: String,
+    /// Directory repositories are cloned into while syncing.
+    pub workdir: PathBuf,
+    pub retry: RetryPolicy,
+    /// What webhooks left running, see [`background`].
+    pub background: Background,
 }
 
-pub async fn handle_pr_task(
-    ctx: Arc<Context>,
-    receiver: mpsc::Receiver<PullRequestEvent> ,
-) -> anyhow::Result<()> {
-    for pr in receiver {
-        log::info!("receiver event");
-        let config = config::get_repo_config(
-            pr.repository.full_name.as_str()).await;
-
-        match config {
-            Ok(c)   => {
-                if let Err(err) = cherry_pick::handle(ctx.clone(), c, &pr).await {
-                    log::error!("failed to process event {:?} with pr_merge handler: {:?}", pr, err);
-                };
-            },
-            Err(err) => {
-                log::error!("failed to get repo config, {}", err);
-            },
-        };
+impl Context {
+    pub fn forge(&self, kind: ForgeKind) -> anyhow::Result<&dyn Forge> {
+        match kind {
+            ForgeKind::Github => Ok(&self.github),
+            ForgeKind::Gitlab => self
+                .gitlab
+                .as_ref()
+                .map(|gl| gl as &dyn Forge)
+                .ok_or_else(|| anyhow::anyhow!("GitLab is not configured, set GITLAB_API_TOKEN")),
+            ForgeKind::Gitea => self
+                .gitea
+                .as_ref()
+                .map(|gt| gt as &dyn Forge)
+                .ok_or_else(|| anyhow::anyhow!("Gitea is not configured, set GITEA_API_TOKEN and GITEA_URL")),
+        }
     }
-
-    Ok(())
 }
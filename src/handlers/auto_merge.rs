@@ -0,0 +1,78 @@
+//! Merging sync pull requests once their checks pass.
+//!
+//! The forge does it if it can. When GitHub cannot, because auto-merge is
+//! not allowed in the repository, docsbot remembers the pull request and
+//! merges it itself when a `check_suite` or `status` event says checks
+//! passed.
+
+use crate::db::merges;
+use crate::forge::{Forge, ForgeKind, MergeMethod};
+use crate::handlers::Context;
+
+/// Sets a freshly opened sync change request up to be merged once its
+/// required checks pass. Failing to do so leaves it open for someone to
+/// merge by hand, which is not worth failing the sync over.
+pub(super) async fn enable(ctx: &Context, forge: &dyn Forge, repo_name: &str, number: u64, method: MergeMethod) {
+    let err = match forge.enable_auto_merge(repo_name, number, method).await {
+        Ok(()) => {
+            log::info!("enabled auto-merge on {}#{}", repo_name, number);
+            return;
+        }
+        Err(err) => err,
+    };
+    if forge.kind() != ForgeKind::Github {
+        log::warn!("failed to enable auto-merge on {}#{}: {:?}", repo_name, number, err);
+        return;
+    }
+
+    log::info!(
+        "auto-merge unavailable on {}#{}, merging it once its checks pass: {:#}",
+        repo_name,
+        number,
+        err
+    );
+    let added = merges::add(&ctx.db.lock().unwrap(), ForgeKind::Github, repo_name, number, method);
+    if let Err(err) = added {
+        log::error!("failed to remember to merge {}#{}: {:?}", repo_name, number, err);
+        return;
+    }
+
+    // The checks may be done already, or there may be none to wait for.
+    if let Err(err) = merge_ready(ctx, repo_name).await {
+        log::warn!("failed to merge the ready pull requests of {}: {:?}", repo_name, err);
+    }
+}
+
+/// Merges the pull requests of `repo_name` waiting for their checks that
+/// are ready, and forgets those closed in the meantime.
+pub async fn merge_ready(ctx: &Context, repo_name: &str) -> anyhow::Result<()> {
+    let waiting = merges::list(&ctx.db.lock().unwrap(), ForgeKind::Github, repo_name)?;
+
+    for (number, method) in waiting {
+        let pr = match ctx.github.pull_request(repo_name, number).await {
+            Ok(pr) => pr,
+            Err(err) => {
+                log::warn!("failed to look up {}#{}: {:?}", repo_name, number, err);
+                continue;
+            }
+        };
+
+        if pr.state != "open" {
+            log::info!("{}#{} was closed, not merging it", repo_name, number);
+        } else if !pr.is_ready_to_merge() {
+            log::debug!("{}#{} is not ready to merge: {}", repo_name, number, pr.mergeable_state);
+            continue;
+        } else if let Err(err) = ctx.github.merge_pull_request(repo_name, number, method, &pr.head.sha).await {
+            // A new push or another check may have come in since, the next
+            // event gets another try.
+            log::warn!("failed to merge {}#{}: {:?}", repo_name, number, err);
+            continue;
+        } else {
+            log::info!("merged {}#{}", repo_name, number);
+        }
+
+        merges::remove(&ctx.db.lock().unwrap(), ForgeKind::Github, repo_name, number)?;
+    }
+
+    Ok(())
+}
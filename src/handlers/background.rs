@@ -0,0 +1,42 @@
+//! Work a webhook starts but is answered without waiting for, like
+//! previews, which take a few API requests for every label.
+
+use std::future::Future;
+use std::sync::Mutex;
+
+use tokio::task::JoinHandle;
+
+/// The tasks started for webhooks, kept so that one-off commands and tests
+/// can wait for them.
+#[derive(Default)]
+pub struct Background {
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl Background {
+    /// Runs `task` on its own, logging what failed as `what`.
+    pub fn spawn<F>(&self, what: String, task: F)
+    where
+        F: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let handle = tokio::spawn(async move {
+            if let Err(err) = task.await {
+                log::error!("failed {}: {:?}", what, err);
+            }
+        });
+
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.retain(|task| !task.is_finished());
+        tasks.push(handle);
+    }
+
+    /// Waits for the tasks started so far to finish.
+    pub async fn wait(&self) {
+        let tasks = std::mem::take(&mut *self.tasks.lock().unwrap());
+        for task in tasks {
+            if let Err(err) = task.await {
+                log::error!("background task panicked: {}", err);
+            }
+        }
+    }
+}
@@ -1,196 +1,1013 @@
-use crate::github::{PullRequest, PullRequestEvent};
-use crate::handlers::Context;
-use crate::config::{RepoConfig, LabelConfig};
-use crate::git::{Git, GitCredential};
-use std::sync::Arc;
-use std::collections::HashMap;
-use std::convert::TryFrom;
-use std::{env, fs, writeln};
-use std::path::Path;
-use std::fmt::Write as FmtWrite;
+use crate::forge::{ChangeCommit, ChangeRequest, ChangedFile, FileStatus, Forge, NewChangeRequest};
+use crate::handlers::{Context, HandlerError, Job};
+use crate::handlers::{auto_merge, front_matter, janitor};
+use crate::handlers::rewrite::Rewriter;
+use crate::handlers::retry::{self, BranchNotVisible};
+use crate::config::{LabelConfig, SyncMode, SyncPath};
+use crate::db::branches;
+use crate::git::{Git, GitCredential, PushError};
+use crate::metrics;
+use anyhow::Context as _;
+use std::sync::{Arc, Mutex};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
 use git2::IndexAddOption;
-use serde_json::json;
 use std::time::Duration;
-use std::thread::sleep;
 
-pub async fn handle(
-    ctx: Arc<Context>,
-    config: Arc<RepoConfig>,
-    pr: &PullRequestEvent,
-) -> anyhow::Result<()> {
-    let labels = pr.pull_request.labels();
-    let repo_name = &pr.repository.full_name;
-
-    for config_label in config.labels.iter() {
-        log::info!("config label: {}", config_label.label);
-        let label = labels.iter().find(|&l| l.name == config_label.label);
-        let pull_request = &pr.pull_request;
-        match label {
-            Some(_l) => {
-                match handle_docs_label(
-                    ctx.clone(),
-                    config_label,
-                    pull_request,
-                    repo_name.to_string(),
-                ).await {
-                    Ok(()) => {
-                        log::info!("handle docs label successfully!")
-                    },
-                    Err(_e) => {},
-                }
-            },
-            _ => return Ok(()),
-        };
+/// How long to wait for a pushed branch to show up in the API before
+/// opening a pull request from it.
+const BRANCH_VISIBLE_ATTEMPTS: u32 = 20;
+const BRANCH_VISIBLE_INTERVAL: Duration = Duration::from_millis(500);
+lazy_static::lazy_static! {
+    /// Checkouts git is working in on a blocking thread. That work carries on
+    /// even after the job it is for was given up on.
+    static ref CHECKOUTS_IN_USE: Mutex<HashSet<PathBuf>> = Default::default();
+}
+
+/// Front-matter conflicts listed in a sync pull request at most, the rest
+/// are only counted.
+const MAX_LISTED_CONFLICTS: usize = 50;
+
+/// Why syncing a change request to a label's base branch failed, by the
+/// step that failed.
+#[derive(thiserror::Error, Debug)]
+pub enum SyncError {
+    #[error("{0}")]
+    Config(String),
+    #[error("failed to clone {remote_url}")]
+    Clone {
+        remote_url: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    #[error("failed to create branch {branch}")]
+    Branch {
+        branch: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    #[error("failed to apply the changes to {file}")]
+    Apply {
+        file: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    #[error("failed to rebase {branch} onto {onto}")]
+    Rebase {
+        branch: String,
+        onto: String,
+        /// The commit the branch was at
+        head: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    #[error("failed to commit the synced changes")]
+    Commit(#[source] anyhow::Error),
+    #[error("failed to push {branch}")]
+    Push {
+        branch: String,
+        #[source]
+        source: anyhow::Error,
+    },
+    #[error("forge API request failed")]
+    Api(#[source] anyhow::Error),
+}
+
+impl SyncError {
+    /// Whether trying the whole job again may succeed, see
+    /// [`retry::is_transient`].
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            SyncError::Clone { source, .. } | SyncError::Push { source, .. } | SyncError::Api(source) => {
+                retry::is_transient(source)
+            }
+            SyncError::Config(_)
+            | SyncError::Branch { .. }
+            | SyncError::Apply { .. }
+            | SyncError::Rebase { .. }
+            | SyncError::Commit(_) => false,
+        }
     }
 
-    Ok(())
+    /// How long the forge asked to wait before trying again, see
+    /// [`retry::retry_after`].
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            SyncError::Api(source) => retry::retry_after(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<SyncError> for HandlerError {
+    fn from(err: SyncError) -> HandlerError {
+        HandlerError::Message(match &err {
+            SyncError::Config(msg) => format!("docsbot is not set up correctly for this repository: {}", msg),
+            SyncError::Clone { .. } => "the repository could not be cloned".to_string(),
+            SyncError::Branch { branch, .. } => format!("the sync branch `{}` could not be created", branch),
+            SyncError::Apply { file, .. } => format!(
+                "the changes to `{}` could not be applied, the file may have diverged on the target branch",
+                file
+            ),
+            SyncError::Rebase { branch, onto, .. } => format!(
+                "the batch branch `{}` could not be rebased onto `{}`, its conflicts have to be resolved by hand",
+                branch, onto
+            ),
+            SyncError::Commit(_) => "the synced changes could not be committed".to_string(),
+            SyncError::Push { branch, source } if matches!(source.downcast_ref(), Some(PushError::StaleLease(_))) => {
+                format!(
+                    "the sync branch `{}` has commits docsbot did not push, so it was left alone",
+                    branch
+                )
+            }
+            SyncError::Push { branch, .. } => format!("the sync branch `{}` could not be pushed", branch),
+            SyncError::Api(source) => format!("a request to the forge API failed: {}", source),
+        })
+    }
+}
+
+pub async fn handle(ctx: Arc<Context>, job: Job) -> Result<SyncReport, SyncError> {
+    handle_docs_label(ctx, &job.config, &job.request).await
+}
+
+/// What a successful sync did.
+#[derive(Debug, Clone, Default)]
+pub struct SyncReport {
+    /// Target files written or removed
+    pub applied: BTreeSet<String>,
+    /// Files of the change request outside the synced directories
+    pub skipped: BTreeSet<String>,
+    /// Target files whose front-matter had changes of its own, which the
+    /// sync replaced with the source's
+    pub front_matter_conflicts: BTreeSet<String>,
+    /// The sync change request, None if the changes were pushed onto the
+    /// base branch
+    pub change_request_url: Option<String>,
+}
+
+/// Name of the branch a change request is synced to a label's base branch
+/// on, which is also the directory it is checked out in. It stays the same
+/// when the change request is synced again, so that the sync pull request
+/// opened the first time is updated instead of a second one being opened.
+/// In batch mode every change request is synced on the same branch.
+pub(crate) fn sync_branch(pr: &ChangeRequest, config: &LabelConfig) -> String {
+    let label: String = config
+        .label
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '.' || c == '_' { c } else { '-' })
+        .collect();
+    let label = label.trim_matches('.');
+
+    if config.batch {
+        format!("{}sync-{}", janitor::BRANCH_PREFIX, label)
+    } else {
+        format!("{}{}-{}", janitor::BRANCH_PREFIX, pr.number, label)
+    }
+}
+
+/// Marks the description of a sync pull request with what it syncs.
+fn sync_marker(pr: &ChangeRequest, label: &str) -> String {
+    format!("<!-- docsbot-sync: {}#{} {} -->", pr.repo_name, pr.number, label)
+}
+
+/// The description of a batch pull request: a checklist of the change
+/// requests it syncs, keeping the items of its `previous` description as
+/// they are so that ticked boxes stay ticked.
+fn batch_body(pr: &ChangeRequest, config: &LabelConfig, previous: Option<&str>) -> String {
+    let mut items: Vec<String> = previous
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| line.starts_with("- ["))
+        .map(str::to_string)
+        .collect();
+    let marker = sync_marker(pr, &config.label);
+    if !items.iter().any(|item| item.contains(&marker)) {
+        items.push(format!("- [ ] {} {} {}", pr.html_url, pr.title, marker));
+    }
+
+    format!(
+        "Syncs the docs changed in these pull requests to `{}`:\n\n{}\n",
+        config.base_branch,
+        items.join("\n")
+    )
+}
+
+/// Points reviewers at the target files whose front matter was replaced
+/// even though it had changes of its own.
+fn front_matter_note(conflicts: &BTreeSet<String>) -> String {
+    let mut note = String::from(
+        "The front matter of these files had changes of its own on the base branch, \
+         which this sync replaced. Check that none of them should be kept:\n\n",
+    );
+    for file in conflicts.iter().take(MAX_LISTED_CONFLICTS) {
+        note.push_str(&format!("- `{}`\n", file));
+    }
+    if conflicts.len() > MAX_LISTED_CONFLICTS {
+        note.push_str(&format!("- and {} more\n", conflicts.len() - MAX_LISTED_CONFLICTS));
+    }
+    note
+}
+
+/// Removes the checkout of a job that was abandoned halfway, unless git is
+/// still working in it, in which case the janitor gets to it later.
+pub fn remove_checkout(ctx: &Context, job: &Job) {
+    let dir = ctx.workdir.join(sync_branch(&job.request, &job.config));
+    // Held while removing, so that no job starts using it meanwhile.
+    let in_use = CHECKOUTS_IN_USE.lock().unwrap();
+    if in_use.contains(&dir) {
+        log::info!("{:?} is still in use, leaving it to the janitor", dir);
+        return;
+    }
+    if dir.exists() {
+        if let Err(err) = fs::remove_dir_all(&dir) {
+            log::warn!("failed to remove {:?}: {}", dir, err);
+        }
+    }
+}
+
+/// Marks a checkout as in use until dropped.
+struct InUse(PathBuf);
+
+impl InUse {
+    fn new(dir: PathBuf) -> Self {
+        CHECKOUTS_IN_USE.lock().unwrap().insert(dir.clone());
+        InUse(dir)
+    }
+}
+
+impl Drop for InUse {
+    fn drop(&mut self) {
+        CHECKOUTS_IN_USE.lock().unwrap().remove(&self.0);
+    }
 }
 
 async fn handle_docs_label(
     ctx: Arc<Context>,
     config: &LabelConfig,
-    pr_request: &PullRequest,
-    repo_name: String,
-) -> anyhow::Result<()> {
-    let file_diff = parse_files_diff(&pr_request.diff_url).await.unwrap();
-    let commit = if let Some(s) = &pr_request.merge_commit_sha {
-        s
-    } else {
+    pr_request: &ChangeRequest,
+) -> Result<SyncReport, SyncError> {
+    let forge = ctx
+        .forge(pr_request.forge)
+        .map_err(|e| SyncError::Config(e.to_string()))?;
+    let repo_name = &pr_request.repo_name;
+    if pr_request.merge_commit_sha.is_none() {
         log::error!("no merge_commit_sha in pr_request");
-        return Ok(());
+        return Ok(SyncReport::default());
+    }
+    let target = sync_branch(pr_request, config);
+    let existing = forge
+        .find_open_change_request(repo_name, &target)
+        .await
+        .map_err(SyncError::Api)?;
+
+    // Lets a batch branch tell which change requests it has.
+    let synced_from = format!("Synced-from: {}", pr_request.html_url);
+    let commits = if config.preserve_commits {
+        Commits::Replay {
+            refspec: format!("+{}:{}", forge.change_request_ref(pr_request.number), SOURCE_REF),
+            commits: forge
+                .change_request_commits(repo_name, pr_request.number)
+                .await
+                .map_err(SyncError::Api)?,
+            trailer: synced_from.clone(),
+        }
+    } else {
+        let files = forge
+            .changed_files(repo_name, pr_request.number)
+            .await
+            .map_err(SyncError::Api)?;
+        let message = if config.batch {
+            format!("sync {}#{} to {}\n\n{}", repo_name, pr_request.number, config.label, synced_from)
+        } else {
+            format!("sync to {}", config.label)
+        };
+        Commits::Squash {
+            message,
+            files,
+            merge_commit: pr_request.merge_commit_sha.clone(),
+        }
     };
 
-    let target = &(commit.clone()[0..12]);
+    let (start, mut body) = if config.batch {
+        let previous = existing.as_ref().and_then(|e| e.body.as_deref());
+        let start = if existing.is_some() { Start::Batch { synced_from } } else { Start::Base };
+        (start, batch_body(pr_request, config, previous))
+    } else {
+        let description = match pr_request.reverted_number() {
+            Some(reverted) => format!(
+                "Syncs the revert of #{} in {} to `{}`.",
+                reverted, pr_request.html_url, config.base_branch
+            ),
+            None => format!(
+                "Syncs the docs changed in {} to `{}`.",
+                pr_request.html_url, config.base_branch
+            ),
+        };
+        let body = format!("{}\n\n{}", description, sync_marker(pr_request, &config.label));
+        (Start::Base, body)
+    };
 
-    cherry_pick(repo_name.as_str(), config, file_diff, target).unwrap();
+    let remote_url = forge.clone_url(repo_name);
+    let cred = forge
+        .git_credentials()
+        .map_err(|e| SyncError::Config(e.to_string()))?;
 
-    log::info!("sleep 2 s");
-    sleep(Duration::from_secs(2));
-    log::info!("sleep end");
+    let land = match config.mode {
+        SyncMode::Push => pr_request.merge_commit_sha.clone(),
+        SyncMode::Pr => None,
+    };
 
-    let body = json!({
-        "title": format!("sync docs to {}", &config.label),
-        "head": target,
-        "base": config.base_branch,
-        "maintainer_can_modify": true,
-    });
+    let pushed = branches::head(&ctx.db.lock().unwrap(), pr_request.forge, repo_name, &target)
+        .map_err(|source| SyncError::Branch {
+            branch: target.clone(),
+            source,
+        })?
+        .and_then(|sha| git2::Oid::from_str(&sha).ok());
+
+    let workdir = ctx.workdir.clone();
+    let label_config = config.clone();
+    let branch = target.clone();
+    let in_use = InUse::new(workdir.join(&target));
+    let (outcome, mut report) = tokio::task::spawn_blocking(move || {
+        let _in_use = in_use;
+        cherry_pick(&workdir, &remote_url, cred, &label_config, &branch, pushed, start, commits, land.as_deref())
+    })
+    .await
+    .unwrap_or_else(|err| std::panic::resume_unwind(err.into_panic()))?;
+    match outcome {
+        Outcome::Landed => {
+            log::info!("pushed the changes straight onto {}", config.base_branch);
+            return Ok(report);
+        }
+        Outcome::Branch(head) => {
+            let recorded =
+                branches::record(&ctx.db.lock().unwrap(), pr_request.forge, repo_name, &target, &head.to_string());
+            if let Err(err) = recorded {
+                log::error!("failed to record the push of {} to {}: {:?}", head, target, err);
+            }
+        }
+    }
 
-    let gh = ctx.github.clone();
+    if !report.front_matter_conflicts.is_empty() {
+        body = format!("{}\n\n{}", body.trim_end(), front_matter_note(&report.front_matter_conflicts));
+    }
 
-    gh.create_pull_request(repo_name.as_str(), body.to_string()).await.unwrap();
+    wait_for_branch(forge, repo_name, &target)
+        .await
+        .map_err(SyncError::Api)?;
 
-    Ok(())
+    let title = match pr_request.reverted_number() {
+        Some(reverted) if !config.batch => format!("revert docs sync of #{} to {}", reverted, config.label),
+        _ => format!("sync docs to {}", config.label),
+    };
+    let timer = metrics::SYNC_STEP_DURATION.with_label_values(&["create_pr"]).start_timer();
+    match existing {
+        Some(existing) => {
+            forge
+                .update_change_request(repo_name, existing.number, &title, &body)
+                .await
+                .map_err(SyncError::Api)?;
+            log::info!("updated {}", existing.html_url);
+            report.change_request_url = Some(existing.html_url);
+        }
+        None => {
+            let request = NewChangeRequest {
+                title: &title,
+                body: &body,
+                head: &target,
+                base: &config.base_branch,
+            };
+            let opened = forge
+                .open_change_request(repo_name, &request)
+                .await
+                .map_err(SyncError::Api)?;
+            log::info!("opened {}", opened.html_url);
+            if let Some(method) = config.auto_merge {
+                auto_merge::enable(&ctx, forge, repo_name, opened.number, method).await;
+            }
+            report.change_request_url = Some(opened.html_url);
+        }
+    }
+    timer.observe_duration();
+
+    Ok(report)
+}
+
+/// Pushes are not always visible to the API right away, and opening a pull
+/// request from a branch the forge does not know about yet fails.
+async fn wait_for_branch(forge: &dyn Forge, repo_name: &str, branch: &str) -> anyhow::Result<()> {
+    for _ in 0..BRANCH_VISIBLE_ATTEMPTS {
+        if forge.branch_exists(repo_name, branch).await? {
+            return Ok(());
+        }
+        tokio::time::sleep(BRANCH_VISIBLE_INTERVAL).await;
+    }
+
+    Err(BranchNotVisible {
+        repo_name: repo_name.to_string(),
+        branch: branch.to_string(),
+    }
+    .into())
+}
+
+/// Where the commits of a change request are fetched to when replaying them.
+const SOURCE_REF: &str = "refs/docsbot/source";
+
+/// What the sync branch is built on.
+enum Start {
+    /// A new branch off the base branch
+    Base,
+    /// The batch branch of an open batch pull request, rebased onto the
+    /// base branch. It is left as it is if one of its commits has the
+    /// `synced_from` trailer already.
+    Batch { synced_from: String },
+}
+
+/// How the synced changes are committed.
+enum Commits {
+    /// All in one commit, copying the files the change request touched.
+    /// The merge commit tells what the files were before the change, if
+    /// the checkout has it.
+    Squash {
+        message: String,
+        files: Vec<ChangedFile>,
+        merge_commit: Option<String>,
+    },
+    /// A commit for each commit of the change request, fetched through
+    /// `refspec`, with `trailer` added to their messages
+    Replay {
+        refspec: String,
+        commits: Vec<ChangeCommit>,
+        trailer: String,
+    },
 }
 
+/// Where the synced changes went.
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    /// Onto the sync branch, which now points at the given commit, to open
+    /// a pull request from
+    Branch(git2::Oid),
+    /// Onto the base branch
+    Landed,
+}
+
+/// Syncs the changes onto `target_branch`. With `land`, the merge commit of
+/// the change request, they are pushed onto the base branch instead if the
+/// files they touch have no changes of their own there. `pushed` is what
+/// docsbot last pushed onto `target_branch`, which is only replaced while
+/// the remote still has it.
+#[allow(clippy::too_many_arguments)]
 fn cherry_pick(
-    repo_name: &str,
+    workdir: &Path,
+    remote_url: &str,
+    cred: GitCredential,
     config: &LabelConfig,
-    file_diff: HashMap<String, String>,
     target_branch: &str,
-) -> anyhow::Result<()> {
-    let current_dir = env::current_dir().unwrap();
+    pushed: Option<git2::Oid>,
+    start: Start,
+    commits: Commits,
+    land: Option<&str>,
+) -> Result<(Outcome, SyncReport), SyncError> {
+    let gt = Git::new(workdir.to_path_buf(), cred).map_err(|e| SyncError::Config(e.to_string()))?;
+
+    let repo_dir = workdir.join(target_branch);
+    let base_branch = config.base_branch.as_str();
+    let clone_error = |source: anyhow::Error| SyncError::Clone {
+        remote_url: remote_url.to_string(),
+        source,
+    };
 
-    let repo = format!("https://github.com/{}", repo_name);
+    // Left over by an earlier attempt at the same job.
+    if repo_dir.exists() {
+        fs::remove_dir_all(&repo_dir)
+            .with_context(|| format!("removing stale checkout {:?}", repo_dir))
+            .map_err(clone_error)?;
+    }
 
-    let cred = GitCredential::new(
-        env::var("GITHUB_USERNAME").unwrap(),
-        env::var("GITHUB_PASSWORD").unwrap(),
-    );
-    let gt = Git::new(current_dir, cred).unwrap();
+    let timer = metrics::SYNC_STEP_DURATION.with_label_values(&["clone"]).start_timer();
+    let repo = gt
+        .clone_repo(target_branch, base_branch, remote_url)
+        .map_err(|e| clone_error(e.into()))?;
+    if let Commits::Replay { refspec, .. } = &commits {
+        gt.fetch_ref(&repo, "origin", refspec)
+            .map_err(|e| clone_error(e.into()))?;
+    }
+    timer.observe_duration();
 
-    let repo_dir = target_branch.clone();
-    let base_branch = config.base_branch.as_str();
+    let remote_head = repo
+        .find_reference(&format!("refs/remotes/origin/{}", target_branch))
+        .ok()
+        .and_then(|reference| reference.target());
 
-    let repo = gt.clone_repo(repo_dir,  base_branch, repo.as_str()).unwrap();
+    let branch_error = |source: anyhow::Error| SyncError::Branch {
+        branch: target_branch.to_string(),
+        source,
+    };
+    let resume = match &start {
+        Start::Batch { synced_from } if remote_head.is_some() => Some(synced_from.as_str()),
+        _ => None,
+    };
+    if resume.is_some() {
+        gt.create_branch_from_remote(&repo, target_branch, "origin")
+            .map_err(|e| branch_error(e.into()))?;
+    } else {
+        gt.create_branch(&repo, target_branch, base_branch)
+            .map_err(|e| branch_error(e.into()))?;
+    }
+    gt.checkout(&repo, target_branch).map_err(branch_error)?;
+
+    let mut report = SyncReport::default();
+    let land = match land {
+        Some(merge_commit) if resume.is_none() => match diverged_files(&repo, config, merge_commit) {
+            Ok(diverged) if diverged.is_empty() => true,
+            Ok(diverged) => {
+                log::info!("{:?} diverged on {}, opening a pull request instead", diverged, base_branch);
+                report.front_matter_conflicts.extend(
+                    diverged
+                        .into_iter()
+                        .filter(|(_, divergence)| divergence.front_matter)
+                        .map(|(target, _)| target),
+                );
+                false
+            }
+            Err(err) => {
+                log::warn!("failed to compare the synced files on {}, opening a pull request instead: {:?}", base_branch, err);
+                false
+            }
+        },
+        _ => false,
+    };
 
-    gt.create_branch(&repo, target_branch, base_branch).unwrap();
+    let commit_error = |e: git2::Error| SyncError::Commit(e.into());
+    if resume.is_some() {
+        gt.rebase_by_command(&repo_dir, base_branch)
+            .map_err(|source| SyncError::Rebase {
+                branch: target_branch.to_string(),
+                onto: base_branch.to_string(),
+                head: remote_head.map(|oid| oid.to_string()).unwrap_or_default(),
+                source,
+            })?;
+    }
 
-    gt.checkout(&repo, target_branch).unwrap();
+    // A batch branch may have the changes already, pushed by an attempt
+    // that failed afterwards.
+    let synced = match resume {
+        Some(synced_from) => has_commit(&repo, base_branch, synced_from).map_err(commit_error)?,
+        None => false,
+    };
+    if synced {
+        log::info!("{} has the changes already", target_branch);
+    } else {
+        let timer = metrics::SYNC_STEP_DURATION.with_label_values(&["apply"]).start_timer();
+        match commits {
+            Commits::Squash { message, files, merge_commit } => {
+                let before = merge_commit.as_deref().and_then(|sha| tree_before(&repo, sha));
+                squash(&gt, &repo, &repo_dir, config, &files, before.as_ref(), &message, &mut report)?
+            }
+            Commits::Replay { commits, trailer, .. } => {
+                replay(&gt, &repo, &repo_dir, config, commits, &trailer, &mut report)?
+            }
+        }
+        timer.observe_duration();
+    }
 
-    for sync_path in config.sync_paths.iter() {
-        for (file, _diff) in file_diff.iter() {
-            let path = Path::new(file);
-            log::info!("file: {:?}", file);
-            if path.starts_with(&sync_path.source_directory) {
-                let source_file_path = Path::new(repo_dir).join(path);
+    let timer = metrics::SYNC_STEP_DURATION.with_label_values(&["push"]).start_timer();
+    let mut landed = false;
+    if land {
+        match gt.push_to(&repo, target_branch, "origin", base_branch) {
+            Ok(()) => landed = true,
+            // Not a fast forward, which another attempt may get past, or
+            // trouble reaching the remote.
+            Err(err) if err.class() != git2::ErrorClass::Reference || err.code() == git2::ErrorCode::NotFastForward => {
+                return Err(SyncError::Push {
+                    branch: base_branch.to_string(),
+                    source: err.into(),
+                });
+            }
+            Err(err) => {
+                log::warn!("pushing onto {} was rejected, opening a pull request instead: {}", base_branch, err);
+            }
+        }
+    }
+    let outcome = if landed {
+        Outcome::Landed
+    } else {
+        // A resumed batch branch was rebased on top of what the remote has,
+        // so that can be replaced. Anything else is only replaced if it is
+        // what docsbot pushed: a sync branch docsbot has no record of, or
+        // that someone pushed to since, is left alone.
+        let lease = if resume.is_some() { remote_head } else { remote_head.and(pushed) };
+        gt.push_branch(&repo_dir, target_branch, "origin", lease)
+            .map_err(|e| SyncError::Push {
+                branch: target_branch.to_string(),
+                source: e.into(),
+            })?;
+        let head = repo
+            .refname_to_id(&format!("refs/heads/{}", target_branch))
+            .map_err(commit_error)?;
+        Outcome::Branch(head)
+    };
+    timer.observe_duration();
 
-                let base_file = path.strip_prefix(&sync_path.source_directory).unwrap();
-                let target_file_path = Path::new(repo_dir)
-                    .join(&sync_path.target_directory)
-                    .join(base_file);
+    // The sync itself is done, a checkout left behind only costs disk space.
+    if let Err(err) = fs::remove_dir_all(&repo_dir) {
+        log::warn!("failed to remove {:?}: {}", repo_dir, err);
+    }
 
-                log::info!("copy {:?} to {:?}", source_file_path, target_file_path);
+    Ok((outcome, report))
+}
 
-                fs::copy(source_file_path, target_file_path).unwrap();
+/// Lists the target files of the files changed by `merge_commit` that
+/// differ from what their source was before the change, that is which have
+/// changes of their own the sync would overwrite, with the parts that do.
+fn diverged_files(
+    repo: &git2::Repository,
+    config: &LabelConfig,
+    merge_commit: &str,
+) -> anyhow::Result<Vec<(String, Divergence)>> {
+    let merge = repo.find_commit(git2::Oid::from_str(merge_commit)?)?;
+    let before = merge.parent(0)?.tree()?;
+    let head = repo.head()?.peel_to_tree()?;
+    let diff = repo.diff_tree_to_tree(Some(&before), Some(&merge.tree()?), None)?;
+    let rewriters = rewriters(config)?;
+
+    let mut diverged = Vec::new();
+    for delta in diff.deltas() {
+        let path = match delta.new_file().path().or_else(|| delta.old_file().path()) {
+            Some(path) => path,
+            None => continue,
+        };
+        for (sync_path, rewriter) in config.sync_paths.iter().zip(&rewriters) {
+            if let Ok(base_file) = path.strip_prefix(&sync_path.source_directory) {
+                let target = Path::new(&sync_path.target_directory).join(base_file);
+                let divergence = divergence(
+                    sync_path,
+                    rewriter,
+                    &target,
+                    blob_contents(repo, &before, path).as_deref(),
+                    blob_contents(repo, &head, &target).as_deref(),
+                );
+                if divergence.front_matter || divergence.body {
+                    diverged.push((target.to_string_lossy().into_owned(), divergence));
+                }
             }
         }
     }
 
+    Ok(diverged)
+}
 
-    let mut index = repo.index().expect("cannot get the Index file");
-    index.add_all(["."].iter(), IndexAddOption::DEFAULT, None).unwrap();
-    index.write().unwrap();
+/// Which parts of a target file have changes of their own.
+#[derive(Debug, Default)]
+struct Divergence {
+    front_matter: bool,
+    body: bool,
+}
 
-    // gt.commit_index(
-    //     &repo,
-    //     &mut index,
-    //     format!("sync to {}", config.target_directory)
-    //         .as_str())
-    //     .unwrap();
-    gt.commit_by_command(repo_dir, format!("sync to {}", config.label).as_str()).unwrap();
+/// Compares `existing`, the contents of `target` if it exists, to what
+/// `before`, its source before the change, syncs to. Pinned front-matter
+/// entries are the target's own, so they never diverge.
+fn divergence(
+    sync_path: &SyncPath,
+    rewriter: &Rewriter,
+    target: &Path,
+    before: Option<&[u8]>,
+    existing: Option<&[u8]>,
+) -> Divergence {
+    let synced = before.map(|before| synced_contents(sync_path, rewriter, target, before, existing));
+    if synced.as_deref() == existing {
+        return Divergence::default();
+    }
+    let (synced, existing) = match (synced, existing) {
+        (Some(synced), Some(existing)) if front_matter::is_markdown(target) => (synced, existing),
+        _ => {
+            return Divergence {
+                front_matter: false,
+                body: true,
+            }
+        }
+    };
 
-    gt.push_branch(&repo, target_branch, "origin").unwrap();
+    let synced = front_matter::Document::parse(&String::from_utf8_lossy(&synced));
+    let existing = front_matter::Document::parse(&String::from_utf8_lossy(existing));
+    let entries = |document: &front_matter::Document| {
+        document
+            .front_matter
+            .as_ref()
+            .map(|f| f.without(&sync_path.pinned_front_matter).entries)
+            .unwrap_or_default()
+    };
+    Divergence {
+        front_matter: entries(&synced) != entries(&existing),
+        body: synced.body != existing.body,
+    }
+}
 
-    fs::remove_dir_all(target_branch).unwrap();
+/// Contents of `path` in `tree`, if it is a file there.
+fn blob_contents(repo: &git2::Repository, tree: &git2::Tree, path: &Path) -> Option<Vec<u8>> {
+    let blob = tree.get_path(path).ok()?.to_object(repo).ok()?.peel_to_blob().ok()?;
+    Some(blob.content().to_vec())
+}
 
-    Ok(())
+/// The tree before `commit`, if the checkout has it.
+fn tree_before<'r>(repo: &'r git2::Repository, commit: &str) -> Option<git2::Tree<'r>> {
+    let commit = repo.find_commit(git2::Oid::from_str(commit).ok()?).ok()?;
+    commit.parent(0).ok()?.tree().ok()
 }
 
-async fn parse_files_diff(url: &String) -> anyhow::Result<HashMap<String, String>, reqwest::Error>{
-    let file_content = reqwest::get(url).await?
-        .text().await?;
-
-    // log::info!("{}", file_content);
-    let mut file_diff_map: HashMap<String, String> = HashMap::new();
-    let mut file_name = "".to_string();
-    let mut file_start_map: HashMap<String, u64> = HashMap::new();
-
-    for (num, line) in file_content.lines().enumerate() {
-        let num_u64 = u64::try_from(num).unwrap();
-        if line.starts_with("diff --git a") {
-            file_name = line
-                .split(' ')
-                .find(|&n| n.starts_with("a/"))
-                .unwrap()
-                .trim_start_matches("a/")
-                .to_string();
-
-            // let new_file_name = file_name.clone();
-            file_start_map.insert(file_name.clone(), num_u64+2);
-            let start_diff = "".to_string();
-            file_diff_map.insert(file_name.clone(), start_diff);
-        }
-
-        if !file_name.is_empty() {
-            let mut diffs = file_diff_map.get(file_name.as_str()).unwrap().clone();
-            let start_index = file_start_map.get(file_name.as_str()).unwrap();
-
-            if num_u64 >= *start_index {
-                // if !diffs.is_empty() {
-                //     diffs.push('\n')
-                // }
-                // diffs.push_str(line);
-
-                match writeln!(&mut diffs, "{}", line) {
-                    Ok(_) => {},
-                    Err(e) => panic!("failed to run writeln: {:?}", e),
+/// Copies the `files` changed by the change request, as they are on the
+/// base branch, to the target directories and commits them all at once.
+/// Removed files are removed from the target directories. Given the tree
+/// `before` the change, targets whose front-matter had changes of its own
+/// are reported.
+#[allow(clippy::too_many_arguments)]
+fn squash(
+    gt: &Git,
+    repo: &git2::Repository,
+    repo_dir: &Path,
+    config: &LabelConfig,
+    files: &[ChangedFile],
+    before: Option<&git2::Tree>,
+    message: &str,
+    report: &mut SyncReport,
+) -> Result<(), SyncError> {
+    for file in files.iter() {
+        if !config.sync_paths.iter().any(|p| Path::new(&file.filename).starts_with(&p.source_directory)) {
+            report.skipped.insert(file.filename.clone());
+        }
+    }
+
+    let rewriters = rewriters(config).map_err(|e| SyncError::Config(format!("{:#}", e)))?;
+    for (sync_path, rewriter) in config.sync_paths.iter().zip(&rewriters) {
+        for file in files.iter() {
+            let path = Path::new(&file.filename);
+            log::info!("file: {:?}", file.filename);
+            if let Ok(base_file) = path.strip_prefix(&sync_path.source_directory) {
+                let source_file_path = repo_dir.join(path);
+                let target_file_path = repo_dir
+                    .join(&sync_path.target_directory)
+                    .join(base_file);
+                let target = Path::new(&sync_path.target_directory).join(base_file);
+                if let Some(before) = before {
+                    let existing = fs::read(&target_file_path).ok();
+                    let before = blob_contents(repo, before, path);
+                    if divergence(sync_path, rewriter, &target, before.as_deref(), existing.as_deref()).front_matter {
+                        report.front_matter_conflicts.insert(target.to_string_lossy().into_owned());
+                    }
+                }
+
+                let result = if file.status == FileStatus::Removed {
+                    log::info!("remove {:?}", target_file_path);
+                    match fs::remove_file(&target_file_path) {
+                        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                        result => result,
+                    }
+                    .with_context(|| format!("removing {:?}", target_file_path))
+                } else {
+                    log::info!("copy {:?} to {:?}", source_file_path, target_file_path);
+                    fs::read(&source_file_path)
+                        .and_then(|contents| {
+                            let existing = fs::read(&target_file_path).ok();
+                            let synced = synced_contents(
+                                sync_path,
+                                rewriter,
+                                &target_file_path,
+                                &contents,
+                                existing.as_deref(),
+                            );
+                            if let Some(dir) = target_file_path.parent() {
+                                fs::create_dir_all(dir)?;
+                            }
+                            fs::write(&target_file_path, synced)
+                        })
+                        .with_context(|| format!("copying {:?} to {:?}", source_file_path, target_file_path))
                 };
+                result.map_err(|source| SyncError::Apply {
+                    file: file.filename.clone(),
+                    source,
+                })?;
+                report.applied.insert(target.to_string_lossy().into_owned());
+            }
+        }
+    }
+
+    let commit_error = |e: git2::Error| SyncError::Commit(e.into());
+    let mut index = repo.index().map_err(commit_error)?;
+    index
+        .add_all(["."].iter(), IndexAddOption::DEFAULT, None)
+        .map_err(commit_error)?;
+    index.update_all(["."].iter(), None).map_err(commit_error)?;
+    index.write().map_err(commit_error)?;
+
+    gt.commit_by_command(repo_dir, message)
+        .map_err(SyncError::Commit)
+}
 
-                file_diff_map.insert(file_name.clone(), diffs);
+/// Replays `commits` on the checked out branch in the order they were made,
+/// each limited to the synced paths and moved to the target directories.
+/// Merge commits and commits not touching the synced paths are left out.
+fn replay(
+    gt: &Git,
+    repo: &git2::Repository,
+    repo_dir: &Path,
+    config: &LabelConfig,
+    commits: Vec<ChangeCommit>,
+    trailer: &str,
+    report: &mut SyncReport,
+) -> Result<(), SyncError> {
+    let commit_error = |e: git2::Error| SyncError::Commit(e.into());
+    let rewriters = rewriters(config).map_err(|e| SyncError::Config(format!("{:#}", e)))?;
+    let mut committed = 0;
+
+    for change in in_commit_order(repo, commits).map_err(SyncError::Commit)? {
+        let commit = git2::Oid::from_str(&change.sha)
+            .and_then(|oid| repo.find_commit(oid))
+            .map_err(commit_error)?;
+        // Merging the base branch into the change request brings in changes
+        // that are not its own, they are synced on their own if at all.
+        if commit.parent_count() > 1 {
+            log::info!("{} is a merge commit, skipping it", change.sha);
+            continue;
+        }
+        let tree = commit.tree().map_err(commit_error)?;
+        let parent_tree = match commit.parent(0) {
+            Ok(parent) => Some(parent.tree().map_err(commit_error)?),
+            Err(_) => None,
+        };
+        let diff = repo
+            .diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)
+            .map_err(commit_error)?;
+
+        for delta in diff.deltas() {
+            let removed = delta.status() == git2::Delta::Deleted;
+            let file = if removed { delta.old_file() } else { delta.new_file() };
+            let path = match file.path() {
+                Some(path) => path,
+                None => continue,
+            };
+            let mut synced = false;
+            for (sync_path, rewriter) in config.sync_paths.iter().zip(&rewriters) {
+                if let Ok(base_file) = path.strip_prefix(&sync_path.source_directory) {
+                    let target = Path::new(&sync_path.target_directory).join(base_file);
+                    let existing = fs::read(repo_dir.join(&target)).ok();
+                    let before = parent_tree.as_ref().and_then(|tree| blob_contents(repo, tree, path));
+                    if divergence(sync_path, rewriter, &target, before.as_deref(), existing.as_deref()).front_matter {
+                        report.front_matter_conflicts.insert(target.to_string_lossy().into_owned());
+                    }
+                    copy_blob(repo, &tree, path, &repo_dir.join(&target), removed, sync_path, rewriter).map_err(|source| {
+                        SyncError::Apply {
+                            file: path.to_string_lossy().into_owned(),
+                            source,
+                        }
+                    })?;
+                    report.applied.insert(target.to_string_lossy().into_owned());
+                    synced = true;
+                }
+            }
+            if !synced {
+                report.skipped.insert(path.to_string_lossy().into_owned());
             }
         }
+
+        let mut index = repo.index().map_err(commit_error)?;
+        index
+            .add_all(["."].iter(), IndexAddOption::DEFAULT, None)
+            .map_err(commit_error)?;
+        index.update_all(["."].iter(), None).map_err(commit_error)?;
+        index.write().map_err(commit_error)?;
+        let head_tree = repo
+            .head()
+            .and_then(|head| head.peel_to_tree())
+            .map_err(commit_error)?;
+        if index.write_tree().map_err(commit_error)? == head_tree.id() {
+            log::info!("{} does not touch the synced paths", change.sha);
+            continue;
+        }
+
+        let message = format!(
+            "{}\n\n(cherry picked from commit {})\n{}",
+            change.message.trim_end(),
+            change.sha,
+            trailer
+        );
+        let author = format!("{} <{}>", change.author_name, change.author_email);
+        gt.commit_as_by_command(repo_dir, &message, &author, &change.author_date.to_rfc2822())
+            .map_err(SyncError::Commit)?;
+        committed += 1;
+    }
+
+    if committed == 0 {
+        return Err(SyncError::Commit(anyhow::anyhow!(
+            "none of the commits touch the synced paths"
+        )));
+    }
+
+    Ok(())
+}
+
+/// The rewriters of the sync paths of `config`, in the same order.
+fn rewriters(config: &LabelConfig) -> anyhow::Result<Vec<Rewriter>> {
+    config.sync_paths.iter().map(Rewriter::new).collect()
+}
+
+/// What `source` becomes as it is synced over `existing`, the contents of
+/// `target` if it exists: rewritten, with the pinned front-matter entries
+/// `existing` has.
+fn synced_contents(
+    sync_path: &SyncPath,
+    rewriter: &Rewriter,
+    target: &Path,
+    source: &[u8],
+    existing: Option<&[u8]>,
+) -> Vec<u8> {
+    let rewritten = rewriter.rewrite_bytes(source);
+    if sync_path.pinned_front_matter.is_empty() || !front_matter::is_markdown(target) {
+        return rewritten.into_owned();
+    }
+
+    let existing = existing.and_then(|e| std::str::from_utf8(e).ok()).unwrap_or_default();
+    match std::str::from_utf8(&rewritten) {
+        Ok(text) => front_matter::keep_pinned(text, existing, &sync_path.pinned_front_matter).into_bytes(),
+        Err(_) => rewritten.into_owned(),
+    }
+}
+
+/// Sorts the commits of a change request, which the API lists in whatever
+/// order it likes, parents first.
+fn in_commit_order(repo: &git2::Repository, commits: Vec<ChangeCommit>) -> anyhow::Result<Vec<ChangeCommit>> {
+    let mut pending: HashMap<String, ChangeCommit> =
+        commits.into_iter().map(|c| (c.sha.clone(), c)).collect();
+
+    let mut walk = repo.revwalk()?;
+    walk.push_ref(SOURCE_REF)?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL)?;
+
+    let mut ordered = Vec::new();
+    for oid in walk {
+        if pending.is_empty() {
+            break;
+        }
+        if let Some(commit) = pending.remove(&oid?.to_string()) {
+            ordered.push(commit);
+        }
+    }
+    if let Some(sha) = pending.keys().next() {
+        anyhow::bail!("commit {} of the change request was not fetched", sha);
+    }
+
+    ordered.reverse();
+    Ok(ordered)
+}
+
+/// Writes `path` as it is in `tree` to `target`, rewritten, or removes
+/// `target` if the file was `removed`.
+fn copy_blob(
+    repo: &git2::Repository,
+    tree: &git2::Tree,
+    path: &Path,
+    target: &Path,
+    removed: bool,
+    sync_path: &SyncPath,
+    rewriter: &Rewriter,
+) -> anyhow::Result<()> {
+    if removed {
+        if target.exists() {
+            fs::remove_file(target).with_context(|| format!("removing {:?}", target))?;
+        }
+        return Ok(());
+    }
+
+    let blob = tree.get_path(path)?.to_object(repo)?.peel_to_blob()?;
+    if let Some(dir) = target.parent() {
+        fs::create_dir_all(dir).with_context(|| format!("creating {:?}", dir))?;
+    }
+    let existing = fs::read(target).ok();
+    let synced = synced_contents(sync_path, rewriter, target, blob.content(), existing.as_deref());
+    fs::write(target, synced).with_context(|| format!("writing {:?}", target))?;
+
+    Ok(())
+}
+
+/// Tells whether a commit on the checked out branch since `base_branch` has
+/// `trailer` in its message.
+fn has_commit(repo: &git2::Repository, base_branch: &str, trailer: &str) -> Result<bool, git2::Error> {
+    let base = repo
+        .find_branch(base_branch, git2::BranchType::Local)?
+        .get()
+        .peel_to_commit()?;
+
+    let mut walk = repo.revwalk()?;
+    walk.push_head()?;
+    walk.hide(base.id())?;
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        if commit.message().is_some_and(|m| m.lines().any(|line| line == trailer)) {
+            return Ok(true);
+        }
     }
 
-    Ok(file_diff_map)
+    Ok(false)
 }
 
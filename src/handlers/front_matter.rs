@@ -0,0 +1,258 @@
+//! The YAML front-matter Docusaurus docs start with, as far as syncing needs
+//! it: split into its top-level entries, each kept as written, so that
+//! entries can be swapped between files without reformatting the rest.
+
+use std::path::Path;
+
+/// A doc split into its front-matter and body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Document {
+    pub front_matter: Option<FrontMatter>,
+    pub body: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrontMatter {
+    /// The `---` lines around the entries
+    open: String,
+    close: String,
+    pub entries: Vec<Entry>,
+}
+
+/// A top-level key of the front-matter, with the lines of its value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// Empty for comments and blank lines before the first key
+    pub key: String,
+    pub text: String,
+}
+
+/// Whether `path` is a doc that may have front-matter.
+pub fn is_markdown(path: &Path) -> bool {
+    matches!(path.extension().and_then(|e| e.to_str()), Some("md") | Some("mdx"))
+}
+
+/// The key a front-matter line starts an entry for, if it does.
+pub fn entry_key(line: &str) -> Option<&str> {
+    if line.starts_with(|c: char| c.is_whitespace() || c == '#' || c == '-') {
+        return None;
+    }
+    let (key, _) = line.split_once(':')?;
+    Some(key.trim().trim_matches(|c| c == '"' || c == '\''))
+}
+
+fn is_delimiter(line: &str) -> bool {
+    line.trim_end() == "---"
+}
+
+impl Document {
+    pub fn parse(text: &str) -> Document {
+        let mut lines = text.split_inclusive('\n');
+        let open = match lines.next() {
+            Some(line) if is_delimiter(line) => line,
+            _ => return Document { front_matter: None, body: text.to_string() },
+        };
+
+        let mut entries: Vec<Entry> = Vec::new();
+        let mut consumed = open.len();
+        for line in lines {
+            consumed += line.len();
+            if is_delimiter(line) {
+                let front_matter = FrontMatter {
+                    open: open.to_string(),
+                    close: line.to_string(),
+                    entries,
+                };
+                return Document {
+                    front_matter: Some(front_matter),
+                    body: text[consumed..].to_string(),
+                };
+            }
+            match (entry_key(line), entries.last_mut()) {
+                (None, Some(entry)) => entry.text.push_str(line),
+                (key, _) => entries.push(Entry {
+                    key: key.unwrap_or_default().to_string(),
+                    text: line.to_string(),
+                }),
+            }
+        }
+
+        // Never closed, so not front-matter after all.
+        Document { front_matter: None, body: text.to_string() }
+    }
+
+    pub fn render(&self) -> String {
+        match &self.front_matter {
+            Some(front_matter) => format!("{}{}", front_matter.render(), self.body),
+            None => self.body.clone(),
+        }
+    }
+}
+
+impl FrontMatter {
+    pub fn render(&self) -> String {
+        let mut text = self.open.clone();
+        for entry in self.entries.iter() {
+            text.push_str(&entry.text);
+        }
+        text.push_str(&self.close);
+        text
+    }
+
+    /// How many lines the front-matter takes, delimiters included.
+    pub fn line_count(&self) -> usize {
+        self.render().lines().count()
+    }
+
+    /// The front-matter without the `pinned` entries.
+    pub fn without(&self, pinned: &[String]) -> FrontMatter {
+        FrontMatter {
+            entries: self
+                .entries
+                .iter()
+                .filter(|entry| !pinned.contains(&entry.key))
+                .cloned()
+                .collect(),
+            ..self.clone()
+        }
+    }
+}
+
+/// `source` as it should be synced over `target`: its body and front-matter,
+/// except for the `pinned` entries, which are kept as `target` has them, or
+/// left out if it has none.
+pub fn keep_pinned(source: &str, target: &str, pinned: &[String]) -> String {
+    let source = Document::parse(source);
+    let target = Document::parse(target);
+    let kept: Vec<&Entry> = target
+        .front_matter
+        .iter()
+        .flat_map(|front_matter| front_matter.entries.iter())
+        .filter(|entry| pinned.contains(&entry.key))
+        .collect();
+    if source.front_matter.is_none() && kept.is_empty() {
+        return source.body;
+    }
+
+    let mut front_matter = source.front_matter.unwrap_or_else(|| FrontMatter {
+        open: "---\n".to_string(),
+        close: "---\n".to_string(),
+        entries: Vec::new(),
+    });
+    let mut entries = Vec::new();
+    for entry in front_matter.entries.drain(..) {
+        if !pinned.contains(&entry.key) {
+            entries.push(entry);
+        } else if let Some(kept) = kept.iter().find(|kept| kept.key == entry.key) {
+            entries.push((*kept).clone());
+        }
+    }
+    for entry in kept {
+        if !entries.iter().any(|e| e.key == entry.key) {
+            entries.push(entry.clone());
+        }
+    }
+    front_matter.entries = entries;
+
+    Document {
+        front_matter: Some(front_matter),
+        body: source.body,
+    }
+    .render()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pinned(keys: &[&str]) -> Vec<String> {
+        keys.iter().map(|key| key.to_string()).collect()
+    }
+
+    #[test]
+    fn entry_keys() {
+        assert_eq!(entry_key("title: Intro"), Some("title"));
+        assert_eq!(entry_key("\"sidebar_label\": Intro"), Some("sidebar_label"));
+        assert_eq!(entry_key("'slug': /intro"), Some("slug"));
+        assert_eq!(entry_key("  - intro"), None);
+        assert_eq!(entry_key("  nested: value"), None);
+        assert_eq!(entry_key("- item"), None);
+        assert_eq!(entry_key("# comment: not a key"), None);
+        assert_eq!(entry_key("no colon"), None);
+    }
+
+    #[test]
+    fn entries_keep_the_lines_of_their_values() {
+        let text = "---\n# managed by docsbot\ntitle: Intro\ntags:\n  - start\n  - guide\ndescription: >\n  Folded\n  text\n---\n# Intro\n";
+        let document = Document::parse(text);
+
+        let front_matter = document.front_matter.as_ref().unwrap();
+        let entries: Vec<(&str, &str)> = front_matter
+            .entries
+            .iter()
+            .map(|entry| (entry.key.as_str(), entry.text.as_str()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("", "# managed by docsbot\n"),
+                ("title", "title: Intro\n"),
+                ("tags", "tags:\n  - start\n  - guide\n"),
+                ("description", "description: >\n  Folded\n  text\n"),
+            ],
+        );
+        assert_eq!(document.body, "# Intro\n");
+        assert_eq!(front_matter.line_count(), 10);
+        assert_eq!(document.render(), text);
+    }
+
+    #[test]
+    fn unclosed_front_matter_is_body() {
+        let text = "---\ntitle: Intro\n\n# Intro\n";
+        let document = Document::parse(text);
+
+        assert_eq!(document.front_matter, None);
+        assert_eq!(document.body, text);
+    }
+
+    #[test]
+    fn front_matter_starts_on_the_first_line() {
+        let text = "# Intro\n---\ntitle: Intro\n---\n";
+
+        assert_eq!(Document::parse(text).front_matter, None);
+    }
+
+    #[test]
+    fn crlf_delimiters() {
+        let document = Document::parse("---\r\ntitle: Intro\r\n---\r\nBody\r\n");
+
+        assert_eq!(document.front_matter.unwrap().entries[0].key, "title");
+        assert_eq!(document.body, "Body\r\n");
+    }
+
+    #[test]
+    fn pinned_entries_keep_the_target_values() {
+        let source = "---\ntitle: New\nslug: /next/intro\ntags:\n  - a\n---\nNew body\n";
+        let target = "---\ntitle: Old\nslug: /intro\ncustom_edit_url: null\n---\nOld body\n";
+
+        assert_eq!(
+            keep_pinned(source, target, &pinned(&["slug", "custom_edit_url"])),
+            "---\ntitle: New\nslug: /intro\ntags:\n  - a\ncustom_edit_url: null\n---\nNew body\n",
+        );
+    }
+
+    #[test]
+    fn pinned_entries_the_target_lacks_are_left_out() {
+        let source = "---\ntitle: New\nslug: /next/intro\n---\nBody\n";
+
+        assert_eq!(keep_pinned(source, "Body\n", &pinned(&["slug"])), "---\ntitle: New\n---\nBody\n");
+    }
+
+    #[test]
+    fn pinned_entries_outlive_the_source_front_matter() {
+        let target = "---\nslug: /intro\n---\nOld body\n";
+
+        assert_eq!(keep_pinned("New body\n", target, &pinned(&["slug"])), "---\nslug: /intro\n---\nNew body\n");
+        assert_eq!(keep_pinned("New body\n", "Old body\n", &pinned(&["slug"])), "New body\n");
+    }
+}
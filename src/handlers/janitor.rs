@@ -0,0 +1,98 @@
+//! Cleaning up after sync jobs that did not run to completion: checkouts
+//! left in the working directory and branches pushed to the remote that no
+//! pull request is open for anymore. Also forgets old webhook deliveries.
+
+use std::fs;
+use std::path::Path;
+
+use chrono::Utc;
+
+use crate::config;
+use crate::db::deliveries;
+use crate::handlers::Context;
+
+/// Every branch the bot pushes, and every checkout it makes under the
+/// working directory, is named `docsbot/<something>`.
+pub const BRANCH_PREFIX: &str = "docsbot/";
+
+/// How many days deliveries are kept for replaying unless
+/// `DELIVERY_RETENTION_DAYS` says otherwise.
+const DEFAULT_DELIVERY_RETENTION_DAYS: i64 = 30;
+
+/// Removes checkouts and remote branches of sync jobs, except for the
+/// branches `is_active` says are being synced right now. It is asked again
+/// right before each removal, as jobs keep starting while the janitor runs.
+pub async fn run(ctx: &Context, is_active: &(dyn Fn(&str) -> bool + Sync)) {
+    clean_checkouts(&ctx.workdir, is_active);
+    prune_deliveries(ctx);
+
+    let config = match config::get_config() {
+        Ok(config) => config,
+        Err(err) => {
+            log::error!("janitor: failed to load the configuration: {}", err);
+            return;
+        }
+    };
+    for repo in config.repos.iter() {
+        if let Err(err) = clean_branches(ctx, repo, is_active).await {
+            log::error!("janitor: failed to clean up branches of {}: {:?}", repo.name, err);
+        }
+    }
+}
+
+fn clean_checkouts(workdir: &Path, is_active: &dyn Fn(&str) -> bool) {
+    let dir = workdir.join(BRANCH_PREFIX);
+    let entries = match fs::read_dir(&dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.flatten() {
+        let branch = format!("{}{}", BRANCH_PREFIX, entry.file_name().to_string_lossy());
+        if is_active(&branch) {
+            continue;
+        }
+
+        log::info!("janitor: removing stale checkout {:?}", entry.path());
+        if let Err(err) = fs::remove_dir_all(entry.path()) {
+            log::warn!("janitor: failed to remove {:?}: {}", entry.path(), err);
+        }
+    }
+}
+
+fn prune_deliveries(ctx: &Context) {
+    let days = std::env::var("DELIVERY_RETENTION_DAYS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(DEFAULT_DELIVERY_RETENTION_DAYS);
+    let cutoff = Utc::now() - chrono::Duration::days(days);
+
+    match deliveries::prune(&ctx.db.lock().unwrap(), cutoff) {
+        Ok(0) => {}
+        Ok(pruned) => log::info!("janitor: forgot {} delivery(ies) received before {}", pruned, cutoff),
+        Err(err) => log::error!("janitor: failed to prune deliveries: {:?}", err),
+    }
+}
+
+async fn clean_branches(
+    ctx: &Context,
+    repo: &config::RepoConfig,
+    is_active: &(dyn Fn(&str) -> bool + Sync),
+) -> anyhow::Result<()> {
+    let forge = ctx.forge(repo.forge)?;
+
+    for branch in forge.list_branches(&repo.name, BRANCH_PREFIX).await? {
+        if is_active(&branch) || forge.find_open_change_request(&repo.name, &branch).await?.is_some() {
+            continue;
+        }
+        // A job may have picked the branch up while the forge was asked.
+        if is_active(&branch) {
+            continue;
+        }
+
+        log::info!("janitor: deleting branch {} of {}, no pull request is open for it", branch, repo.name);
+        forge.delete_branch(&repo.name, &branch).await?;
+    }
+
+    Ok(())
+}
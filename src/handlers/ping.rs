@@ -0,0 +1,92 @@
+use crate::config;
+use crate::forge::ForgeKind;
+use crate::github::PingEvent;
+
+/// Events docsbot needs to receive to do its job: merges to sync, and the
+/// checks that auto-merging sync pull requests waits for.
+const REQUIRED_EVENTS: &[&str] = &["pull_request", "check_suite", "status"];
+
+/// Validates the configuration of a freshly set up webhook.
+///
+/// Returns a description of the hook when it is usable, or the list of
+/// problems found with it otherwise.
+pub async fn handle(event: &PingEvent) -> Result<String, String> {
+    let mut problems = Vec::new();
+    let hook = &event.hook;
+
+    if !hook.active {
+        problems.push("the webhook is not active".to_string());
+    }
+
+    match hook.config.content_type.as_deref() {
+        Some("json") => {}
+        Some(other) => problems.push(format!(
+            "content type must be `application/json`, got `{}`",
+            other
+        )),
+        None => problems.push("content type must be `application/json`".to_string()),
+    }
+
+    let subscribed_all = hook.events.iter().any(|e| e == "*");
+    for required in REQUIRED_EVENTS {
+        if !subscribed_all && !hook.events.iter().any(|e| e == required) {
+            problems.push(format!("the webhook must subscribe to `{}` events", required));
+        }
+    }
+
+    let target = match &event.repository {
+        Some(repo) => {
+            if let Err(err) = config::get_repo_config(ForgeKind::Github, &repo.full_name).await {
+                problems.push(format!("{}: {}", repo.full_name, err));
+            }
+            repo.full_name.clone()
+        }
+        None => "organization".to_string(),
+    };
+
+    if problems.is_empty() {
+        Ok(format!(
+            "pong: hook {} for {} is configured correctly, listening to {}.\n{}",
+            event.hook_id,
+            target,
+            hook.events.join(", "),
+            event.zen,
+        ))
+    } else {
+        Err(format!(
+            "hook {} for {} is misconfigured:\n- {}",
+            event.hook_id,
+            target,
+            problems.join("\n- "),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ping(events: &[&str]) -> PingEvent {
+        serde_json::from_value(serde_json::json!({
+            "zen": "Keep it logically awesome.",
+            "hook_id": 1,
+            "hook": {
+                "type": "Repository",
+                "active": true,
+                "events": events,
+                "config": { "content_type": "json" },
+            },
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn hooks_must_send_the_events_auto_merge_waits_for() {
+        let err = handle(&ping(&["pull_request"])).await.unwrap_err();
+        assert!(err.contains("`check_suite`"), "{}", err);
+        assert!(err.contains("`status`"), "{}", err);
+
+        assert!(handle(&ping(&["pull_request", "check_suite", "status"])).await.is_ok());
+        assert!(handle(&ping(&["*"])).await.is_ok());
+    }
+}
@@ -0,0 +1,467 @@
+//! Running sync jobs: a bounded number at a time, one at a time per
+//! repository and base branch, and shutting down without losing any.
+
+use std::collections::hash_map::{Entry, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::{mpsc, watch, Semaphore};
+use tokio::task::JoinHandle;
+
+use crate::config::{self, LabelConfig};
+use crate::db::{conflicts, pending, synced};
+use crate::forge::{ChangeRequest, ForgeKind};
+use crate::handlers::statuses::{self, SyncStatus};
+use crate::handlers::{cherry_pick, janitor, Context, HandlerError};
+use crate::metrics;
+
+/// Number of sync jobs run at the same time unless `SYNC_WORKERS` says
+/// otherwise.
+const DEFAULT_WORKERS: usize = 4;
+/// How long running jobs get to finish on shutdown unless
+/// `SHUTDOWN_TIMEOUT_SECS` says otherwise.
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(60);
+/// How often the janitor runs unless `JANITOR_INTERVAL_SECS` says otherwise.
+const DEFAULT_JANITOR_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone)]
+pub struct WorkerOptions {
+    pub workers: usize,
+    pub shutdown_timeout: Duration,
+    /// How often to clean up after failed jobs, see [`janitor`]. None for
+    /// one-off runs, which should leave the remote alone.
+    pub janitor_interval: Option<Duration>,
+}
+
+impl WorkerOptions {
+    /// Reads `SYNC_WORKERS`, `SHUTDOWN_TIMEOUT_SECS` and
+    /// `JANITOR_INTERVAL_SECS`.
+    pub fn from_env() -> Self {
+        let env_number = |name| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+        };
+
+        WorkerOptions {
+            workers: env_number("SYNC_WORKERS")
+                .filter(|&n| n > 0)
+                .map_or(DEFAULT_WORKERS, |n| n as usize),
+            shutdown_timeout: env_number("SHUTDOWN_TIMEOUT_SECS")
+                .map_or(DEFAULT_SHUTDOWN_TIMEOUT, Duration::from_secs),
+            janitor_interval: Some(
+                env_number("JANITOR_INTERVAL_SECS")
+                    .filter(|&n| n > 0)
+                    .map_or(DEFAULT_JANITOR_INTERVAL, Duration::from_secs),
+            ),
+        }
+    }
+}
+
+/// Syncing one change request to one label's base branch.
+#[derive(Clone)]
+pub struct Job {
+    pub request: Arc<ChangeRequest>,
+    pub config: LabelConfig,
+}
+
+/// Jobs pushing to the same repository and base branch run one after the
+/// other, in the order their change requests were merged.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Lane {
+    forge: ForgeKind,
+    repo_name: String,
+    base_branch: String,
+}
+
+/// Resolves once `shutdown` flips to true, or never if its sender is gone
+/// without having done so.
+pub async fn shutdown_requested(mut shutdown: watch::Receiver<bool>) {
+    while !*shutdown.borrow() {
+        if shutdown.changed().await.is_err() {
+            futures::future::pending::<()>().await;
+        }
+    }
+}
+
+/// Turns merged change requests into sync jobs and runs them, starting with
+/// the ones left pending by the previous run.
+///
+/// Returns once `receiver` is closed. Normally every queued job has finished
+/// by then. If `shutdown` was triggered, jobs that had not started yet are
+/// persisted instead, and running ones get `shutdown_timeout` to finish
+/// before they are persisted and their checkouts removed too.
+pub async fn handle_pr_task(
+    ctx: Arc<Context>,
+    mut receiver: mpsc::UnboundedReceiver<ChangeRequest>,
+    options: WorkerOptions,
+    shutdown: watch::Receiver<bool>,
+) -> anyhow::Result<()> {
+    let mut pool = Pool {
+        ctx: ctx.clone(),
+        permits: Arc::new(Semaphore::new(options.workers.max(1))),
+        shutdown,
+        running: Default::default(),
+        lanes: HashMap::new(),
+        lane_tasks: Vec::new(),
+    };
+
+    if let Some(interval) = options.janitor_interval {
+        // Nothing is running yet, so this cleans up everything left behind
+        // by the previous run.
+        janitor::run(&ctx, &|_: &str| false).await;
+        tokio::spawn(run_janitor(
+            ctx.clone(),
+            pool.running.clone(),
+            pool.shutdown.clone(),
+            interval,
+        ));
+    }
+
+    let restored = pending::take_all(&ctx.db.lock().unwrap())?;
+    if !restored.is_empty() {
+        log::info!("resuming {} sync job(s) pending from the last run", restored.len());
+    }
+    for (request, label) in restored {
+        let config = match config::get_repo_config(request.forge, &request.repo_name).await {
+            Ok(c) => c,
+            Err(err) => {
+                log::error!("dropping pending job for {}: {}", request.repo_name, err);
+                continue;
+            }
+        };
+        match config.labels.iter().find(|l| l.label == label) {
+            Some(label) => {
+                pool.queue(Job {
+                    request: Arc::new(request),
+                    config: label.clone(),
+                })
+                .await
+            }
+            None => log::error!("dropping pending job for {}: label {} is no longer configured", request.repo_name, label),
+        }
+    }
+
+    while let Some(pr) = receiver.recv().await {
+        log::info!("received {} change request {}#{}", pr.forge, pr.repo_name, pr.number);
+        let config = match config::get_repo_config(pr.forge, &pr.repo_name).await {
+            Ok(c) => c,
+            Err(err) => {
+                log::error!("failed to get repo config, {}", err);
+                continue;
+            }
+        };
+
+        let labels = labels_to_sync(&ctx, &pr);
+        let pr = Arc::new(pr);
+        for label in config.labels.iter().filter(|l| labels.contains(&l.label)) {
+            pool.queue(Job {
+                request: pr.clone(),
+                config: label.clone(),
+            })
+            .await;
+        }
+    }
+
+    pool.finish(options.shutdown_timeout).await;
+    Ok(())
+}
+
+/// The labels of a change request, and for a revert the labels the reverted
+/// change request was synced to, so that the revert is synced there too.
+fn labels_to_sync(ctx: &Context, pr: &ChangeRequest) -> Vec<String> {
+    let mut labels = pr.labels.clone();
+
+    if let Some(reverted) = pr.reverted_number() {
+        match synced::labels(&ctx.db.lock().unwrap(), pr.forge, &pr.repo_name, reverted) {
+            Ok(synced) => {
+                log::info!("{}#{} reverts #{}, synced to {:?}", pr.repo_name, pr.number, reverted, synced);
+                labels.extend(synced.into_iter().filter(|l| !pr.labels.contains(l)));
+            }
+            Err(err) => log::error!("failed to look up where #{} was synced to: {:?}", reverted, err),
+        }
+    }
+
+    labels
+}
+
+struct Pool {
+    ctx: Arc<Context>,
+    permits: Arc<Semaphore>,
+    shutdown: watch::Receiver<bool>,
+    /// The job each lane is working on
+    running: Arc<Mutex<HashMap<Lane, Job>>>,
+    lanes: HashMap<Lane, mpsc::UnboundedSender<Job>>,
+    lane_tasks: Vec<JoinHandle<()>>,
+}
+
+impl Pool {
+    async fn queue(&mut self, job: Job) {
+        statuses::update(&self.ctx, &job, SyncStatus::Queued).await;
+        if *self.shutdown.borrow() {
+            persist(&self.ctx, &job);
+            return;
+        }
+
+        let lane = Lane {
+            forge: job.request.forge,
+            repo_name: job.request.repo_name.clone(),
+            base_branch: job.config.base_branch.clone(),
+        };
+        let jobs = match self.lanes.entry(lane) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let (tx, rx) = mpsc::unbounded_channel();
+                self.lane_tasks.push(tokio::spawn(run_lane(
+                    self.ctx.clone(),
+                    self.permits.clone(),
+                    self.running.clone(),
+                    self.shutdown.clone(),
+                    entry.key().clone(),
+                    rx,
+                )));
+                entry.insert(tx)
+            }
+        };
+
+        // The lane only hangs up when shutting down.
+        if let Err(mpsc::error::SendError(job)) = jobs.send(job) {
+            persist(&self.ctx, &job);
+        }
+    }
+
+    async fn finish(self, shutdown_timeout: Duration) {
+        drop(self.lanes);
+        let lanes = futures::future::join_all(self.lane_tasks);
+
+        if !*self.shutdown.borrow() {
+            log_lane_failures(lanes.await);
+            return;
+        }
+
+        match tokio::time::timeout(shutdown_timeout, lanes).await {
+            Ok(results) => log_lane_failures(results),
+            Err(_) => {
+                let running: Vec<Job> = self.running.lock().unwrap().drain().map(|(_, job)| job).collect();
+                log::warn!(
+                    "{} sync job(s) still running after {:?}, keeping them for the next start",
+                    running.len(),
+                    shutdown_timeout
+                );
+                for job in running {
+                    persist(&self.ctx, &job);
+                    cherry_pick::remove_checkout(&self.ctx, &job);
+                }
+            }
+        }
+    }
+}
+
+async fn run_janitor(
+    ctx: Arc<Context>,
+    running: Arc<Mutex<HashMap<Lane, Job>>>,
+    shutdown: watch::Receiver<bool>,
+    interval: Duration,
+) {
+    let mut ticks = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    let is_active = |branch: &str| {
+        running
+            .lock()
+            .unwrap()
+            .values()
+            .any(|job| cherry_pick::sync_branch(&job.request, &job.config) == branch)
+    };
+
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {}
+            _ = shutdown_requested(shutdown.clone()) => return,
+        }
+
+        janitor::run(&ctx, &is_active).await;
+    }
+}
+
+fn log_lane_failures(results: Vec<Result<(), tokio::task::JoinError>>) {
+    for err in results.into_iter().filter_map(Result::err) {
+        log::error!("sync lane failed: {}", err);
+    }
+}
+
+fn persist(ctx: &Context, job: &Job) {
+    let pr = &job.request;
+    log::info!("keeping sync of {}#{} to {} for the next start", pr.repo_name, pr.number, job.config.label);
+    if let Err(err) = pending::save(&ctx.db.lock().unwrap(), pr, &job.config.label) {
+        log::error!("failed to persist sync of {}#{}: {:?}", pr.repo_name, pr.number, err);
+    }
+}
+
+async fn run_lane(
+    ctx: Arc<Context>,
+    permits: Arc<Semaphore>,
+    running: Arc<Mutex<HashMap<Lane, Job>>>,
+    shutdown: watch::Receiver<bool>,
+    lane: Lane,
+    mut jobs: mpsc::UnboundedReceiver<Job>,
+) {
+    loop {
+        let job = tokio::select! {
+            biased;
+            _ = shutdown_requested(shutdown.clone()) => break,
+            job = jobs.recv() => match job {
+                Some(job) => job,
+                None => return,
+            },
+        };
+
+        running.lock().unwrap().insert(lane.clone(), job.clone());
+        let run = run_job(&ctx, &permits, shutdown.clone(), job);
+        tokio::pin!(run);
+        tokio::select! {
+            _ = &mut run => {
+                running.lock().unwrap().remove(&lane);
+            }
+            _ = shutdown_requested(shutdown.clone()) => {
+                // Keep what is queued behind the running job before waiting
+                // for it, in case it does not finish in time.
+                persist_queue(&ctx, &mut jobs);
+                run.await;
+                running.lock().unwrap().remove(&lane);
+                return;
+            }
+        }
+    }
+
+    persist_queue(&ctx, &mut jobs);
+}
+
+fn persist_queue(ctx: &Context, jobs: &mut mpsc::UnboundedReceiver<Job>) {
+    jobs.close();
+    while let Ok(job) = jobs.try_recv() {
+        persist(ctx, &job);
+    }
+}
+
+/// Runs `job` until it succeeds, fails for good or runs out of attempts.
+/// Retries happen in place so later jobs of the lane keep waiting for it.
+async fn run_job(
+    ctx: &Arc<Context>,
+    permits: &Semaphore,
+    shutdown: watch::Receiver<bool>,
+    job: Job,
+) {
+    let description = format!("{}#{} to {}", job.request.repo_name, job.request.number, job.config.label);
+    let record = |outcome: &str| {
+        metrics::SYNC_JOBS
+            .with_label_values(&[&job.request.repo_name, &job.config.label, outcome])
+            .inc()
+    };
+
+    statuses::update(ctx, &job, SyncStatus::InProgress).await;
+    for attempt in 1.. {
+        let result = {
+            let _permit = permits.acquire().await.expect("the semaphore is never closed");
+            // Run on its own task so that a panicking job does not take the
+            // rest of the lane down with it.
+            tokio::spawn(cherry_pick::handle(ctx.clone(), job.clone())).await
+        };
+
+        let err = match result {
+            Ok(Ok(report)) => {
+                record("success");
+                log::info!("synced {}", description);
+                if let Err(err) = synced::record(&ctx.db.lock().unwrap(), &job.request, &job.config.label) {
+                    log::error!("failed to record the sync of {}: {:?}", description, err);
+                }
+                statuses::update(ctx, &job, SyncStatus::Synced(report)).await;
+                return;
+            }
+            Ok(Err(err)) => err,
+            Err(err) => {
+                record("failure");
+                log::error!("sync of {} panicked: {}", description, err);
+                statuses::update(ctx, &job, SyncStatus::Failed("the sync crashed".to_string())).await;
+                return;
+            }
+        };
+
+        if err.is_retryable() && attempt < ctx.retry.max_attempts {
+            let delay = ctx.retry.delay(attempt, err.retry_after());
+            record("retry");
+            log::warn!(
+                "attempt {} to sync {} failed, retrying in {:?}: {:?}",
+                attempt, description, delay, err
+            );
+            tokio::select! {
+                _ = tokio::time::sleep(delay) => continue,
+                _ = shutdown_requested(shutdown.clone()) => {
+                    persist(ctx, &job);
+                    return;
+                }
+            }
+        }
+
+        record("failure");
+        log::error!("failed to sync {} after {} attempt(s): {:?}", description, attempt, err);
+        let conflict = match &err {
+            cherry_pick::SyncError::Rebase { branch, head, .. } => Some((branch.clone(), head.clone())),
+            _ => None,
+        };
+        let err: HandlerError = err.into();
+        statuses::update(ctx, &job, SyncStatus::Failed(err.to_string())).await;
+        match conflict {
+            Some((branch, head)) => report_conflict(ctx, &job, &branch, &head, err).await,
+            None => report_failure(ctx, &job, err).await,
+        }
+        return;
+    }
+}
+
+/// Lets the reviewers of the batch pull request on `branch` know it has
+/// conflicts with its base branch. Every merge synced to it fails until
+/// they are resolved, but the conflicts are reported once for each `head`
+/// of the branch rather than on each of those change requests.
+async fn report_conflict(ctx: &Context, job: &Job, branch: &str, head: &str, err: HandlerError) {
+    let pr = &job.request;
+    let new = conflicts::record(&ctx.db.lock().unwrap(), pr.forge, &pr.repo_name, branch, head);
+    match new {
+        Ok(true) => {}
+        Ok(false) => return,
+        Err(err) => log::error!("failed to record the conflicts of {}: {:?}", branch, err),
+    }
+
+    let body = format!(
+        "**Error**: {}.\n\n\
+         Docs labeled `{}` are not added to this pull request until then.",
+        err, job.config.label
+    );
+    let posted = match ctx.forge(pr.forge) {
+        Ok(forge) => match forge.find_open_change_request(&pr.repo_name, branch).await {
+            Ok(Some(batch)) => forge.post_comment(&pr.repo_name, batch.number, &body).await,
+            Ok(None) => Ok(()),
+            Err(err) => Err(err),
+        },
+        Err(err) => Err(err),
+    };
+    if let Err(err) = posted {
+        log::error!("failed to report the conflicts of {} in {}: {:?}", branch, pr.repo_name, err);
+    }
+}
+
+/// Lets the author of the change request know their docs were not synced.
+async fn report_failure(ctx: &Context, job: &Job, err: HandlerError) {
+    let pr = &job.request;
+    let body = format!(
+        "**Error**: failed to sync these docs to `{}` ({}): {}.\n\n\
+         The changes have to be synced by hand.",
+        job.config.base_branch, job.config.label, err
+    );
+
+    let posted = match ctx.forge(pr.forge) {
+        Ok(forge) => forge.post_comment(&pr.repo_name, pr.number, &body).await,
+        Err(err) => Err(err),
+    };
+    if let Err(err) = posted {
+        log::error!("failed to report sync failure on {}#{}: {:?}", pr.repo_name, pr.number, err);
+    }
+}
@@ -0,0 +1,482 @@
+//! A comment on open pull requests with docs sync labels, previewing which
+//! files merging them would sync to each version and whether their changes
+//! apply cleanly there, so that conflicts get fixed before merging rather
+//! than in the sync pull requests.
+
+use std::path::Path;
+
+use crate::config::{LabelConfig, RepoConfig, SyncPath};
+use crate::forge::{ChangedFile, FileStatus, Forge};
+use crate::github::PullRequestEvent;
+use crate::handlers::front_matter::{self, Document};
+use crate::handlers::rewrite::Rewriter;
+use crate::handlers::Context;
+
+/// Marks the preview comment, so that it is updated instead of posted again.
+const PREVIEW_MARKER: &str = "<!-- docsbot-preview -->";
+
+/// How one hunk of a patch fares against the target file.
+#[derive(Debug, PartialEq, Eq)]
+enum HunkFit {
+    Clean,
+    /// The target has the change already
+    Applied,
+    Conflict,
+}
+
+/// How one hunk fares, its lines in the front-matter apart from the others.
+#[derive(Debug, PartialEq, Eq)]
+struct HunkFits {
+    front_matter: HunkFit,
+    body: HunkFit,
+}
+
+/// What telling front-matter from body lines in a patch takes.
+struct FrontMatterLines<'a> {
+    /// How many lines the front-matter of the file the patch applies to
+    /// takes
+    count: usize,
+    /// Entries the target keeps its own values of
+    pinned: &'a [String],
+}
+
+/// Posts or updates the preview comment of the pull request of `event`, if
+/// it has labels `config` syncs.
+pub async fn handle(ctx: &Context, config: &RepoConfig, event: &PullRequestEvent) -> anyhow::Result<()> {
+    let pr = &event.pull_request;
+    let repo_name = &event.repository.full_name;
+    if let Some(added) = &event.label {
+        if !config.labels.iter().any(|l| l.label == added.name) {
+            return Ok(());
+        }
+    }
+    let labels: Vec<&LabelConfig> = config
+        .labels
+        .iter()
+        .filter(|l| pr.labels().iter().any(|label| label.name == l.label))
+        .collect();
+    if labels.is_empty() {
+        return Ok(());
+    }
+
+    let files = ctx.github.changed_files(repo_name, pr.number).await?;
+    let mut sections = String::new();
+    let mut conflicts = 0;
+    for label in labels.iter() {
+        let (section, label_conflicts) = preview_label(ctx, event, label, &files).await?;
+        sections.push_str(&section);
+        conflicts += label_conflicts;
+    }
+
+    let summary = if conflicts == 0 {
+        "All changes apply cleanly to the versioned docs.".to_string()
+    } else {
+        format!(
+            "**Conflicts**: {} file(s) do not apply cleanly to the versioned docs, syncing them would \
+             overwrite changes made there. Please fix them before merging.",
+            conflicts
+        )
+    };
+    let mut body = format!("{}\n### Docs sync preview\n\n{}\n\n{}", PREVIEW_MARKER, summary, sections);
+    let skipped: Vec<String> = files
+        .iter()
+        .filter(|f| !labels.iter().any(|l| target_path(l, &f.filename).is_some()))
+        .map(|f| format!("`{}`", f.filename))
+        .collect();
+    if !skipped.is_empty() {
+        body.push_str(&format!("Not synced, outside the synced directories: {}\n", skipped.join(", ")));
+    }
+
+    let comments = ctx.github.issue_comments(repo_name, pr.number).await?;
+    let previous = comments
+        .iter()
+        .find(|c| c.user.login == ctx.username && c.body.starts_with(PREVIEW_MARKER));
+    match previous {
+        Some(previous) if previous.body == body => {}
+        Some(previous) => ctx.github.update_comment(repo_name, previous.id, &body).await?,
+        None => ctx.github.post_comment(repo_name, pr.number, &body).await?,
+    }
+
+    Ok(())
+}
+
+/// The section of the preview about one label, and how many of its files
+/// conflict.
+async fn preview_label(
+    ctx: &Context,
+    event: &PullRequestEvent,
+    config: &LabelConfig,
+    files: &[ChangedFile],
+) -> anyhow::Result<(String, usize)> {
+    let repo_name = &event.repository.full_name;
+    let base_branch = &config.base_branch;
+    let mut rows = Vec::new();
+    let mut conflicts = 0;
+
+    for file in files.iter() {
+        let (sync_path, target) = match sync_path_of(config, &file.filename) {
+            Some(found) => found,
+            None => continue,
+        };
+        // The patch of a renamed file applies to the file it was renamed from.
+        let current = file
+            .previous_filename
+            .as_deref()
+            .and_then(|previous| target_path(config, previous))
+            .unwrap_or_else(|| target.clone());
+
+        let result = match (file.status, &file.patch) {
+            (FileStatus::Removed, _) => "removed".to_string(),
+            (FileStatus::Added, _) => "new file".to_string(),
+            (_, None) => "not checked, the forge has no patch for it".to_string(),
+            (_, Some(patch)) => match ctx.github.file_contents(repo_name, &current, base_branch).await? {
+                None => {
+                    conflicts += 1;
+                    format!("conflicts, `{}` is not on `{}`", current, base_branch)
+                }
+                Some(contents) => {
+                    let rewriter = Rewriter::new(sync_path)?;
+                    let source_front_matter = source_front_matter_lines(ctx, event, sync_path, file).await?;
+                    let front_matter = source_front_matter.map(|count| FrontMatterLines {
+                        count,
+                        pinned: &sync_path.pinned_front_matter,
+                    });
+                    let fits = check_hunks(
+                        patch,
+                        &String::from_utf8_lossy(&contents),
+                        &rewriter,
+                        front_matter.as_ref(),
+                    );
+                    let conflicting: Vec<String> = fits
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, fits)| fits.body == HunkFit::Conflict)
+                        .map(|(i, _)| (i + 1).to_string())
+                        .collect();
+                    let mut results = Vec::new();
+                    if fits.iter().any(|fits| fits.front_matter == HunkFit::Conflict) {
+                        results.push("front-matter conflicts".to_string());
+                    }
+                    if !conflicting.is_empty() {
+                        results.push(format!("conflicts in hunk(s) {} of {}", conflicting.join(", "), fits.len()));
+                    }
+                    if results.is_empty() {
+                        "applies cleanly".to_string()
+                    } else {
+                        conflicts += 1;
+                        results.join(", ")
+                    }
+                }
+            },
+        };
+        rows.push(format!("| `{}` | `{}` | {} |", file.filename, target, result));
+    }
+
+    let mut section = format!("#### `{}` to `{}`\n\n", config.label, base_branch);
+    if rows.is_empty() {
+        section.push_str("Nothing to sync.\n\n");
+    } else {
+        section.push_str("| File | Synced to | Result |\n| --- | --- | --- |\n");
+        section.push_str(&rows.join("\n"));
+        section.push_str("\n\n");
+    }
+
+    Ok((section, conflicts))
+}
+
+/// How many lines the front-matter of the doc `file` was changed from takes,
+/// if it is a doc and the pull request says where its base branch is.
+async fn source_front_matter_lines(
+    ctx: &Context,
+    event: &PullRequestEvent,
+    sync_path: &SyncPath,
+    file: &ChangedFile,
+) -> anyhow::Result<Option<usize>> {
+    let base = match &event.pull_request.base {
+        Some(base) => base,
+        None => return Ok(None),
+    };
+    let source = file.previous_filename.as_deref().unwrap_or(&file.filename);
+    if sync_path.pinned_front_matter.is_empty() || !front_matter::is_markdown(Path::new(source)) {
+        return Ok(None);
+    }
+
+    let contents = ctx
+        .github
+        .file_contents(&event.repository.full_name, source, &base.sha)
+        .await?;
+    Ok(contents.map(|contents| {
+        Document::parse(&String::from_utf8_lossy(&contents))
+            .front_matter
+            .map_or(0, |front_matter| front_matter.line_count())
+    }))
+}
+
+/// Where `filename` is synced to for a label, if it is synced at all.
+fn target_path(config: &LabelConfig, filename: &str) -> Option<String> {
+    sync_path_of(config, filename).map(|(_, target)| target)
+}
+
+/// The sync path `filename` is synced through for a label and where to.
+fn sync_path_of<'a>(config: &'a LabelConfig, filename: &str) -> Option<(&'a SyncPath, String)> {
+    config.sync_paths.iter().find_map(|sync_path| {
+        let base_file = Path::new(filename).strip_prefix(&sync_path.source_directory).ok()?;
+        let target = Path::new(&sync_path.target_directory).join(base_file);
+        Some((sync_path, target.to_string_lossy().into_owned()))
+    })
+}
+
+/// Checks each hunk of `patch`, in unified diff format, against `target`,
+/// rewriting its lines the way they would be when synced. A hunk applies
+/// cleanly if the lines it expects are in `target`, anywhere, as the
+/// versioned docs need not line up with the source ones.
+///
+/// Given the `front_matter` lines of the file the patch applies to, the
+/// lines of a hunk in the front-matter are checked against the front-matter
+/// of `target` and the others against its body, leaving out the pinned
+/// entries, which syncing does not touch.
+fn check_hunks(
+    patch: &str,
+    target: &str,
+    rewriter: &Rewriter,
+    front_matter: Option<&FrontMatterLines>,
+) -> Vec<HunkFits> {
+    let (target_front_matter, target_body) = match front_matter {
+        Some(front_matter) => {
+            let document = Document::parse(target);
+            let rendered = document
+                .front_matter
+                .map(|f| f.without(front_matter.pinned).render())
+                .unwrap_or_default();
+            (rendered, document.body)
+        }
+        None => (String::new(), target.to_string()),
+    };
+    let target_front_matter: Vec<&str> = target_front_matter.lines().collect();
+    let target_body: Vec<&str> = target_body.lines().collect();
+    let front_matter_count = front_matter.map_or(0, |front_matter| front_matter.count);
+    let pinned = front_matter.map_or(&[][..], |front_matter| front_matter.pinned);
+
+    let fit = |target: &[&str], old: &[String], new: &[String]| {
+        if contains(target, old) {
+            HunkFit::Clean
+        } else if contains(target, new) {
+            HunkFit::Applied
+        } else {
+            HunkFit::Conflict
+        }
+    };
+
+    let mut fits = Vec::new();
+    // The lines of the current hunk, front-matter and body, and the line of
+    // the file the patch applies to it is at
+    let mut hunk: Option<([Vec<String>; 4], usize)> = None;
+    for line in patch.lines().chain(std::iter::once("@@")) {
+        if line.starts_with("@@") {
+            if let Some(([front_old, front_new, body_old, body_new], _)) = hunk.take() {
+                fits.push(HunkFits {
+                    front_matter: fit(
+                        &target_front_matter,
+                        &without_pinned(front_old, pinned),
+                        &without_pinned(front_new, pinned),
+                    ),
+                    body: fit(&target_body, &body_old, &body_new),
+                });
+            }
+            hunk = Some((Default::default(), hunk_start(line)));
+            continue;
+        }
+
+        let ([front_old, front_new, body_old, body_new], position) = match hunk.as_mut() {
+            Some(hunk) => hunk,
+            None => continue,
+        };
+        let (old, new) = if *position <= front_matter_count {
+            (front_old, front_new)
+        } else {
+            (body_old, body_new)
+        };
+        let rewritten = || rewriter.rewrite(line.get(1..).unwrap_or_default()).into_owned();
+        match line.chars().next() {
+            Some('-') => {
+                old.push(rewritten());
+                *position += 1;
+            }
+            Some('+') => new.push(rewritten()),
+            // An empty context line may have been trimmed somewhere on the
+            // way.
+            Some(' ') | None => {
+                old.push(rewritten());
+                new.push(rewritten());
+                *position += 1;
+            }
+            // `\ No newline at end of file`
+            _ => {}
+        }
+    }
+
+    fits
+}
+
+/// The line of the file a patch applies to a hunk starts at, going by its
+/// `@@ -start,count +start,count @@` header.
+fn hunk_start(header: &str) -> usize {
+    header
+        .split_whitespace()
+        .find_map(|range| range.strip_prefix('-'))
+        .and_then(|range| range.split(',').next())
+        .and_then(|start| start.parse().ok())
+        .unwrap_or(1)
+}
+
+/// Front-matter `lines` without the lines of the `pinned` entries.
+fn without_pinned(lines: Vec<String>, pinned: &[String]) -> Vec<String> {
+    let mut current = None;
+    lines
+        .into_iter()
+        .filter(|line| {
+            if line.trim_end() == "---" {
+                current = None;
+            } else if let Some(key) = front_matter::entry_key(line) {
+                current = Some(key.to_string());
+            }
+            !current.as_ref().is_some_and(|key| pinned.contains(key))
+        })
+        .collect()
+}
+
+fn contains(haystack: &[&str], needle: &[String]) -> bool {
+    needle.is_empty()
+        || haystack
+            .windows(needle.len())
+            .any(|window| window.iter().zip(needle).all(|(line, expected)| line == expected))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewriter(version: Option<&str>) -> Rewriter {
+        let mut toml = "source_directory = \"docs\"\nsource_sidebars = \"sidebars.js\"\n\
+                        target_directory = \"versioned_docs/version-2.0.4\"\n\
+                        target_sidebars = \"versioned_sidebars/version-2.0.4-sidebars.json\"\n"
+            .to_string();
+        if let Some(version) = version {
+            toml.push_str(&format!("version = \"{}\"\n", version));
+        }
+        Rewriter::new(&toml::from_str::<SyncPath>(&toml).unwrap()).unwrap()
+    }
+
+    fn body(fit: HunkFit) -> HunkFits {
+        HunkFits {
+            front_matter: HunkFit::Clean,
+            body: fit,
+        }
+    }
+
+    const PATCH: &str = "@@ -1,3 +1,3 @@\n # Intro\n \n-Some text.\n+Better text.";
+
+    #[test]
+    fn hunks_apply_anywhere_in_the_target() {
+        let target = "# Old intro\n\nIntro\n\nSome text.\n";
+
+        assert_eq!(check_hunks(PATCH, target, &rewriter(None), None), [body(HunkFit::Conflict)]);
+        let target = "# Intro\n\nSome text.\n\nMore text.\n";
+        assert_eq!(check_hunks(PATCH, target, &rewriter(None), None), [body(HunkFit::Clean)]);
+    }
+
+    #[test]
+    fn targets_with_the_change_have_it_applied() {
+        let target = "# Intro\n\nBetter text.\n";
+
+        assert_eq!(check_hunks(PATCH, target, &rewriter(None), None), [body(HunkFit::Applied)]);
+    }
+
+    #[test]
+    fn each_hunk_is_checked_on_its_own() {
+        let patch = "@@ -1,2 +1,2 @@\n-# Intro\n+# Introduction\n \n@@ -10,2 +10,2 @@\n-Bye.\n+Goodbye.\n";
+        let target = "# Intro\n\nHello.\n";
+
+        assert_eq!(
+            check_hunks(patch, target, &rewriter(None), None),
+            [body(HunkFit::Clean), body(HunkFit::Conflict)],
+        );
+    }
+
+    #[test]
+    fn lines_are_compared_rewritten() {
+        let patch = "@@ -1 +1 @@\n-See [usage](/docs/next/usage).\n+See [usage](/docs/next/usage) first.";
+        let target = "See [usage](/docs/2.0.4/usage).\n";
+
+        assert_eq!(check_hunks(patch, target, &rewriter(Some("2.0.4")), None), [body(HunkFit::Clean)]);
+        assert_eq!(check_hunks(patch, target, &rewriter(None), None), [body(HunkFit::Conflict)]);
+    }
+
+    #[test]
+    fn missing_newlines_at_the_end_are_not_lines() {
+        let patch = "@@ -1,2 +1,2 @@\n # Intro\n-Old\n\\ No newline at end of file\n+New\n\\ No newline at end of file";
+
+        assert_eq!(check_hunks(patch, "# Intro\nOld", &rewriter(None), None), [body(HunkFit::Clean)]);
+    }
+
+    #[test]
+    fn front_matter_lines_are_checked_against_the_target_front_matter() {
+        // The source has `---`, `title: Old` and `---` before its body.
+        let patch = "@@ -2,4 +2,4 @@\n-title: Old\n+title: New\n ---\n-Body\n+New body";
+        let lines = FrontMatterLines { count: 3, pinned: &[] };
+
+        let target = "---\ntitle: Old\n---\nOther body\n";
+        assert_eq!(
+            check_hunks(patch, target, &rewriter(None), Some(&lines)),
+            [HunkFits {
+                front_matter: HunkFit::Clean,
+                body: HunkFit::Conflict,
+            }],
+        );
+        let target = "---\ntitle: Other\n---\nBody\n";
+        assert_eq!(
+            check_hunks(patch, target, &rewriter(None), Some(&lines)),
+            [HunkFits {
+                front_matter: HunkFit::Conflict,
+                body: HunkFit::Clean,
+            }],
+        );
+    }
+
+    #[test]
+    fn pinned_entries_are_left_out() {
+        let patch = "@@ -2,3 +2,3 @@\n title: Intro\n-slug: /next/intro\n+slug: /next/start\n ---";
+        let target = "---\ntitle: Intro\nslug: /intro\n---\nBody\n";
+        let pinned = ["slug".to_string()];
+
+        let lines = FrontMatterLines { count: 4, pinned: &pinned };
+        assert_eq!(check_hunks(patch, target, &rewriter(None), Some(&lines)), [body(HunkFit::Clean)]);
+        let lines = FrontMatterLines { count: 4, pinned: &[] };
+        assert_eq!(
+            check_hunks(patch, target, &rewriter(None), Some(&lines)),
+            [HunkFits {
+                front_matter: HunkFit::Conflict,
+                body: HunkFit::Clean,
+            }],
+        );
+    }
+
+    #[test]
+    fn hunk_starts() {
+        assert_eq!(hunk_start("@@ -12,7 +12,8 @@ ## Usage"), 12);
+        assert_eq!(hunk_start("@@ -5 +5 @@"), 5);
+        assert_eq!(hunk_start("@@ -0,0 +1,3 @@"), 0);
+        assert_eq!(hunk_start("@@ malformed @@"), 1);
+    }
+
+    #[test]
+    fn pinned_lines() {
+        let lines = ["title: Intro", "slug: >", "  /intro", "tags:", "  - start", "slug: /again", "---"];
+        let lines: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+
+        assert_eq!(
+            without_pinned(lines, &["slug".to_string()]),
+            ["title: Intro", "tags:", "  - start", "---"],
+        );
+    }
+}
@@ -0,0 +1,116 @@
+//! Deciding whether a failed sync job is worth another attempt, and when.
+
+use std::time::Duration;
+
+use rand::Rng;
+
+use crate::forge::ApiError;
+use crate::git::PushError;
+
+/// How often and how patiently a failed sync job is retried.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first one
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled for every following one
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_secs(5),
+            max_delay: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Reads `SYNC_MAX_ATTEMPTS` and `SYNC_RETRY_DELAY_SECS`, falling back to
+    /// the defaults for anything unset.
+    pub fn from_env() -> Self {
+        let mut policy = RetryPolicy::default();
+
+        if let Some(attempts) = env_number("SYNC_MAX_ATTEMPTS") {
+            policy.max_attempts = attempts.max(1) as u32;
+        }
+        if let Some(secs) = env_number("SYNC_RETRY_DELAY_SECS") {
+            policy.base_delay = Duration::from_secs(secs);
+        }
+
+        policy
+    }
+
+    /// Delay before retrying after `attempt` (starting at 1) failed: the
+    /// exponential backoff, of which a random half is taken off so that
+    /// jobs failing together do not retry in lockstep. Never shorter than
+    /// `retry_after`, what the forge asked to wait, see [`retry_after`].
+    pub fn delay(&self, attempt: u32, retry_after: Option<Duration>) -> Duration {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+
+        backoff
+            .mul_f64(rand::thread_rng().gen_range(0.5..=1.0))
+            .max(retry_after.unwrap_or_default())
+    }
+}
+
+fn env_number(name: &str) -> Option<u64> {
+    std::env::var(name).ok()?.parse().ok()
+}
+
+/// Raised when a pushed branch does not show up in the forge API in time.
+#[derive(thiserror::Error, Debug)]
+#[error("pushed branch {branch} did not show up on {repo_name} in time")]
+pub struct BranchNotVisible {
+    pub repo_name: String,
+    pub branch: String,
+}
+
+/// Tells failures that may go away on their own (network trouble, server
+/// errors, rate limiting, a push racing another one) from those that will
+/// not.
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| {
+        if let Some(err) = cause.downcast_ref::<ApiError>() {
+            return err.rate_limited
+                || err.status.is_server_error()
+                || err.status == reqwest::StatusCode::TOO_MANY_REQUESTS;
+        }
+
+        if let Some(err) = cause.downcast_ref::<reqwest::Error>() {
+            return err.is_timeout()
+                || err.is_connect()
+                || err.status().is_some_and(|status| {
+                    status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                });
+        }
+
+        if let Some(err) = cause.downcast_ref::<git2::Error>() {
+            return err.code() == git2::ErrorCode::NotFastForward
+                || matches!(
+                    err.class(),
+                    git2::ErrorClass::Net | git2::ErrorClass::Http | git2::ErrorClass::Ssh
+                );
+        }
+
+        if let Some(err) = cause.downcast_ref::<PushError>() {
+            // A stale lease means someone else pushed, which retrying would
+            // push over.
+            return matches!(err, PushError::Failed { .. });
+        }
+
+        cause.is::<BranchNotVisible>()
+    })
+}
+
+/// How long the forge asked to wait before trying `err` again, if it did.
+pub fn retry_after(err: &anyhow::Error) -> Option<Duration> {
+    err.chain()
+        .filter_map(|cause| cause.downcast_ref::<ApiError>())
+        .find_map(|err| err.retry_after)
+}
@@ -0,0 +1,154 @@
+//! Rewriting docs as they are copied into a versioned directory: links to
+//! the `next` docs pointed at the version of the copy, version placeholders
+//! filled in, and whatever else the rules of the sync path say.
+
+use std::borrow::Cow;
+
+use anyhow::Context as _;
+use regex::Regex;
+
+use crate::config::SyncPath;
+
+/// The rewrite rules of a sync path, ready to apply.
+pub struct Rewriter {
+    rules: Vec<(Regex, String)>,
+}
+
+impl Rewriter {
+    pub fn new(sync_path: &SyncPath) -> anyhow::Result<Rewriter> {
+        let mut rules = Vec::new();
+
+        if let Some(version) = &sync_path.version {
+            let version = version.replace('$', "$$");
+            // Docusaurus serves the unreleased docs under `/docs/next/`.
+            rules.push((Regex::new(r"/docs/next/")?, format!("/docs/{}/", version)));
+            rules.push((Regex::new(r"\{\{\s*version\s*\}\}")?, version));
+        }
+        for rule in sync_path.rewrites.iter() {
+            let pattern = Regex::new(&rule.pattern)
+                .with_context(|| format!("invalid rewrite pattern `{}`", rule.pattern))?;
+            rules.push((pattern, rule.replacement.clone()));
+        }
+
+        Ok(Rewriter { rules })
+    }
+
+    /// Applies the rules, in order, to each line of `text`.
+    pub fn rewrite<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if self.rules.is_empty() {
+            return Cow::Borrowed(text);
+        }
+
+        let mut rewritten = String::with_capacity(text.len());
+        for line in text.split_inclusive('\n') {
+            let (content, newline) = match line.strip_suffix('\n') {
+                Some(content) => (content, "\n"),
+                None => (line, ""),
+            };
+            let mut content = Cow::Borrowed(content);
+            for (pattern, replacement) in self.rules.iter() {
+                if let Cow::Owned(replaced) = pattern.replace_all(&content, replacement.as_str()) {
+                    content = Cow::Owned(replaced);
+                }
+            }
+            rewritten.push_str(&content);
+            rewritten.push_str(newline);
+        }
+
+        Cow::Owned(rewritten)
+    }
+
+    /// Rewrites the contents of a file, leaving files that are not text
+    /// alone.
+    pub fn rewrite_bytes<'a>(&self, contents: &'a [u8]) -> Cow<'a, [u8]> {
+        match std::str::from_utf8(contents) {
+            Ok(text) => match self.rewrite(text) {
+                Cow::Borrowed(_) => Cow::Borrowed(contents),
+                Cow::Owned(text) => Cow::Owned(text.into_bytes()),
+            },
+            Err(_) => Cow::Borrowed(contents),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::RewriteRule;
+
+    fn sync_path(version: Option<&str>, rewrites: &[(&str, &str)]) -> SyncPath {
+        SyncPath {
+            source_directory: "docs".to_string(),
+            source_sidebars: "sidebars.js".to_string(),
+            target_directory: "versioned_docs/version-2.0.4".to_string(),
+            target_sidebars: "versioned_sidebars/version-2.0.4-sidebars.json".to_string(),
+            version: version.map(str::to_string),
+            rewrites: rewrites
+                .iter()
+                .map(|(pattern, replacement)| RewriteRule {
+                    pattern: pattern.to_string(),
+                    replacement: replacement.to_string(),
+                })
+                .collect(),
+            pinned_front_matter: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn the_version_fills_in_links_and_placeholders() {
+        let rewriter = Rewriter::new(&sync_path(Some("2.0.4"), &[])).unwrap();
+
+        assert_eq!(
+            rewriter.rewrite("See [usage](/docs/next/usage).\nInstall {{ version }}, or {{version}}.\n"),
+            "See [usage](/docs/2.0.4/usage).\nInstall 2.0.4, or 2.0.4.\n",
+        );
+    }
+
+    #[test]
+    fn a_dollar_in_the_version_is_taken_literally() {
+        let rewriter = Rewriter::new(&sync_path(Some("$1.0"), &[])).unwrap();
+
+        assert_eq!(rewriter.rewrite("Install {{version}}"), "Install $1.0");
+    }
+
+    #[test]
+    fn replacements_can_refer_to_capture_groups() {
+        let rules = [(r"img/(\w+)\.png", "img/$1@2x.png"), (r"(?P<tool>npm) i", "$tool install")];
+        let rewriter = Rewriter::new(&sync_path(None, &rules)).unwrap();
+
+        assert_eq!(
+            rewriter.rewrite("![logo](img/logo.png)\nnpm i docsbot"),
+            "![logo](img/logo@2x.png)\nnpm install docsbot",
+        );
+    }
+
+    #[test]
+    fn rules_apply_in_order_after_the_version() {
+        let rewriter = Rewriter::new(&sync_path(Some("2.0.4"), &[("/docs/2.0.4/", "/v2/")])).unwrap();
+
+        assert_eq!(rewriter.rewrite("/docs/next/intro\n"), "/v2/intro\n");
+    }
+
+    #[test]
+    fn without_rules_text_is_borrowed() {
+        let rewriter = Rewriter::new(&sync_path(None, &[])).unwrap();
+
+        assert!(matches!(rewriter.rewrite("/docs/next/intro"), Cow::Borrowed(_)));
+        assert!(matches!(rewriter.rewrite_bytes(b"/docs/next/intro"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn files_that_are_not_text_are_left_alone() {
+        let rewriter = Rewriter::new(&sync_path(Some("2.0.4"), &[])).unwrap();
+        let png = b"\x89PNG /docs/next/ \xff";
+
+        assert_eq!(rewriter.rewrite_bytes(png), &png[..]);
+    }
+
+    #[test]
+    fn invalid_patterns_are_reported() {
+        let err = Rewriter::new(&sync_path(None, &[("(unclosed", "")])).err().unwrap();
+
+        assert_eq!(err.to_string(), "invalid rewrite pattern `(unclosed`");
+    }
+}
@@ -0,0 +1,83 @@
+//! A `docsbot/sync` commit status on the merge commit of a change request
+//! for each label it is synced to, so that maintainers see how the syncs
+//! went without reading the comments of the bot. GitHub only.
+//!
+//! Statuses rather than check runs, which only GitHub Apps may create.
+
+use std::collections::BTreeSet;
+
+use serde_json::json;
+
+use crate::forge::ForgeKind;
+use crate::handlers::cherry_pick::SyncReport;
+use crate::handlers::{Context, Job};
+
+pub const CONTEXT: &str = "docsbot/sync";
+
+/// The longest description GitHub takes.
+const MAX_DESCRIPTION: usize = 140;
+
+/// Where the sync to one label is at.
+#[derive(Debug, Clone)]
+pub enum SyncStatus {
+    Queued,
+    InProgress,
+    Synced(SyncReport),
+    /// With what went wrong
+    Failed(String),
+}
+
+/// Sets the status of the sync of `job` on the merge commit of its change
+/// request.
+pub async fn update(ctx: &Context, job: &Job, status: SyncStatus) {
+    let pr = &job.request;
+    let sha = match (pr.forge, &pr.merge_commit_sha) {
+        (ForgeKind::Github, Some(sha)) => sha,
+        _ => return,
+    };
+
+    let base_branch = &job.config.base_branch;
+    let (state, description, target_url) = match &status {
+        SyncStatus::Queued => ("pending", format!("Queued to sync to `{}`", base_branch), None),
+        SyncStatus::InProgress => ("pending", format!("Syncing to `{}`", base_branch), None),
+        SyncStatus::Synced(report) => {
+            let (done, url) = match &report.change_request_url {
+                Some(url) => (format!("Synced to `{}`", base_branch), Some(url.clone())),
+                None => (format!("Pushed onto `{}`", base_branch), None),
+            };
+            ("success", format!("{}: {}", done, counts(report)), url)
+        }
+        SyncStatus::Failed(message) => ("failure", format!("Failed: {}", message), None),
+    };
+    let payload = json!({
+        "state": state,
+        "context": format!("{} ({})", CONTEXT, job.config.label),
+        "description": truncate(&description),
+        "target_url": target_url,
+    });
+
+    if let Err(err) = ctx.github.create_commit_status(&pr.repo_name, sha, &payload).await {
+        log::warn!("failed to set the sync status of {}#{}: {:?}", pr.repo_name, pr.number, err);
+    }
+}
+
+/// How many files were applied, skipped and kept their own front matter.
+fn counts(report: &SyncReport) -> String {
+    let count = |files: &BTreeSet<String>, what: &str| format!("{} {}", files.len(), what);
+    let mut counts = vec![count(&report.applied, "applied"), count(&report.skipped, "skipped")];
+    if !report.front_matter_conflicts.is_empty() {
+        counts.push(count(&report.front_matter_conflicts, "front-matter conflict(s)"));
+    }
+    counts.join(", ")
+}
+
+/// Cuts `description` down to what GitHub takes.
+fn truncate(description: &str) -> String {
+    let description = description.replace('\n', " ");
+    if description.chars().count() <= MAX_DESCRIPTION {
+        return description;
+    }
+    let mut cut: String = description.chars().take(MAX_DESCRIPTION - 1).collect();
+    cut.push('…');
+    cut
+}
@@ -0,0 +1,219 @@
+//! Tracking translations that fall behind the docs they translate.
+//!
+//! Each merged pull request changing a doc without touching its
+//! translations leaves them stale: the change is recorded against them until
+//! a pull request updates them, and an issue per language lists the stale
+//! translations with the changes they miss. Updating a translation records
+//! the commit it caught up with.
+
+use std::path::Path;
+
+use crate::config::{RepoConfig, TranslationConfig};
+use crate::db::translations::{self, SourceChange, StaleTranslation};
+use crate::forge::{ChangedFile, FileStatus, Forge, ForgeKind};
+use crate::github::PullRequestEvent;
+use crate::handlers::Context;
+
+/// GitHub refuses issue descriptions longer than 65536 characters; diffs,
+/// then whole translations, are left out past this.
+const ISSUE_BODY_LIMIT: usize = 60_000;
+/// Translations listed in an issue at most, the rest wait for those to be
+/// updated.
+const MAX_LISTED_TRANSLATIONS: usize = 100;
+
+lazy_static::lazy_static! {
+    /// Held while handling a merge, so that merges handled at the same time
+    /// do not both open an issue.
+    static ref TRACKING: tokio::sync::Mutex<()> = tokio::sync::Mutex::new(());
+}
+
+/// Records what the merged pull request of `event` did to the translations
+/// `config` tracks, and updates the issues listing the stale ones.
+pub async fn handle(ctx: &Context, config: &RepoConfig, event: &PullRequestEvent) -> anyhow::Result<()> {
+    let pr = &event.pull_request;
+    let repo_name = &event.repository.full_name;
+    let merge_commit = match (&pr.merge_commit_sha, config.translations.is_empty()) {
+        (Some(sha), false) => sha,
+        _ => return Ok(()),
+    };
+    let _tracking = TRACKING.lock().await;
+
+    let files = ctx.github.changed_files(repo_name, pr.number).await?;
+    for translation in config.translations.iter() {
+        let updated: Vec<&str> = files
+            .iter()
+            .filter(|f| Path::new(&f.filename).starts_with(&translation.translation_directory))
+            .map(|f| f.filename.as_str())
+            .collect();
+        for path in updated.iter() {
+            translations::align(&ctx.db.lock().unwrap(), ForgeKind::Github, repo_name, path, merge_commit)?;
+        }
+
+        let mut left_stale = false;
+        for file in files.iter() {
+            let path = match translation_path(translation, file) {
+                Some(path) => path,
+                None => continue,
+            };
+            if updated.contains(&path.as_str()) {
+                continue;
+            }
+            // Only docs translated already can fall behind.
+            if ctx.github.file_contents(repo_name, &path, merge_commit).await?.is_none() {
+                continue;
+            }
+
+            log::info!("{}#{} leaves {} stale", repo_name, pr.number, path);
+            let change = SourceChange {
+                source_path: file.filename.clone(),
+                number: pr.number,
+                patch: file.patch.clone(),
+            };
+            let conn = ctx.db.lock().unwrap();
+            translations::add_change(&conn, ForgeKind::Github, repo_name, &translation.language, &path, &change)?;
+            left_stale = true;
+        }
+
+        if left_stale {
+            if let Some(label) = &translation.stale_label {
+                ctx.github.add_labels(repo_name, pr.number, std::slice::from_ref(label)).await?;
+            }
+        }
+        if left_stale || !updated.is_empty() {
+            update_issue(ctx, repo_name, translation).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The translation of the doc `file` changed, if it is a translated doc.
+/// Removed docs leave their translations stale too, until they are removed
+/// as well.
+fn translation_path(translation: &TranslationConfig, file: &ChangedFile) -> Option<String> {
+    let filename = match (file.status, &file.previous_filename) {
+        (FileStatus::Renamed, Some(previous)) => previous,
+        _ => &file.filename,
+    };
+    let base_file = Path::new(filename).strip_prefix(&translation.source_directory).ok()?;
+    let path = Path::new(&translation.translation_directory).join(base_file);
+    Some(path.to_string_lossy().into_owned())
+}
+
+/// Opens or updates the issue listing the stale translations to a language,
+/// closing it once there are none left.
+async fn update_issue(ctx: &Context, repo_name: &str, translation: &TranslationConfig) -> anyhow::Result<()> {
+    let language = &translation.language;
+    let (stale, issue) = {
+        let conn = ctx.db.lock().unwrap();
+        (
+            translations::stale(&conn, ForgeKind::Github, repo_name, language)?,
+            translations::issue(&conn, ForgeKind::Github, repo_name, language)?,
+        )
+    };
+
+    let body = issue_body(language, &stale);
+    match issue {
+        Some(number) => ctx.github.update_issue(repo_name, number, &body, !stale.is_empty()).await?,
+        None if stale.is_empty() => {}
+        None => {
+            let title = format!("Stale `{}` translations", language);
+            let number = ctx.github.create_issue(repo_name, &title, &body).await?;
+            log::info!("opened {}#{} to track stale {} translations", repo_name, number, language);
+            translations::set_issue(&ctx.db.lock().unwrap(), ForgeKind::Github, repo_name, language, number)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn issue_body(language: &str, stale: &[StaleTranslation]) -> String {
+    if stale.is_empty() {
+        return format!("All `{}` translations are up to date with the docs they translate.\n", language);
+    }
+
+    let mut body = format!(
+        "The `{}` translations below are behind the docs they translate. Updating one in a pull request \
+         takes it off this list.\n",
+        language
+    );
+    for (listed, translation) in stale.iter().enumerate() {
+        let numbers: Vec<String> = translation.changes.iter().map(|c| format!("#{}", c.number)).collect();
+        let mut heading = format!("\n#### `{}`\n\n", translation.path);
+        match &translation.aligned_with {
+            Some(sha) => heading.push_str(&format!("Last updated along with {}. ", sha)),
+            None => heading.push_str("Not updated since docsbot started tracking it. "),
+        }
+        heading.push_str(&format!("Missing changes from {}.\n", numbers.join(", ")));
+        if listed == MAX_LISTED_TRANSLATIONS || body.len() + heading.len() > ISSUE_BODY_LIMIT {
+            body.push_str(&format!(
+                "\n{} more translation(s) are behind as well, they are listed once these are updated.\n",
+                stale.len() - listed
+            ));
+            break;
+        }
+        body.push_str(&heading);
+
+        let mut left_out = Vec::new();
+        for change in translation.changes.iter() {
+            let diff = match &change.patch {
+                Some(patch) => format!(
+                    "\n<details><summary>#{}: <code>{}</code></summary>\n\n```diff\n{}\n```\n\n</details>\n",
+                    change.number, change.source_path, patch
+                ),
+                None => String::new(),
+            };
+            if body.len() + diff.len() > ISSUE_BODY_LIMIT {
+                left_out.push(format!("#{}", change.number));
+            } else {
+                body.push_str(&diff);
+            }
+        }
+        if !left_out.is_empty() {
+            body.push_str(&format!(
+                "\nThe diffs of {} are left out, this issue is too long.\n",
+                left_out.join(", ")
+            ));
+        }
+    }
+
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stale(count: usize, patch: &str) -> Vec<StaleTranslation> {
+        (0..count)
+            .map(|n| StaleTranslation {
+                path: format!("i18n/zh/docusaurus-plugin-content-docs/current/doc-{}.md", n),
+                aligned_with: None,
+                changes: vec![SourceChange {
+                    source_path: format!("docs/doc-{}.md", n),
+                    number: 140,
+                    patch: Some(patch.to_string()),
+                }],
+            })
+            .collect()
+    }
+
+    #[test]
+    fn long_lists_are_cut_short() {
+        let body = issue_body("zh", &stale(5000, "-a\n+b"));
+
+        assert!(body.len() <= 65536, "{}", body.len());
+        assert_eq!(body.matches("#### ").count(), MAX_LISTED_TRANSLATIONS);
+        assert!(body.ends_with("\n4900 more translation(s) are behind as well, they are listed once these are updated.\n"));
+    }
+
+    #[test]
+    fn long_diffs_are_left_out() {
+        let body = issue_body("zh", &stale(3, &"+line\n".repeat(5000)));
+
+        assert!(body.len() <= 65536, "{}", body.len());
+        assert_eq!(body.matches("#### ").count(), 3);
+        assert_eq!(body.matches("<details>").count(), 1);
+        assert_eq!(body.matches("The diffs of #140 are left out").count(), 2);
+    }
+}
@@ -3,7 +3,11 @@ pub mod db;
 pub mod model;
 pub mod handlers;
 pub mod github;
+pub mod gitlab;
+pub mod gitea;
+pub mod forge;
 pub mod git;
 pub mod webhook;
 pub mod config;
-pub mod interactions;
\ No newline at end of file
+pub mod interactions;
+pub mod metrics;
\ No newline at end of file
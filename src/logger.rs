@@ -6,7 +6,7 @@ use std::pin::Pin;
 
 thread_local! {
     // Uuid implement Copy
-    static REQUEST_ID: Cell<Option<Uuid>> = Cell::new(None);
+    static REQUEST_ID: Cell<Option<Uuid>> = const { Cell::new(None) };
 }
 
 pub fn init() {
@@ -3,27 +3,172 @@ use anyhow::Context as _;
 use std::{env, thread};
 use std::net::SocketAddr;
 use std::option::Option::Some;
-use std::sync::{Arc, mpsc};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
 use futures::future::FutureExt;
 use futures::StreamExt;
 use reqwest::Client;
 use uuid::Uuid;
-use docsbot::{logger, db, webhook, github};
-use docsbot::github::PullRequestEvent;
-use docsbot::handlers::{Context, handle_pr_task};
+use chrono::Utc;
+use docsbot::{logger, db, webhook, github, gitlab, gitea, git, config, metrics};
+use docsbot::db::deliveries;
+use docsbot::forge::{ChangeRequest, ForgeKind};
+use docsbot::github::User;
+use docsbot::handlers::{Context, WorkerOptions, handle_pr_task, shutdown_requested};
+use docsbot::handlers::retry::RetryPolicy;
+use docsbot::webhook::WebhookOutcome;
 use hyper::{header, Body, Request, Response, Server, StatusCode, Method};
+use serde_json::json;
+use subtle::ConstantTimeEq;
+
+fn json_response(status: StatusCode, body: serde_json::Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+/// How long the outcome of checking the GitHub token is reused for, so
+/// that readiness probes do not eat into the API rate limit.
+const TOKEN_CHECK_TTL: Duration = Duration::from_secs(60);
+
+lazy_static::lazy_static! {
+    static ref TOKEN_CHECK: Mutex<Option<(Instant, Result<(), String>)>> = Mutex::new(None);
+}
+
+async fn check_github_token(ctx: &Context) -> Result<(), String> {
+    if let Some((checked_at, result)) = TOKEN_CHECK.lock().unwrap().as_ref() {
+        if checked_at.elapsed() < TOKEN_CHECK_TTL {
+            return result.clone();
+        }
+    }
+
+    let result = User::current(&ctx.github).await.map(|_| ()).map_err(|e| e.to_string());
+    *TOKEN_CHECK.lock().unwrap() = Some((Instant::now(), result.clone()));
+    result
+}
+
+async fn readiness(ctx: &Context) -> Response<Body> {
+    let config = config::get_config().map(|_| ()).map_err(|e| e.to_string());
+    let db = db::ping().map_err(|e| e.to_string());
+    let github = check_github_token(ctx).await;
+
+    let ready = config.is_ok() && db.is_ok() && github.is_ok();
+    let check = |r: &Result<(), String>| match r {
+        Ok(()) => json!({"ok": true}),
+        Err(e) => json!({"ok": false, "error": e}),
+    };
+
+    let status = if ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    json_response(status, json!({
+        "ready": ready,
+        "checks": {
+            "config": check(&config),
+            "database": check(&db),
+            "github": check(&github),
+        },
+    }))
+}
+
+fn render_metrics() -> Response<Body> {
+    match metrics::gather() {
+        Ok((content_type, body)) => Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(body))
+            .unwrap(),
+        Err(err) => {
+            log::error!("failed to encode metrics: {:?}", err);
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from(format!("failed to encode metrics: {:?}", err)))
+                .unwrap()
+        }
+    }
+}
+
+fn version() -> Response<Body> {
+    let repos = config::get_config()
+        .map(|c| c.repos.iter().map(|r| r.name.clone()).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    json_response(StatusCode::OK, json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_sha": env!("GIT_SHA"),
+        "repos": repos,
+    }))
+}
+
+fn outcome_response(outcome: Result<WebhookOutcome, webhook::WebhookError>) -> Response<Body> {
+    match outcome {
+        Ok(WebhookOutcome::Processed) => {
+            metrics::WEBHOOK_REQUESTS.with_label_values(&["processed"]).inc();
+            Response::new(Body::from("processed request"))
+        }
+        Ok(WebhookOutcome::Pong { valid, message }) => {
+            metrics::WEBHOOK_REQUESTS.with_label_values(&["ping"]).inc();
+            let status = if valid {
+                StatusCode::OK
+            } else {
+                StatusCode::BAD_REQUEST
+            };
+            Response::builder()
+                .status(status)
+                .body(Body::from(message))
+                .unwrap()
+        }
+        Ok(WebhookOutcome::Skipped(reason)) => {
+            metrics::WEBHOOK_REQUESTS.with_label_values(&["ignored"]).inc();
+            Response::builder()
+                .status(StatusCode::ACCEPTED)
+                .body(Body::from(format!("ignored request: {}", reason)))
+                .unwrap()
+        }
+        Err(err) => {
+            metrics::WEBHOOK_REQUESTS.with_label_values(&["failed"]).inc();
+            log::error!("request failed: {:?}", err);
+            Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from(format!("request failed: {:?}", err)))
+                .unwrap()
+        }
+    }
+}
+
+fn is_admin(headers: &header::HeaderMap) -> bool {
+    let token = match env::var("ADMIN_TOKEN") {
+        Ok(token) if !token.is_empty() => token,
+        _ => return false,
+    };
+
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|given| bool::from(given.as_bytes().ct_eq(token.as_bytes())))
+}
 
 async fn serve_req(
     req: Request<Body>,
     ctx: Arc<Context>,
-    sender: mpsc::Sender<PullRequestEvent>,
+    sender: mpsc::UnboundedSender<ChangeRequest>,
 ) -> Result<Response<Body>, hyper::Error> {
     log::info!("request = {:?}", req);
     let (req, body_stream) = req.into_parts();
 
     match (req.method, req.uri.path()) {
+        (Method::GET, "/healthz") => Ok(Response::new(Body::from("ok"))),
+        (Method::GET, "/readyz") => Ok(readiness(&ctx).await),
+        (Method::GET, "/version") => Ok(version()),
+        (Method::GET, "/metrics") => Ok(render_metrics()),
         (Method::POST, "/github-hook") => {
-            let event = if let Some(ev) = req.headers.get("X-GitHub-Event") {
+            let (event, event_name) = if let Some(ev) = req.headers.get("X-GitHub-Event") {
                 let ev = match ev.to_str().ok() {
                     Some(v) => v,
                     None => {
@@ -35,7 +180,7 @@ async fn serve_req(
                 };
 
                 match ev.parse::<webhook::EventName>() {
-                    Ok(v) => v,
+                    Ok(v) => (v, ev.to_string()),
                     Err(_) => unreachable!(),
                 }
             } else {
@@ -46,6 +191,7 @@ async fn serve_req(
             };
 
             log::debug!("event={}", event);
+            metrics::WEBHOOKS_RECEIVED.with_label_values(&[&event.metric_label()]).inc();
 
             let mut c = body_stream;
             let mut payload = Vec::new();
@@ -66,22 +212,144 @@ async fn serve_req(
 
             // TODO: check signature
 
-            match webhook::webhook(event, payload, &ctx, sender).await {
-                Ok(true) => Ok(Response::new(Body::from("processed request"))),
-                Ok(false) => Ok(Response::new(Body::from("ignored request"))),
-                Err(err) => {
-                    log::error!("request failed: {:?}", err);
-                    Ok(Response::builder()
-                        .status(StatusCode::INTERNAL_SERVER_ERROR)
-                        .body(Body::from(format!("request failed: {:?}", err)))
-                        .unwrap())
+            let delivery_id = req.headers.get("X-GitHub-Delivery").and_then(|v| v.to_str().ok());
+            if let Some(id) = delivery_id {
+                let delivery = deliveries::Delivery {
+                    id: id.to_string(),
+                    event: event_name,
+                    headers: req
+                        .headers
+                        .iter()
+                        .filter_map(|(k, v)| Some((k.to_string(), v.to_str().ok()?.to_string())))
+                        .collect(),
+                    payload: payload.clone(),
+                    received_at: Utc::now(),
+                };
+
+                match deliveries::claim(&ctx.db.lock().unwrap(), &delivery) {
+                    Ok(deliveries::Claim::Claimed) => {}
+                    Ok(claim) => {
+                        let state = match claim {
+                            deliveries::Claim::InFlight => "is being processed",
+                            _ => "was already processed",
+                        };
+                        log::info!("delivery {} {}, skipping", id, state);
+                        metrics::WEBHOOK_REQUESTS.with_label_values(&["duplicate"]).inc();
+                        return Ok(Response::new(Body::from(format!("delivery {} {}", id, state))));
+                    }
+                    Err(err) => {
+                        log::error!("failed to record delivery {}: {:?}", id, err);
+                    }
+                }
+            }
+
+            let outcome = webhook::webhook(event, payload, &ctx, sender).await;
+            // Failed deliveries are released, for GitHub to redeliver.
+            if let Some(id) = delivery_id {
+                let conn = ctx.db.lock().unwrap();
+                let result = match &outcome {
+                    Ok(_) => deliveries::mark_processed(&conn, id),
+                    Err(_) => deliveries::release(&conn, id),
+                };
+                if let Err(err) = result {
+                    log::error!("failed to update the state of delivery {}: {:?}", id, err);
+                }
+            }
+            Ok(outcome_response(outcome))
+        },
+        (Method::POST, "/gitlab-hook") => {
+            let token = req.headers.get("X-Gitlab-Token").and_then(|v| v.to_str().ok());
+            if !gitlab::verify_token(token) {
+                return Ok(Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .body(Body::from("X-Gitlab-Token does not match GITLAB_WEBHOOK_TOKEN"))
+                    .unwrap());
+            }
+
+            let event = match req.headers.get("X-Gitlab-Event").and_then(|v| v.to_str().ok()) {
+                Some(event) => event.to_string(),
+                None => {
+                    return Ok(Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body(Body::from("X-Gitlab-Event header must be set"))
+                        .unwrap());
+                }
+            };
+            metrics::WEBHOOKS_RECEIVED.with_label_values(&[&event]).inc();
+
+            let payload = hyper::body::to_bytes(body_stream).await?;
+            let payload = match String::from_utf8(payload.to_vec()) {
+                Ok(p) => p,
+                Err(_) => {
+                    return Ok(Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body(Body::from("Payload must be UTF-8"))
+                        .unwrap());
+                }
+            };
+
+            Ok(outcome_response(webhook::forge_webhook(ForgeKind::Gitlab, &event, payload, &ctx, sender).await))
+        },
+        (Method::POST, "/gitea-hook") => {
+            let event = match req.headers.get("X-Gitea-Event").and_then(|v| v.to_str().ok()) {
+                Some(event) => event.to_string(),
+                None => {
+                    return Ok(Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body(Body::from("X-Gitea-Event header must be set"))
+                        .unwrap());
+                }
+            };
+
+            let payload = hyper::body::to_bytes(body_stream).await?;
+            let signature = req.headers.get("X-Gitea-Signature").and_then(|v| v.to_str().ok());
+            if !gitea::verify_signature(signature, &payload) {
+                return Ok(Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .body(Body::from("X-Gitea-Signature does not match GITEA_WEBHOOK_SECRET"))
+                    .unwrap());
+            }
+            metrics::WEBHOOKS_RECEIVED.with_label_values(&[&event]).inc();
+
+            let payload = match String::from_utf8(payload.to_vec()) {
+                Ok(p) => p,
+                Err(_) => {
+                    return Ok(Response::builder()
+                        .status(StatusCode::BAD_REQUEST)
+                        .body(Body::from("Payload must be UTF-8"))
+                        .unwrap());
                 }
+            };
+
+            Ok(outcome_response(webhook::forge_webhook(ForgeKind::Gitea, &event, payload, &ctx, sender).await))
+        },
+        (Method::POST, path) if path.starts_with("/admin/deliveries/") => {
+            if !is_admin(&req.headers) {
+                return Ok(Response::builder()
+                    .status(StatusCode::UNAUTHORIZED)
+                    .body(Body::empty())
+                    .unwrap());
             }
+
+            let id = match path
+                .trim_start_matches("/admin/deliveries/")
+                .strip_suffix("/replay")
+            {
+                Some(id) if !id.is_empty() && !id.contains('/') => id,
+                _ => {
+                    return Ok(Response::builder()
+                        .status(StatusCode::NOT_FOUND)
+                        .body(Body::empty())
+                        .unwrap());
+                }
+            };
+
+            Ok(outcome_response(webhook::replay(id, &ctx, sender).await))
         },
         _ => {
              Ok(Response::builder()
                  .status(StatusCode::NOT_FOUND)
-                 .header(header::ALLOW, "POST")
+                 .header(header::ALLOW, "GET, POST")
                  .body(Body::empty())
                  .unwrap())
         }
@@ -91,7 +359,8 @@ async fn serve_req(
 async fn run_server(
     ctx: Arc<Context>,
     addr: SocketAddr,
-    sender: mpsc::Sender<PullRequestEvent>,
+    sender: mpsc::UnboundedSender<ChangeRequest>,
+    shutdown: watch::Receiver<bool>,
 ) -> anyhow::Result<()> {
     log::info!("Listening on http://{}", addr);
     let svc = hyper::service::make_service_fn(move |_conn| {
@@ -115,43 +384,223 @@ async fn run_server(
         }
     });
 
-    let serve_future = Server::bind(&addr).serve(svc);
+    // Stops accepting connections on shutdown, but lets the requests being
+    // served finish.
+    let serve_future = Server::bind(&addr)
+        .serve(svc)
+        .with_graceful_shutdown(shutdown_requested(shutdown));
 
     serve_future.await?;
     Ok(())
 }
 
+const USAGE: &str = "usage:
+    docsbot                                   run the webhook server
+    docsbot replay-delivery <delivery-id>     replay a stored webhook delivery
+    docsbot replay --event <name> [--github-url <url>] [--git-host <url>] <payload.json>
+                                              run a saved payload through the handlers";
+
+/// Sets up what the handlers need. With `github_url`, the GitHub API is
+/// looked for there instead, and without a token, as a fake API has no use
+/// for one.
+fn make_context(github_url: Option<&str>) -> anyhow::Result<Context> {
+    let client = github::http_client_from_env().context("building HTTP client")?;
+    let gh = match github_url {
+        Some(url) => {
+            let token = github::default_token_from_env().unwrap_or_else(|_| "unused".to_string());
+            github::GithubClient::new(client.clone(), token).with_api_url(url)
+        }
+        None => github::GithubClient::new_with_default_token(client.clone())?.with_api_url(&github::api_url_from_env()),
+    }
+    .with_git_url(&github::git_url_from_env());
+
+    if let Ok(path) = env::var("GITHUB_CA_CERT") {
+        git::add_ca_certificates(path.as_ref()).context("loading GITHUB_CA_CERT")?;
+    }
+
+    let conn = db::make_db_conn().context("opening database")?;
+    db::run_migrations(&conn).context("migrating database")?;
+
+    Ok(Context {
+        github: gh,
+        db: std::sync::Mutex::new(conn),
+        username: String::from("docsbot"),
+        gitlab: gitlab::GitlabClient::from_env(client.clone()),
+        gitea: gitea::GiteaClient::from_env(client),
+        workdir: env::current_dir()?,
+        retry: RetryPolicy::from_env(),
+        background: Default::default(),
+    })
+}
+
+/// Options for commands that only process a few requests and exit, which
+/// leave cleaning up to the server.
+fn one_off_options() -> WorkerOptions {
+    WorkerOptions {
+        janitor_interval: None,
+        ..WorkerOptions::from_env()
+    }
+}
+
+/// A shutdown signal for one-off commands, which never fires.
+fn no_shutdown() -> watch::Receiver<bool> {
+    watch::channel(false).1
+}
+
+/// Resolves on SIGTERM or Ctrl-C.
+async fn termination_signal() {
+    let mut sigterm = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+        .expect("failed to install SIGTERM handler");
+    tokio::select! {
+        _ = sigterm.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
+
+/// `docsbot replay-delivery <id>`: runs a stored delivery through the
+/// webhook handler and processes the resulting jobs, then exits.
+async fn replay_delivery(id: &str) -> anyhow::Result<()> {
+    let ctx = Arc::new(make_context(None)?);
+    let (tx, rx) = mpsc::unbounded_channel();
+
+    let outcome = webhook::replay(id, &ctx, tx).await.map_err(|e| e.0)?;
+    log::info!("delivery {} replayed: {:?}", id, outcome);
+
+    handle_pr_task(ctx.clone(), rx, one_off_options(), no_shutdown()).await?;
+    ctx.background.wait().await;
+
+    Ok(())
+}
+
+/// `docsbot replay --event <name> <payload.json>`: runs a saved webhook
+/// payload through the handlers without starting the HTTP server.
+///
+/// `--github-url` and `--git-host` point the bot at a fake GitHub API and
+/// a local git remote (`<git-host>/<owner>/<repo>`) so that a sync can be
+/// reproduced offline.
+async fn replay_file(args: &[String]) -> anyhow::Result<()> {
+    let mut event = None;
+    let mut github_url = None;
+    let mut git_host = None;
+    let mut payload_path = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--event" => event = args.next(),
+            "--github-url" => github_url = args.next(),
+            "--git-host" => git_host = args.next(),
+            _ if payload_path.is_none() && !arg.starts_with("--") => payload_path = Some(arg),
+            _ => anyhow::bail!("unexpected argument `{}`\n{}", arg, USAGE),
+        }
+    }
+
+    let event = event.ok_or_else(|| anyhow::anyhow!("--event is required\n{}", USAGE))?;
+    let payload_path =
+        payload_path.ok_or_else(|| anyhow::anyhow!("a payload file is required\n{}", USAGE))?;
+    let payload = std::fs::read_to_string(payload_path)
+        .with_context(|| format!("reading {}", payload_path))?;
+
+    let mut ctx = make_context(github_url.map(String::as_str))?;
+    if let Some(host) = git_host {
+        ctx.github = ctx.github.with_git_url(host);
+    }
+    let ctx = Arc::new(ctx);
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let event = event
+        .parse::<webhook::EventName>()
+        .unwrap_or_else(|never| match never {});
+
+    let outcome = webhook::webhook(event, payload, &ctx, tx).await.map_err(|e| e.0)?;
+    log::info!("payload {} replayed: {:?}", payload_path, outcome);
+
+    handle_pr_task(ctx.clone(), rx, one_off_options(), no_shutdown()).await?;
+    ctx.background.wait().await;
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() {
     dotenv::dotenv().ok();
     logger::init();
 
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        None => {}
+        Some("replay-delivery") if args.len() == 3 => {
+            if let Err(e) = replay_delivery(&args[2]).await {
+                eprintln!("Failed to replay delivery: {:?}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some("replay") => {
+            if let Err(e) = replay_file(&args[2..]).await {
+                eprintln!("Failed to replay payload: {:?}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some(_) => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    }
+
     let port = env::var("PORT")
         .ok()
         .map(|p| p.parse::<u16>().expect("parsed PORT"))
         .unwrap_or(8000);
 
-    let (tx, rx): (mpsc::Sender<PullRequestEvent>, mpsc::Receiver<PullRequestEvent>) = mpsc::channel();
+    let (tx, rx): (mpsc::UnboundedSender<ChangeRequest>, mpsc::UnboundedReceiver<ChangeRequest>) = mpsc::unbounded_channel();
 
-    let client = Client::new();
-    let gh = github::GithubClient::new_with_default_token(client.clone());
-
-    let ctx = Arc::new(Context{
-        github: gh,
-        // db_conn: conn.unwrap(),
-        username: String::from("docsbot"),
-    });
+    let ctx = match make_context(None) {
+        Ok(ctx) => Arc::new(ctx),
+        Err(e) => {
+            eprintln!("Failed to set up: {:?}", e);
+            std::process::exit(1);
+        }
+    };
 
     let addr:SocketAddr = ([0, 0, 0, 0], port).into();
 
+    // Deliveries the last run stopped halfway through are handled again
+    // when GitHub redelivers them.
+    if let Err(err) = deliveries::release_all(&ctx.db.lock().unwrap()) {
+        log::error!("failed to release the deliveries of the last run: {:?}", err);
+    }
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    tokio::spawn(async move {
+        termination_signal().await;
+        log::info!("shutting down, no longer accepting webhooks");
+        let _ = shutdown_tx.send(true);
+    });
+
     // log::info!("server addr: {}", addr);
     let ctx_ = ctx.clone();
+    let server_shutdown = shutdown_rx.clone();
     tokio::spawn(async move {
-        if let Err(e) = run_server(ctx_, addr, tx).await{
+        if let Err(e) = run_server(ctx_, addr, tx, server_shutdown).await{
             eprintln!("Failed to run server: {:?}", e)
         }
     });
 
-    handle_pr_task(ctx.clone(), rx).await;
-}
+    let options = WorkerOptions::from_env();
+    let shutdown_timeout = options.shutdown_timeout;
+    if let Err(e) = handle_pr_task(ctx.clone(), rx, options, shutdown_rx).await {
+        eprintln!("Failed to process sync jobs: {:?}", e);
+        std::process::exit(1);
+    }
+    // The translations the last webhooks left stale would go unrecorded.
+    if tokio::time::timeout(shutdown_timeout, ctx.background.wait()).await.is_err() {
+        log::warn!("gave up on what the last webhooks left running");
+    }
 
+    // Git work abandoned at the shutdown timeout may still be running on
+    // blocking threads, which the runtime would otherwise wait for.
+    log::info!("shutdown complete");
+    std::process::exit(0);
+}
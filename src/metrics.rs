@@ -0,0 +1,63 @@
+use prometheus::{
+    register_histogram_vec, register_int_counter_vec, register_int_gauge, Encoder, HistogramVec,
+    IntCounterVec, IntGauge, TextEncoder,
+};
+
+lazy_static::lazy_static! {
+    pub static ref WEBHOOKS_RECEIVED: IntCounterVec = register_int_counter_vec!(
+        "docsbot_webhooks_received_total",
+        "Webhooks received, by forge event name",
+        &["event"]
+    ).unwrap();
+
+    pub static ref WEBHOOK_REQUESTS: IntCounterVec = register_int_counter_vec!(
+        "docsbot_webhook_requests_total",
+        "Webhook requests by outcome (processed, ping, ignored, duplicate, failed)",
+        &["outcome"]
+    ).unwrap();
+
+    pub static ref SYNC_JOBS: IntCounterVec = register_int_counter_vec!(
+        "docsbot_sync_jobs_total",
+        "Sync jobs by repository, label and outcome",
+        &["repo", "label", "outcome"]
+    ).unwrap();
+
+    pub static ref SYNC_STEP_DURATION: HistogramVec = register_histogram_vec!(
+        "docsbot_sync_step_duration_seconds",
+        "Duration of the steps of a sync job (clone, apply, push, create_pr)",
+        &["step"],
+        vec![0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0]
+    ).unwrap();
+
+    pub static ref GITHUB_API_CALLS: IntCounterVec = register_int_counter_vec!(
+        "docsbot_github_api_calls_total",
+        "GitHub API calls by response status code",
+        &["status"]
+    ).unwrap();
+
+    pub static ref GITLAB_API_CALLS: IntCounterVec = register_int_counter_vec!(
+        "docsbot_gitlab_api_calls_total",
+        "GitLab API calls by response status code",
+        &["status"]
+    ).unwrap();
+
+    pub static ref GITEA_API_CALLS: IntCounterVec = register_int_counter_vec!(
+        "docsbot_gitea_api_calls_total",
+        "Gitea API calls by response status code",
+        &["status"]
+    ).unwrap();
+
+    pub static ref GITHUB_RATE_LIMIT_REMAINING: IntGauge = register_int_gauge!(
+        "docsbot_github_rate_limit_remaining",
+        "Remaining GitHub API rate limit budget, as last reported by GitHub"
+    ).unwrap();
+}
+
+/// Renders all registered metrics in the Prometheus text exposition format.
+pub fn gather() -> anyhow::Result<(String, String)> {
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    encoder.encode(&prometheus::gather(), &mut buffer)?;
+
+    Ok((encoder.format_type().to_string(), String::from_utf8(buffer)?))
+}
@@ -1,14 +1,17 @@
 #![allow(clippy::new_without_default)]
 
 use std::fmt;
-use std::sync::mpsc;
+use std::sync::Arc;
+use tokio::sync::mpsc;
 use crate::handlers;
 use crate::github;
+use crate::db::deliveries;
 use anyhow::Context;
-use crate::github::PullRequestEvent;
+use crate::forge::{ChangeRequest, ForgeKind};
 
 #[derive(Debug)]
 pub enum EventName {
+    CheckSuite,
     PullRequest,
     PullRequestReview,
     PullRequestReviewComment,
@@ -16,7 +19,9 @@ pub enum EventName {
     Issue,
     Push,
     Create,
-    Other,
+    Ping,
+    Status,
+    Other(String),
 }
 
 impl std::str::FromStr for EventName {
@@ -30,11 +35,26 @@ impl std::str::FromStr for EventName {
             "issues" => EventName::Issue,
             "push" => EventName::Push,
             "create" => EventName::Create,
-            _ => EventName::Other,
+            "ping" => EventName::Ping,
+            "check_suite" => EventName::CheckSuite,
+            "status" => EventName::Status,
+            other => EventName::Other(other.to_string()),
         })
     }
 }
 
+impl EventName {
+    /// The value of the `event` label of the webhook metrics. Events docsbot
+    /// does not know are all counted as `other`, or every name sent in the
+    /// header would add a series.
+    pub fn metric_label(&self) -> String {
+        match self {
+            EventName::Other(_) => "other".to_string(),
+            known => known.to_string(),
+        }
+    }
+}
+
 impl fmt::Display for EventName {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -48,14 +68,27 @@ impl fmt::Display for EventName {
                 EventName::PullRequest => "pull_request",
                 EventName::Push => "push",
                 EventName::Create => "create",
-                EventName::Other => "other",
+                EventName::Ping => "ping",
+                EventName::CheckSuite => "check_suite",
+                EventName::Status => "status",
+                EventName::Other(name) => name,
             }
         )
     }
 }
 
+/// What came of a webhook delivery.
 #[derive(Debug)]
-pub struct WebhookError(anyhow::Error);
+pub enum WebhookOutcome {
+    Processed,
+    /// The delivery was a `ping`; `valid` tells whether the hook is set up the way docsbot needs.
+    Pong { valid: bool, message: String },
+    /// The delivery was accepted but not acted upon, with the reason why.
+    Skipped(String),
+}
+
+#[derive(Debug)]
+pub struct WebhookError(pub anyhow::Error);
 
 impl From<anyhow::Error> for WebhookError {
     fn from(e: anyhow::Error) -> WebhookError {
@@ -64,7 +97,7 @@ impl From<anyhow::Error> for WebhookError {
 }
 
 pub fn deserialize_payload<T: serde::de::DeserializeOwned>(v: &str) -> anyhow::Result<T> {
-    let mut deserializer = serde_json::de::Deserializer::from_str(&v);
+    let mut deserializer = serde_json::de::Deserializer::from_str(v);
     let res: Result<T, _> = serde_path_to_error::deserialize(&mut deserializer);
 
     match res {
@@ -79,24 +112,82 @@ pub fn deserialize_payload<T: serde::de::DeserializeOwned>(v: &str) -> anyhow::R
 pub async fn webhook(
     event: EventName,
     payload: String,
-    ctx: &handlers::Context,
-    sender: mpsc::Sender<PullRequestEvent>,
-) -> Result<bool, WebhookError> {
+    ctx: &Arc<handlers::Context>,
+    sender: mpsc::UnboundedSender<ChangeRequest>,
+) -> Result<WebhookOutcome, WebhookError> {
     let event = match event {
+        EventName::Ping => {
+            let payload = deserialize_payload::<github::PingEvent>(&payload)
+                .with_context(|| format!("{:?} failed to deserialize", event))?;
+
+            return Ok(match handlers::ping::handle(&payload).await {
+                Ok(message) => WebhookOutcome::Pong { valid: true, message },
+                Err(message) => WebhookOutcome::Pong { valid: false, message },
+            });
+        }
         EventName::PullRequest => {
             // log::info!("payload={:?}", &payload);
             let payload = deserialize_payload::<github::PullRequestEvent>(&payload)
-                .with_context(|| format!("{:?} failed to deserialize", event))
-                .map_err(anyhow::Error::from)?;
+                .with_context(|| format!("{:?} failed to deserialize", event))?;
 
             github::Event::PullRequest(payload)
         }
+        EventName::CheckSuite => {
+            let payload = deserialize_payload::<github::CheckSuiteEvent>(&payload)
+                .with_context(|| format!("{:?} failed to deserialize", event))?;
+
+            github::Event::CheckSuite(payload)
+        }
+        EventName::Status => {
+            let payload = deserialize_payload::<github::StatusEvent>(&payload)
+                .with_context(|| format!("{:?} failed to deserialize", event))?;
+
+            github::Event::Status(payload)
+        }
         _ => {
-            return Ok(false);
+            return Ok(WebhookOutcome::Skipped(format!(
+                "event `{}` is not handled by docsbot",
+                event
+            )));
         }
     };
 
-    let errors = handlers::handle(&ctx, &event, sender).await;
+    let errors = handlers::handle(ctx, &event, sender).await;
+    outcome_from_errors(errors)
+}
+
+/// Handles a webhook from a forge other than GitHub: GitLab, identified by
+/// its `X-Gitlab-Event` header, or Gitea, by `X-Gitea-Event`.
+pub async fn forge_webhook(
+    kind: ForgeKind,
+    event: &str,
+    payload: String,
+    ctx: &handlers::Context,
+    sender: mpsc::UnboundedSender<ChangeRequest>,
+) -> Result<WebhookOutcome, WebhookError> {
+    let forge = ctx
+        .forge(kind)
+        .with_context(|| format!("received a {} webhook", kind))?;
+
+    let request = match forge
+        .parse_merge_event(event, &payload)
+        .with_context(|| format!("{} failed to deserialize", event))?
+    {
+        Some(request) => request,
+        None => {
+            return Ok(WebhookOutcome::Skipped(format!(
+                "`{}` is not a merge of a change request",
+                event
+            )));
+        }
+    };
+
+    outcome_from_errors(handlers::handle_merged(request, sender).await)
+}
+
+fn outcome_from_errors(
+    errors: Vec<handlers::HandlerError>,
+) -> Result<WebhookOutcome, WebhookError> {
     let mut other_error = false;
     let mut message = String::new();
 
@@ -124,6 +215,42 @@ pub async fn webhook(
             "handling failed, error logged",
         )))
     } else {
-        Ok(true)
+        Ok(WebhookOutcome::Processed)
+    }
+}
+
+/// Runs a stored delivery through [`webhook`] again.
+pub async fn replay(
+    delivery_id: &str,
+    ctx: &Arc<handlers::Context>,
+    sender: mpsc::UnboundedSender<ChangeRequest>,
+) -> Result<WebhookOutcome, WebhookError> {
+    let delivery = deliveries::get(&ctx.db.lock().unwrap(), delivery_id)?
+        .ok_or_else(|| anyhow::anyhow!("delivery {} not found", delivery_id))?;
+
+    log::info!(
+        "replaying delivery {} ({}) received at {}",
+        delivery.id,
+        delivery.event,
+        delivery.received_at
+    );
+
+    let event = delivery
+        .event
+        .parse::<EventName>()
+        .unwrap_or_else(|never| match never {});
+
+    webhook(event, delivery.payload, ctx, sender).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EventName;
+
+    #[test]
+    fn unknown_events_share_a_metric_label() {
+        assert_eq!("pull_request".parse::<EventName>().unwrap().metric_label(), "pull_request");
+        assert_eq!("deployment".parse::<EventName>().unwrap().metric_label(), "other");
+        assert_eq!("x".repeat(64).parse::<EventName>().unwrap().metric_label(), "other");
     }
 }
@@ -0,0 +1,95 @@
+mod support;
+
+use docsbot::webhook::{self, EventName};
+use serde_json::json;
+use tokio::sync::mpsc;
+use support::{changed_file, pull_request_payload, Harness, TestConfig, BASE_BRANCH, REPO};
+
+/// Synced through pull requests merged once their checks pass
+const AUTO_MERGE_LABEL: &str = "docs/auto-version-2.0.0";
+const MERGE_SHA: &str = "9090909090909090909090909090909090909090";
+
+fn config() -> String {
+    TestConfig::new().label(AUTO_MERGE_LABEL, "2.0.0", "auto_merge = \"squash\"").build()
+}
+
+/// Syncs pull request `number` and returns the number of its sync pull
+/// request.
+async fn sync(h: &Harness, number: u64) -> u64 {
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "# Intro\n"),
+            ("versioned_docs/version-2.0.0/intro.md", "# Old intro\n"),
+        ],
+    );
+    h.github
+        .set_pull_request_files(REPO, number, vec![changed_file("docs/intro.md", "@@ -1 +1 @@")]);
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let payload = pull_request_payload("closed", number, true, MERGE_SHA, &[AUTO_MERGE_LABEL]);
+    webhook::webhook(EventName::PullRequest, payload, &h.ctx, tx)
+        .await
+        .unwrap();
+    h.process(rx).await;
+
+    let pulls = h.github.pull_requests(REPO);
+    assert_eq!(pulls.len(), 1);
+    pulls[0]["number"].as_u64().unwrap()
+}
+
+async fn deliver(h: &Harness, event: EventName, payload: serde_json::Value) {
+    let (tx, _rx) = mpsc::unbounded_channel();
+    webhook::webhook(event, payload.to_string(), &h.ctx, tx)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn auto_merge_is_enabled_on_sync_prs() {
+    let h = Harness::new(&config()).await;
+    sync(&h, 90).await;
+
+    let pulls = h.github.pull_requests(REPO);
+    assert_eq!(pulls[0]["auto_merge"]["merge_method"], "SQUASH");
+    assert_eq!(pulls[0]["state"], "open");
+}
+
+#[tokio::test]
+async fn sync_prs_are_merged_by_docsbot_once_checks_pass() {
+    let h = Harness::new(&config()).await;
+    h.github.disable_auto_merge();
+    let number = sync(&h, 91).await;
+    assert_eq!(h.github.pull_requests(REPO)[0]["state"], "open");
+
+    let check_suite = |conclusion: &str| {
+        json!({
+            "action": "completed",
+            "check_suite": { "head_sha": MERGE_SHA, "conclusion": conclusion },
+            "repository": { "full_name": REPO },
+        })
+    };
+    deliver(&h, EventName::CheckSuite, check_suite("failure")).await;
+    // Required checks are still running.
+    deliver(&h, EventName::CheckSuite, check_suite("success")).await;
+    assert_eq!(h.github.pull_requests(REPO)[0]["state"], "open");
+
+    h.github.set_mergeable_state(REPO, number, "clean");
+    let status = json!({ "sha": MERGE_SHA, "state": "success", "repository": { "full_name": REPO } });
+    deliver(&h, EventName::Status, status.clone()).await;
+
+    let pulls = h.github.pull_requests(REPO);
+    assert_eq!(pulls[0]["merged"], true);
+    assert_eq!(pulls[0]["merge_method"], "squash");
+
+    // Merged pull requests are not looked at again.
+    deliver(&h, EventName::Status, status).await;
+    let merges = h
+        .github
+        .calls()
+        .iter()
+        .filter(|c| c.path.ends_with("/merge"))
+        .count();
+    assert_eq!(merges, 1);
+}
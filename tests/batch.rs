@@ -0,0 +1,129 @@
+mod support;
+
+use git2::BranchType;
+use support::{read_file, Harness, TestConfig, BASE_BRANCH, REPO};
+
+const BATCH_LABEL: &str = "docs/batch-version-2.0.3";
+const BATCH_BRANCH: &str = "docsbot/sync-docs-batch-version-2.0.3";
+
+fn config() -> String {
+    TestConfig::new().label(BATCH_LABEL, "2.0.3", "batch = true").build()
+}
+
+/// Subjects of the commits on `branch` that are not on the base branch,
+/// oldest first.
+fn batch_commits(h: &Harness, branch: &str) -> Vec<String> {
+    let remote = h.remote(REPO);
+    let tip = |name| remote.find_branch(name, BranchType::Local).unwrap().get().target().unwrap();
+
+    let mut walk = remote.revwalk().unwrap();
+    walk.push(tip(branch)).unwrap();
+    walk.hide(tip(BASE_BRANCH)).unwrap();
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE).unwrap();
+    walk.map(|oid| {
+        let commit = remote.find_commit(oid.unwrap()).unwrap();
+        commit.summary().unwrap().to_string()
+    })
+    .collect()
+}
+
+fn remote_files() -> Vec<(&'static str, &'static str)> {
+    vec![
+        ("docs/intro.md", "# Intro\n"),
+        ("docs/usage.md", "# Usage\n"),
+        ("versioned_docs/version-2.0.3/intro.md", "# Old intro\n"),
+        ("versioned_docs/version-2.0.3/usage.md", "# Old usage\n"),
+    ]
+}
+
+#[tokio::test]
+async fn merges_are_collected_in_one_pull_request() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(REPO, BASE_BRANCH, &remote_files());
+
+    h.merge(60, &[BATCH_LABEL], &["docs/intro.md"]).await;
+    h.merge(61, &[BATCH_LABEL], &["docs/usage.md"]).await;
+
+    let pulls = h.github.pull_requests(REPO);
+    assert_eq!(pulls.len(), 1);
+    assert_eq!(pulls[0]["head"]["ref"], BATCH_BRANCH);
+    let body = pulls[0]["body"].as_str().unwrap();
+    let items: Vec<_> = body.lines().filter(|l| l.starts_with("- [ ]")).collect();
+    assert_eq!(items.len(), 2, "{}", body);
+    assert!(items[0].contains(&format!("{}/pull/60", REPO)), "{}", body);
+    assert!(items[1].contains(&format!("{}/pull/61", REPO)), "{}", body);
+
+    assert_eq!(
+        batch_commits(&h, BATCH_BRANCH),
+        vec![
+            format!("sync {}#60 to {}", REPO, BATCH_LABEL),
+            format!("sync {}#61 to {}", REPO, BATCH_LABEL),
+        ]
+    );
+    let remote = h.remote(REPO);
+    assert_eq!(
+        read_file(&remote, BATCH_BRANCH, "versioned_docs/version-2.0.3/intro.md").as_deref(),
+        Some("# Intro\n"),
+    );
+    assert_eq!(
+        read_file(&remote, BATCH_BRANCH, "versioned_docs/version-2.0.3/usage.md").as_deref(),
+        Some("# Usage\n"),
+    );
+}
+
+#[tokio::test]
+async fn the_batch_branch_follows_the_base_branch() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(REPO, BASE_BRANCH, &remote_files());
+
+    h.merge(62, &[BATCH_LABEL], &["docs/intro.md"]).await;
+    h.commit_to_branch(REPO, BASE_BRANCH, &[("README.md", "# Website\n")]);
+    h.merge(63, &[BATCH_LABEL], &["docs/usage.md"]).await;
+
+    // Only the synced commits are on top of the moved base branch.
+    assert_eq!(batch_commits(&h, BATCH_BRANCH).len(), 2);
+    let remote = h.remote(REPO);
+    assert_eq!(
+        read_file(&remote, BATCH_BRANCH, "README.md").as_deref(),
+        Some("# Website\n"),
+    );
+    assert_eq!(h.github.pull_requests(REPO).len(), 1);
+}
+
+#[tokio::test]
+async fn syncing_the_same_merge_twice_adds_it_once() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(REPO, BASE_BRANCH, &remote_files());
+
+    h.merge(64, &[BATCH_LABEL], &["docs/intro.md"]).await;
+    h.merge(64, &[BATCH_LABEL], &["docs/intro.md"]).await;
+
+    assert_eq!(batch_commits(&h, BATCH_BRANCH).len(), 1);
+    let pulls = h.github.pull_requests(REPO);
+    assert_eq!(pulls.len(), 1);
+    let body = pulls[0]["body"].as_str().unwrap();
+    assert_eq!(body.lines().filter(|l| l.starts_with("- [")).count(), 1, "{}", body);
+}
+
+#[tokio::test]
+async fn conflicts_with_the_base_branch_are_reported_once_on_the_batch_pull_request() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(REPO, BASE_BRANCH, &remote_files());
+
+    h.merge(65, &[BATCH_LABEL], &["docs/intro.md"]).await;
+    // Someone changed the synced file on the base branch in the meantime.
+    h.commit_to_branch(REPO, BASE_BRANCH, &[("versioned_docs/version-2.0.3/intro.md", "# Edited intro\n")]);
+    h.merge(66, &[BATCH_LABEL], &["docs/usage.md"]).await;
+    h.merge(67, &[BATCH_LABEL], &["docs/usage.md"]).await;
+
+    let pulls = h.github.pull_requests(REPO);
+    assert_eq!(pulls.len(), 1);
+    let batch = pulls[0]["number"].as_u64().unwrap();
+    let comments = h.github.comments(REPO, batch);
+    assert_eq!(comments.len(), 1, "{:?}", comments);
+    let body = comments[0]["body"].as_str().unwrap();
+    assert!(body.contains("could not be rebased onto `main`"), "{}", body);
+    assert!(h.github.comments(REPO, 66).is_empty());
+    assert!(h.github.comments(REPO, 67).is_empty());
+    assert_eq!(batch_commits(&h, BATCH_BRANCH).len(), 1);
+}
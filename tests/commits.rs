@@ -0,0 +1,171 @@
+mod support;
+
+use docsbot::webhook::{self, EventName};
+use git2::{BranchType, Signature, Time};
+use tokio::sync::mpsc;
+use support::{
+    changed_file, pull_request_commit, pull_request_payload, read_file, Harness, TestConfig, BASE_BRANCH, REPO,
+};
+
+const HISTORY_LABEL: &str = "docs/history-version-2.0.2";
+const MERGE_SHA: &str = "7070707070707070707070707070707070707070";
+const SYNC_BRANCH: &str = "docsbot/70-docs-history-version-2.0.2";
+
+fn config() -> String {
+    TestConfig::new().label(HISTORY_LABEL, "2.0.2", "preserve_commits = true").build()
+}
+
+#[tokio::test]
+async fn commits_of_the_pull_request_are_replayed() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "# Intro\n"),
+            ("docs/usage.md", "# Usage\n"),
+            ("versioned_docs/version-2.0.2/intro.md", "# Old intro\n"),
+            ("versioned_docs/version-2.0.2/usage.md", "# Old usage\n"),
+        ],
+    );
+
+    let alice = Signature::new("Alice", "alice@example.com", &Time::new(1_600_000_000, 0)).unwrap();
+    let bob = Signature::new("Bob", "bob@example.com", &Time::new(1_600_100_000, 0)).unwrap();
+    let head = "refs/pull/70/head";
+    let intro = [("docs/intro.md", "# Intro by Alice\n")];
+    let a = h.commit_to_ref(REPO, head, "refs/heads/main", &intro, &alice, "Reword the intro");
+    let readme = [("README.md", "# Website\n")];
+    let b = h.commit_to_ref(REPO, head, head, &readme, &bob, "Add a readme");
+    let usage = [("docs/usage.md", "# Usage by Bob\n")];
+    let c = h.commit_to_ref(REPO, head, head, &usage, &bob, "Document usage");
+    // Squash merged.
+    h.commit_to_branch(REPO, BASE_BRANCH, &[intro[0], readme[0], usage[0]]);
+
+    h.github.set_pull_request_commits(
+        REPO,
+        70,
+        vec![
+            pull_request_commit(c, "Document usage", &bob),
+            pull_request_commit(a, "Reword the intro", &alice),
+            pull_request_commit(b, "Add a readme", &bob),
+        ],
+    );
+    h.github
+        .set_pull_request_files(REPO, 70, vec![changed_file("docs/intro.md", "@@ -1 +1 @@")]);
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let payload = pull_request_payload("closed", 70, true, MERGE_SHA, &[HISTORY_LABEL]);
+    webhook::webhook(EventName::PullRequest, payload, &h.ctx, tx)
+        .await
+        .unwrap();
+    h.process(rx).await;
+
+    let remote = h.remote(REPO);
+    let tip = |name| remote.find_branch(name, BranchType::Local).unwrap().get().target().unwrap();
+    let mut walk = remote.revwalk().unwrap();
+    walk.push(tip(SYNC_BRANCH)).unwrap();
+    walk.hide(tip(BASE_BRANCH)).unwrap();
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE).unwrap();
+    let synced: Vec<_> = walk.map(|oid| remote.find_commit(oid.unwrap()).unwrap()).collect();
+
+    // The commit not touching the docs is left out.
+    assert_eq!(synced.len(), 2);
+    for (commit, (original, summary, author)) in synced
+        .iter()
+        .zip(&[(a, "Reword the intro", &alice), (c, "Document usage", &bob)])
+    {
+        assert_eq!(commit.summary(), Some(*summary));
+        assert_eq!(commit.author().name(), author.name());
+        assert_eq!(commit.author().email(), author.email());
+        assert_eq!(commit.author().when().seconds(), author.when().seconds());
+        let message = commit.message().unwrap();
+        assert!(message.contains(&format!("(cherry picked from commit {})", original)), "{}", message);
+    }
+
+    assert_eq!(
+        read_file(&remote, SYNC_BRANCH, "versioned_docs/version-2.0.2/intro.md").as_deref(),
+        Some("# Intro by Alice\n"),
+    );
+    assert_eq!(
+        read_file(&remote, SYNC_BRANCH, "versioned_docs/version-2.0.2/usage.md").as_deref(),
+        Some("# Usage by Bob\n"),
+    );
+    assert_eq!(h.github.pull_requests(REPO).len(), 1);
+}
+
+#[tokio::test]
+async fn merge_commits_of_the_pull_request_are_left_out() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "# Intro\n"),
+            ("docs/usage.md", "# Usage\n"),
+            ("versioned_docs/version-2.0.2/intro.md", "# Old intro\n"),
+            ("versioned_docs/version-2.0.2/usage.md", "# Old usage\n"),
+        ],
+    );
+
+    let alice = Signature::new("Alice", "alice@example.com", &Time::new(1_600_000_000, 0)).unwrap();
+    let head = "refs/pull/71/head";
+    let intro = [("docs/intro.md", "# Intro by Alice\n")];
+    let a = h.commit_to_ref(REPO, head, "refs/heads/main", &intro, &alice, "Reword the intro");
+    // Someone else's change, merged into the pull request from main.
+    let usage = [("docs/usage.md", "# Usage on main\n")];
+    h.commit_to_branch(REPO, BASE_BRANCH, &usage);
+    let remote = h.remote(REPO);
+    let merge = {
+        let ours = remote.find_commit(a).unwrap();
+        let theirs = remote.find_reference("refs/heads/main").unwrap().peel_to_commit().unwrap();
+        let mut builder = git2::build::TreeUpdateBuilder::new();
+        builder.upsert(usage[0].0, remote.blob(usage[0].1.as_bytes()).unwrap(), git2::FileMode::Blob);
+        let tree = remote.find_tree(builder.create_updated(&remote, &ours.tree().unwrap()).unwrap()).unwrap();
+        let merge = remote
+            .commit(None, &alice, &alice, "Merge branch 'main'", &tree, &[&ours, &theirs])
+            .unwrap();
+        remote.reference(head, merge, true, "merge main").unwrap();
+        merge
+    };
+    let reviewed = [("docs/intro.md", "# Intro by Alice, reviewed\n")];
+    let c = h.commit_to_ref(REPO, head, head, &reviewed, &alice, "Address review");
+    h.commit_to_branch(REPO, BASE_BRANCH, &reviewed);
+
+    h.github.set_pull_request_commits(
+        REPO,
+        71,
+        vec![
+            pull_request_commit(a, "Reword the intro", &alice),
+            pull_request_commit(merge, "Merge branch 'main'", &alice),
+            pull_request_commit(c, "Address review", &alice),
+        ],
+    );
+    h.github
+        .set_pull_request_files(REPO, 71, vec![changed_file("docs/intro.md", "@@ -1 +1 @@")]);
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let payload = pull_request_payload("closed", 71, true, "7171717171717171717171717171717171717171", &[HISTORY_LABEL]);
+    webhook::webhook(EventName::PullRequest, payload, &h.ctx, tx)
+        .await
+        .unwrap();
+    h.process(rx).await;
+
+    let branch = "docsbot/71-docs-history-version-2.0.2";
+    let tip = |name| remote.find_branch(name, BranchType::Local).unwrap().get().target().unwrap();
+    let mut walk = remote.revwalk().unwrap();
+    walk.push(tip(branch)).unwrap();
+    walk.hide(tip(BASE_BRANCH)).unwrap();
+    let summaries: Vec<_> = walk
+        .map(|oid| remote.find_commit(oid.unwrap()).unwrap().summary().unwrap().to_string())
+        .collect();
+    assert_eq!(summaries, ["Address review", "Reword the intro"]);
+
+    assert_eq!(
+        read_file(&remote, branch, "versioned_docs/version-2.0.2/intro.md").as_deref(),
+        Some("# Intro by Alice, reviewed\n"),
+    );
+    assert_eq!(
+        read_file(&remote, branch, "versioned_docs/version-2.0.2/usage.md").as_deref(),
+        Some("# Old usage\n"),
+    );
+}
@@ -0,0 +1,62 @@
+use chrono::{Duration, Utc};
+use docsbot::db::deliveries::{self, Claim};
+use docsbot::db;
+
+fn delivery(id: &str) -> deliveries::Delivery {
+    deliveries::Delivery {
+        id: id.to_string(),
+        event: "pull_request".to_string(),
+        headers: Default::default(),
+        payload: "{}".to_string(),
+        received_at: Utc::now(),
+    }
+}
+
+#[test]
+fn only_processed_deliveries_are_rejected() {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    db::run_migrations(&conn).unwrap();
+
+    assert_eq!(deliveries::claim(&conn, &delivery("a")).unwrap(), Claim::Claimed);
+    // Handling it failed, so GitHub redelivers it.
+    deliveries::release(&conn, "a").unwrap();
+    assert_eq!(deliveries::claim(&conn, &delivery("a")).unwrap(), Claim::Claimed);
+
+    deliveries::mark_processed(&conn, "a").unwrap();
+    assert_eq!(deliveries::claim(&conn, &delivery("a")).unwrap(), Claim::Processed);
+    assert_eq!(deliveries::claim(&conn, &delivery("b")).unwrap(), Claim::Claimed);
+}
+
+#[test]
+fn deliveries_are_claimed_once() {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    db::run_migrations(&conn).unwrap();
+
+    assert_eq!(deliveries::claim(&conn, &delivery("a")).unwrap(), Claim::Claimed);
+    // Redelivered while the first one is being handled.
+    assert_eq!(deliveries::claim(&conn, &delivery("a")).unwrap(), Claim::InFlight);
+
+    // The process handling it stopped.
+    deliveries::release_all(&conn).unwrap();
+    assert_eq!(deliveries::claim(&conn, &delivery("a")).unwrap(), Claim::Claimed);
+}
+
+#[test]
+fn old_deliveries_are_pruned() {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    db::run_migrations(&conn).unwrap();
+
+    let mut old = delivery("old");
+    old.received_at = Utc::now() - Duration::days(40);
+    deliveries::claim(&conn, &old).unwrap();
+    deliveries::mark_processed(&conn, "old").unwrap();
+    let mut in_flight = delivery("in-flight");
+    in_flight.received_at = Utc::now() - Duration::days(40);
+    deliveries::claim(&conn, &in_flight).unwrap();
+    deliveries::claim(&conn, &delivery("new")).unwrap();
+
+    assert_eq!(deliveries::prune(&conn, Utc::now() - Duration::days(30)).unwrap(), 1);
+    assert!(deliveries::get(&conn, "old").unwrap().is_none());
+    assert!(deliveries::get(&conn, "in-flight").unwrap().is_some());
+    assert!(deliveries::get(&conn, "new").unwrap().is_some());
+}
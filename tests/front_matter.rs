@@ -0,0 +1,179 @@
+mod support;
+
+use docsbot::webhook::{self, EventName};
+use serde_json::json;
+use tokio::sync::mpsc;
+use support::{changed_file, pull_request_payload, read_file, Harness, TestConfig, BASE_BRANCH, REPO};
+
+const FRONT_MATTER_LABEL: &str = "docs/front-matter-version-1.8.0";
+const MERGE_SHA: &str = "d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0d0";
+const TARGET: &str = "---\nid: intro\ntitle: Introduction\nslug: /1.8/intro\n\
+                      custom_edit_url: https://example.com/edit/intro.md\n---\n\n# Intro\n\nSome text.\n";
+
+/// Pushed onto the base branch when nothing diverged
+const PUSH_LABEL: &str = "docs/front-matter-push-version-1.7.0";
+
+fn config() -> String {
+    TestConfig::new()
+        .label(FRONT_MATTER_LABEL, "1.8.0", "")
+        .raw(r#"pinned_front_matter = ["slug", "custom_edit_url"]"#)
+        .label(PUSH_LABEL, "1.7.0", "mode = \"push\"")
+        .raw(r#"pinned_front_matter = ["slug"]"#)
+        .build()
+}
+
+#[tokio::test]
+async fn synced_docs_keep_their_pinned_front_matter() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            (
+                "docs/intro.md",
+                "---\nid: intro\ntitle: Getting started\nslug: /start\nsidebar_position: 2\n---\n\n# Intro\n\nBetter text.\n",
+            ),
+            ("docs/new.md", "---\ntitle: New\nslug: /new\n---\n\n# New\n"),
+            ("versioned_docs/version-1.8.0/intro.md", TARGET),
+        ],
+    );
+    h.merge(130, &[FRONT_MATTER_LABEL], &["docs/intro.md", "docs/new.md"]).await;
+
+    let pulls = h.github.pull_requests(REPO);
+    assert_eq!(pulls.len(), 1);
+    let branch = pulls[0]["head"]["ref"].as_str().unwrap();
+    let remote = h.remote(REPO);
+    assert_eq!(
+        read_file(&remote, branch, "versioned_docs/version-1.8.0/intro.md").as_deref(),
+        Some(
+            "---\nid: intro\ntitle: Getting started\nslug: /1.8/intro\nsidebar_position: 2\n\
+             custom_edit_url: https://example.com/edit/intro.md\n---\n\n# Intro\n\nBetter text.\n"
+        ),
+    );
+    assert_eq!(
+        read_file(&remote, branch, "versioned_docs/version-1.8.0/new.md").as_deref(),
+        Some("---\ntitle: New\n---\n\n# New\n"),
+    );
+}
+
+#[tokio::test]
+async fn preview_reports_front_matter_conflicts_apart() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "---\nid: intro\ntitle: Intro\nslug: /intro\n---\n\n# Intro\n\nSome text.\n"),
+            ("docs/usage.md", "---\nslug: /usage\n---\n\n# Usage\n"),
+            ("versioned_docs/version-1.8.0/usage.md", "---\nslug: /1.8/usage\n---\n\n# Usage\n"),
+        ],
+    );
+    let base = h.commit_to_branch(REPO, BASE_BRANCH, &[("versioned_docs/version-1.8.0/intro.md", TARGET)]);
+    h.github.set_pull_request_files(
+        REPO,
+        131,
+        vec![
+            changed_file(
+                "docs/intro.md",
+                "@@ -1,9 +1,9 @@\n ---\n id: intro\n-title: Intro\n-slug: /intro\n+title: Getting started\n\
+                 +slug: /start\n ---\n \n # Intro\n \n-Some text.\n+Better text.",
+            ),
+            changed_file("docs/usage.md", "@@ -1,3 +1,3 @@\n ---\n-slug: /usage\n+slug: /use\n ---"),
+        ],
+    );
+
+    let mut payload: serde_json::Value =
+        serde_json::from_str(&pull_request_payload("labeled", 131, false, MERGE_SHA, &[FRONT_MATTER_LABEL])).unwrap();
+    payload["label"] = json!({ "name": FRONT_MATTER_LABEL });
+    payload["pull_request"]["base"] = json!({ "sha": base.to_string() });
+    let (tx, _rx) = mpsc::unbounded_channel();
+    webhook::webhook(EventName::PullRequest, payload.to_string(), &h.ctx, tx)
+        .await
+        .unwrap();
+    h.ctx.background.wait().await;
+
+    let comments = h.github.comments(REPO, 131);
+    assert_eq!(comments.len(), 1);
+    let body = comments[0]["body"].as_str().unwrap();
+    assert!(body.contains("**Conflicts**: 1 file(s)"), "{}", body);
+    assert!(
+        body.contains("| `docs/intro.md` | `versioned_docs/version-1.8.0/intro.md` | front-matter conflicts |"),
+        "{}",
+        body
+    );
+    // Pinned entries are the target's own, whatever the source does to them.
+    assert!(
+        body.contains("| `docs/usage.md` | `versioned_docs/version-1.8.0/usage.md` | applies cleanly |"),
+        "{}",
+        body
+    );
+}
+
+#[tokio::test]
+async fn front_matter_changed_on_the_target_is_reported() {
+    let h = Harness::new(&config()).await;
+    let targets: Vec<(String, &str)> = ["1.7.0", "1.8.0"]
+        .iter()
+        .flat_map(|version| {
+            vec![
+                (
+                    format!("versioned_docs/version-{}/intro.md", version),
+                    "---\ntitle: Introduction\nslug: /old/intro\n---\n\n# Intro\n\nSome text.\n",
+                ),
+                (
+                    format!("versioned_docs/version-{}/usage.md", version),
+                    "---\nslug: /old/usage\n---\n\n# Usage\n",
+                ),
+            ]
+        })
+        .collect();
+    let mut files = vec![
+        ("docs/intro.md", "---\ntitle: Intro\nslug: /intro\n---\n\n# Intro\n\nSome text.\n"),
+        ("docs/usage.md", "---\nslug: /usage\n---\n\n# Usage\n"),
+    ];
+    files.extend(targets.iter().map(|(path, contents)| (path.as_str(), *contents)));
+    h.create_remote(REPO, BASE_BRANCH, &files);
+    let merge_commit = h.commit_to_branch(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "---\ntitle: Intro\nslug: /intro\n---\n\n# Intro\n\nBetter text.\n"),
+            ("docs/usage.md", "---\nslug: /usage\n---\n\n# Usage\n\nRun it.\n"),
+        ],
+    );
+    h.github.set_pull_request_files(
+        REPO,
+        132,
+        vec![changed_file("docs/intro.md", "@@ -7 +7 @@"), changed_file("docs/usage.md", "@@ -5 +5,2 @@")],
+    );
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let payload = pull_request_payload("closed", 132, true, &merge_commit.to_string(), &[FRONT_MATTER_LABEL, PUSH_LABEL]);
+    webhook::webhook(EventName::PullRequest, payload, &h.ctx, tx)
+        .await
+        .unwrap();
+    h.process(rx).await;
+
+    // The diverged title keeps the push mode label from pushing.
+    assert_eq!(h.github.pull_requests(REPO).len(), 2);
+    let statuses = h.github.statuses(REPO, &merge_commit.to_string());
+    for label in &[FRONT_MATTER_LABEL, PUSH_LABEL] {
+        let status = statuses
+            .iter()
+            .find(|status| status["context"] == format!("docsbot/sync ({})", label))
+            .unwrap_or_else(|| panic!("no status for {} in {:?}", label, statuses));
+        let description = status["description"].as_str().unwrap();
+        assert!(description.ends_with(", 1 front-matter conflict(s)"), "{}", description);
+    }
+
+    // And the sync pull requests list them.
+    let pulls = h.github.pull_requests(REPO);
+    for (label, version) in &[(FRONT_MATTER_LABEL, "1.8.0"), (PUSH_LABEL, "1.7.0")] {
+        let head = format!("docsbot/132-{}", label.replace('/', "-"));
+        let pull = pulls.iter().find(|pr| pr["head"]["ref"] == *head).unwrap();
+        let body = pull["body"].as_str().unwrap();
+        assert!(body.contains("front matter of these files had changes of its own"), "{}", body);
+        assert!(body.contains(&format!("- `versioned_docs/version-{}/intro.md`\n", version)), "{}", body);
+        assert!(!body.contains("usage.md"), "{}", body);
+    }
+}
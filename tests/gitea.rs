@@ -0,0 +1,137 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use docsbot::forge::{FileStatus, Forge, ForgeKind};
+use docsbot::git::GitCredential;
+use docsbot::gitea::{self, GiteaClient};
+use hmac::{Hmac, Mac};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Response, Server};
+use serde_json::json;
+use sha2::Sha256;
+
+const DIFF: &str = "diff --git a/docs/intro.md b/docs/intro.md
+index 1111111..2222222 100644
+--- a/docs/intro.md
++++ b/docs/intro.md
+@@ -1,2 +1,2 @@
+ # Intro
+-Hello
++Hello, world
+diff --git a/docs/new.md b/docs/new.md
+new file mode 100644
+index 0000000..3333333
+--- /dev/null
++++ b/docs/new.md
+@@ -0,0 +1 @@
++# New
+diff --git a/docs/old name.md b/docs/new name.md
+similarity index 100%
+rename from docs/old name.md
+rename to docs/new name.md
+";
+
+fn pull_request_hook(action: &str, merged: bool) -> String {
+    json!({
+        "action": action,
+        "number": 3,
+        "pull_request": {
+            "number": 3,
+            "title": "Update the docs",
+            "body": "Fixes a typo",
+            "html_url": "https://gitea.example.com/docs/website/pulls/3",
+            "user": { "login": "contributor" },
+            "labels": [{ "name": "docs/cherry-version-2.0.4" }],
+            "merged": merged,
+            "merge_commit_sha": "4f0c2a1b9d8e7f6a5b4c3d2e1f0a9b8c7d6e5f4a",
+        },
+        "repository": { "full_name": "docs/website" },
+    })
+    .to_string()
+}
+
+fn client(url: &str) -> GiteaClient {
+    GiteaClient::new(reqwest::Client::new(), "token".to_string(), url)
+}
+
+#[test]
+fn merged_pull_request_becomes_change_request() {
+    let request = client("https://gitea.example.com")
+        .parse_merge_event("pull_request", &pull_request_hook("closed", true))
+        .unwrap()
+        .expect("a merged pull request");
+
+    assert_eq!(request.forge, ForgeKind::Gitea);
+    assert_eq!(request.repo_name, "docs/website");
+    assert_eq!(request.number, 3);
+    assert_eq!(request.labels, vec!["docs/cherry-version-2.0.4".to_string()]);
+}
+
+#[test]
+fn unmerged_pull_requests_are_ignored() {
+    let gitea = client("https://gitea.example.com");
+
+    for (action, merged) in &[("closed", false), ("opened", false), ("edited", true)] {
+        let parsed = gitea
+            .parse_merge_event("pull_request", &pull_request_hook(action, *merged))
+            .unwrap();
+        assert!(parsed.is_none(), "{} should be ignored", action);
+    }
+    assert!(gitea.parse_merge_event("push", "{}").unwrap().is_none());
+}
+
+#[test]
+fn signature_is_checked_against_secret() {
+    std::env::set_var("GITEA_WEBHOOK_SECRET", "s3cret");
+    let payload = pull_request_hook("closed", true);
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(b"s3cret").unwrap();
+    mac.update(payload.as_bytes());
+    let signature = hex::encode(mac.finalize().into_bytes());
+
+    assert!(gitea::verify_signature(Some(&signature), payload.as_bytes()));
+    assert!(!gitea::verify_signature(Some(&signature), b"{}"));
+    assert!(!gitea::verify_signature(Some("not hex"), payload.as_bytes()));
+    assert!(!gitea::verify_signature(None, payload.as_bytes()));
+}
+
+#[tokio::test]
+async fn changed_files_are_split_from_pull_request_diff() {
+    let make_svc = make_service_fn(|_conn| async {
+        Ok::<_, Infallible>(service_fn(|req: hyper::Request<Body>| async move {
+            assert_eq!(req.uri().path(), "/api/v1/repos/docs/website/pulls/3.diff");
+            assert_eq!(req.headers()["Authorization"], "token token");
+            Ok::<_, Infallible>(Response::new(Body::from(DIFF)))
+        }))
+    });
+    let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+    let server = Server::bind(&addr).serve(make_svc);
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(server);
+
+    let files = client(&url).changed_files("docs/website", 3).await.unwrap();
+
+    assert_eq!(files.len(), 3);
+    assert_eq!(files[0].filename, "docs/intro.md");
+    assert_eq!(files[0].status, FileStatus::Modified);
+    assert_eq!(
+        files[0].patch.as_deref(),
+        Some("@@ -1,2 +1,2 @@\n # Intro\n-Hello\n+Hello, world\n")
+    );
+    assert_eq!(files[1].filename, "docs/new.md");
+    assert_eq!(files[1].status, FileStatus::Added);
+    assert_eq!(files[2].filename, "docs/new name.md");
+    assert_eq!(files[2].status, FileStatus::Renamed);
+    assert_eq!(files[2].previous_filename.as_deref(), Some("docs/old name.md"));
+    assert!(files[2].patch.is_none());
+}
+
+#[test]
+fn git_authenticates_with_the_api_token() {
+    let gitea = GiteaClient::new(reqwest::Client::new(), "gitea-token".to_string(), "https://gitea.example.com");
+
+    assert_eq!(
+        gitea.git_credentials().unwrap(),
+        GitCredential::new("gitea-token".to_string(), "x-oauth-basic".to_string()),
+    );
+}
@@ -0,0 +1,79 @@
+use docsbot::forge::{Forge, ForgeKind};
+use docsbot::git::GitCredential;
+use docsbot::gitlab::GitlabClient;
+use serde_json::json;
+
+fn merge_request_hook(action: &str) -> String {
+    json!({
+        "object_kind": "merge_request",
+        "user": { "username": "contributor" },
+        "project": { "id": 7, "path_with_namespace": "docs/website" },
+        "object_attributes": {
+            "iid": 12,
+            "title": "Update the docs",
+            "description": "Fixes a typo",
+            "url": "https://gitlab.com/docs/website/-/merge_requests/12",
+            "state": "merged",
+            "action": action,
+            "merge_commit_sha": "4f0c2a1b9d8e7f6a5b4c3d2e1f0a9b8c7d6e5f4a",
+            "target_branch": "main",
+        },
+        "labels": [{ "title": "docs/cherry-version-2.0.4" }],
+    })
+    .to_string()
+}
+
+#[test]
+fn merged_merge_request_becomes_change_request() {
+    let gitlab = GitlabClient::new(reqwest::Client::new(), "token".to_string());
+
+    let request = gitlab
+        .parse_merge_event("Merge Request Hook", &merge_request_hook("merge"))
+        .unwrap()
+        .expect("a merged merge request");
+
+    assert_eq!(request.forge, ForgeKind::Gitlab);
+    assert_eq!(request.repo_name, "docs/website");
+    assert_eq!(request.number, 12);
+    assert_eq!(request.labels, vec!["docs/cherry-version-2.0.4".to_string()]);
+    assert_eq!(
+        request.merge_commit_sha.as_deref(),
+        Some("4f0c2a1b9d8e7f6a5b4c3d2e1f0a9b8c7d6e5f4a")
+    );
+}
+
+#[test]
+fn other_merge_request_actions_are_ignored() {
+    let gitlab = GitlabClient::new(reqwest::Client::new(), "token".to_string());
+
+    for action in &["open", "update", "close"] {
+        let parsed = gitlab
+            .parse_merge_event("Merge Request Hook", &merge_request_hook(action))
+            .unwrap();
+        assert!(parsed.is_none(), "{} should be ignored", action);
+    }
+    assert!(gitlab
+        .parse_merge_event("Push Hook", "{}")
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn webhook_token_must_match() {
+    std::env::set_var("GITLAB_WEBHOOK_TOKEN", "secret");
+
+    assert!(docsbot::gitlab::verify_token(Some("secret")));
+    assert!(!docsbot::gitlab::verify_token(Some("secreT")));
+    assert!(!docsbot::gitlab::verify_token(Some("secret2")));
+    assert!(!docsbot::gitlab::verify_token(None));
+}
+
+#[test]
+fn git_authenticates_with_the_api_token() {
+    let gitlab = GitlabClient::new(reqwest::Client::new(), "glpat-token".to_string());
+
+    assert_eq!(
+        gitlab.git_credentials().unwrap(),
+        GitCredential::new("oauth2".to_string(), "glpat-token".to_string()),
+    );
+}
@@ -0,0 +1,49 @@
+mod support;
+
+use docsbot::handlers::janitor;
+use support::{sync_branch, Harness, TestConfig, BASE_BRANCH, REPO};
+
+fn config() -> String {
+    TestConfig::with_label().build()
+}
+
+#[tokio::test]
+async fn abandoned_sync_branches_and_checkouts_are_removed() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(REPO, BASE_BRANCH, &[("docs/intro.md", "# Intro\n")]);
+    h.create_branch(REPO, "docsbot/abandoned", BASE_BRANCH);
+    h.create_branch(REPO, "docsbot/open", BASE_BRANCH);
+    h.create_branch(REPO, "docsbot/active", BASE_BRANCH);
+    h.create_branch(REPO, "feature", BASE_BRANCH);
+    h.github.open_pull_request(REPO, "docsbot/open", BASE_BRANCH);
+
+    let checkouts = h.workdir.path().join("docsbot");
+    std::fs::create_dir_all(checkouts.join("stale")).unwrap();
+    std::fs::create_dir_all(checkouts.join("active")).unwrap();
+
+    janitor::run(&h.ctx, &|branch: &str| branch == "docsbot/active").await;
+
+    let remote = h.remote(REPO);
+    let exists = |branch| remote.find_branch(branch, git2::BranchType::Local).is_ok();
+    assert!(!exists("docsbot/abandoned"));
+    assert!(exists("docsbot/open"));
+    assert!(exists("docsbot/active"));
+    assert!(exists("feature"));
+    assert!(exists(BASE_BRANCH));
+
+    assert!(!checkouts.join("stale").exists());
+    assert!(checkouts.join("active").exists());
+}
+
+#[tokio::test]
+async fn sync_branches_are_kept_while_their_pull_request_is_open() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(REPO, BASE_BRANCH, &[("docs/intro.md", "# Intro\n")]);
+    let branch = sync_branch(42);
+    h.create_branch(REPO, &branch, BASE_BRANCH);
+    h.github.open_pull_request(REPO, &branch, BASE_BRANCH);
+
+    janitor::run(&h.ctx, &|_: &str| false).await;
+
+    assert!(h.remote(REPO).find_branch(&branch, git2::BranchType::Local).is_ok());
+}
@@ -0,0 +1,83 @@
+mod support;
+
+use docsbot::webhook::{self, EventName};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use support::{changed_file, pull_request_payload, Harness, TestConfig, BASE_BRANCH, LABEL, REPO};
+
+const HEAD_SHA: &str = "b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0b0";
+
+fn config() -> String {
+    TestConfig::with_label().build()
+}
+
+async fn deliver(h: &Harness, action: &str) {
+    let mut payload: Value = serde_json::from_str(&pull_request_payload(action, 110, false, HEAD_SHA, &[LABEL])).unwrap();
+    payload["pull_request"]["state"] = json!("open");
+    if action == "labeled" {
+        payload["label"] = json!({ "name": LABEL });
+    }
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    webhook::webhook(EventName::PullRequest, payload.to_string(), &h.ctx, tx)
+        .await
+        .unwrap();
+    h.ctx.background.wait().await;
+    assert!(rx.try_recv().is_err(), "an open pull request was queued for syncing");
+}
+
+#[tokio::test]
+async fn preview_comment_shows_what_would_conflict() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "# Intro\n\nSome text.\n"),
+            ("docs/usage.md", "# Usage\n\nRun it.\n"),
+            ("versioned_docs/version-2.0.4/intro.md", "# Intro\n\nSome text.\n"),
+            ("versioned_docs/version-2.0.4/usage.md", "# Usage\n\nRun it with care.\n"),
+        ],
+    );
+    h.github.set_pull_request_files(
+        REPO,
+        110,
+        vec![
+            changed_file("docs/intro.md", "@@ -1,3 +1,3 @@\n # Intro\n \n-Some text.\n+Better text."),
+            changed_file("docs/usage.md", "@@ -1,3 +1,3 @@\n # Usage\n \n-Run it.\n+Run it now."),
+            changed_file("README.md", "@@ -1 +1 @@\n-# Site\n+# Website"),
+        ],
+    );
+
+    deliver(&h, "labeled").await;
+
+    let comments = h.github.comments(REPO, 110);
+    assert_eq!(comments.len(), 1);
+    let body = comments[0]["body"].as_str().unwrap();
+    assert!(body.contains("**Conflicts**: 1 file(s)"), "{}", body);
+    assert!(
+        body.contains("| `docs/intro.md` | `versioned_docs/version-2.0.4/intro.md` | applies cleanly |"),
+        "{}",
+        body
+    );
+    assert!(
+        body.contains("| `docs/usage.md` | `versioned_docs/version-2.0.4/usage.md` | conflicts in hunk(s) 1 of 1 |"),
+        "{}",
+        body
+    );
+    assert!(body.contains("outside the synced directories: `README.md`"), "{}", body);
+
+    // The versioned docs get fixed and the pull request pushed to again.
+    h.commit_to_branch(
+        REPO,
+        BASE_BRANCH,
+        &[("versioned_docs/version-2.0.4/usage.md", "# Usage\n\nRun it.\n")],
+    );
+    deliver(&h, "synchronize").await;
+
+    let comments = h.github.comments(REPO, 110);
+    assert_eq!(comments.len(), 1);
+    let body = comments[0]["body"].as_str().unwrap();
+    assert!(body.contains("All changes apply cleanly"), "{}", body);
+    assert!(!body.contains("conflicts in"), "{}", body);
+}
@@ -0,0 +1,80 @@
+mod support;
+
+use docsbot::webhook::{self, EventName};
+use git2::BranchType;
+use tokio::sync::mpsc;
+use support::{changed_file, pull_request_payload, read_file, Harness, TestConfig, BASE_BRANCH, REPO};
+
+const PUSH_LABEL: &str = "docs/push-version-2.0.1";
+const VERSIONED_INTRO: &str = "versioned_docs/version-2.0.1/intro.md";
+
+fn config() -> String {
+    TestConfig::new().label(PUSH_LABEL, "2.0.1", "mode = \"push\"").build()
+}
+
+async fn sync(h: &Harness, number: u64, merge_sha: &str) {
+    h.github
+        .set_pull_request_files(REPO, number, vec![changed_file("docs/intro.md", "@@ -1 +1 @@")]);
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let payload = pull_request_payload("closed", number, true, merge_sha, &[PUSH_LABEL]);
+    webhook::webhook(EventName::PullRequest, payload, &h.ctx, tx)
+        .await
+        .unwrap();
+    h.process(rx).await;
+}
+
+fn docsbot_branches(remote: &git2::Repository) -> Vec<String> {
+    remote
+        .branches(Some(BranchType::Local))
+        .unwrap()
+        .map(|branch| branch.unwrap().0.name().unwrap().unwrap().to_string())
+        .filter(|name| name.starts_with("docsbot/"))
+        .collect()
+}
+
+#[tokio::test]
+async fn changes_are_pushed_onto_the_base_branch() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[("docs/intro.md", "# Intro\n"), (VERSIONED_INTRO, "# Intro\n")],
+    );
+    let merge = h.commit_to_branch(REPO, BASE_BRANCH, &[("docs/intro.md", "# New intro\n")]);
+
+    sync(&h, 80, &merge.to_string()).await;
+
+    let remote = h.remote(REPO);
+    assert_eq!(
+        read_file(&remote, BASE_BRANCH, VERSIONED_INTRO).as_deref(),
+        Some("# New intro\n"),
+    );
+    let tip = remote.find_branch(BASE_BRANCH, BranchType::Local).unwrap().get().peel_to_commit().unwrap();
+    assert_eq!(tip.parent_id(0).unwrap(), merge);
+    assert!(h.github.pull_requests(REPO).is_empty());
+    assert!(docsbot_branches(&remote).is_empty());
+}
+
+#[tokio::test]
+async fn diverged_docs_get_a_pull_request() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[("docs/intro.md", "# Intro\n"), (VERSIONED_INTRO, "# Intro for 2.0.1\n")],
+    );
+    let merge = h.commit_to_branch(REPO, BASE_BRANCH, &[("docs/intro.md", "# New intro\n")]);
+
+    sync(&h, 81, &merge.to_string()).await;
+
+    let remote = h.remote(REPO);
+    assert_eq!(
+        read_file(&remote, BASE_BRANCH, VERSIONED_INTRO).as_deref(),
+        Some("# Intro for 2.0.1\n"),
+    );
+    let pulls = h.github.pull_requests(REPO);
+    assert_eq!(pulls.len(), 1);
+    let head = pulls[0]["head"]["ref"].as_str().unwrap();
+    assert_eq!(read_file(&remote, head, VERSIONED_INTRO).as_deref(), Some("# New intro\n"));
+}
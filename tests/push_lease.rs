@@ -0,0 +1,96 @@
+use std::path::Path;
+use std::process::Command;
+
+use docsbot::git::{Git, GitCredential, PushError};
+
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(["-c", "user.name=docsbot", "-c", "user.email=docsbot@example.com"])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "git {:?}: {}", args, String::from_utf8_lossy(&output.stderr));
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+/// A bare remote with `main`, and a clone of it with `sync` committed on
+/// top.
+fn setup(dir: &Path) -> (std::path::PathBuf, std::path::PathBuf) {
+    let remote = dir.join("remote.git");
+    let clone = dir.join("clone");
+    git(dir, &["init", "--bare", "-b", "main", remote.to_str().unwrap()]);
+    git(dir, &["clone", remote.to_str().unwrap(), clone.to_str().unwrap()]);
+    git(&clone, &["commit", "--allow-empty", "-m", "initial"]);
+    git(&clone, &["push", "origin", "HEAD:refs/heads/main"]);
+    git(&clone, &["checkout", "-b", "sync"]);
+    git(&clone, &["commit", "--allow-empty", "-m", "sync"]);
+    (remote, clone)
+}
+
+fn push(clone: &Path, lease: Option<&str>) -> Result<(), PushError> {
+    let gt = Git::new(clone.to_path_buf(), GitCredential::new(String::new(), String::new())).unwrap();
+    gt.push_branch(clone, "sync", "origin", lease.map(|oid| git2::Oid::from_str(oid).unwrap()))
+}
+
+#[test]
+fn branch_is_pushed_over_what_was_fetched() {
+    let dir = tempfile::tempdir().unwrap();
+    let (remote, clone) = setup(dir.path());
+    push(&clone, None).unwrap();
+    let fetched = git(&remote, &["rev-parse", "sync"]);
+
+    // Another attempt of the same sync rewrites the branch.
+    git(&clone, &["commit", "--amend", "--allow-empty", "-m", "sync again"]);
+    push(&clone, Some(&fetched)).unwrap();
+    assert_eq!(git(&remote, &["rev-parse", "sync"]), git(&clone, &["rev-parse", "HEAD"]));
+}
+
+#[test]
+fn pushes_made_in_between_are_not_overwritten() {
+    let dir = tempfile::tempdir().unwrap();
+    let (remote, clone) = setup(dir.path());
+    let main = git(&remote, &["rev-parse", "main"]);
+
+    // Someone pushes the branch after it was found missing.
+    git(&remote, &["branch", "sync", "main"]);
+    assert!(matches!(push(&clone, None), Err(PushError::StaleLease(_))));
+
+    // Or after it was fetched.
+    let other = dir.path().join("other");
+    git(dir.path(), &["clone", remote.to_str().unwrap(), other.to_str().unwrap()]);
+    git(&other, &["checkout", "sync"]);
+    git(&other, &["commit", "--allow-empty", "-m", "by hand"]);
+    git(&other, &["push", "origin", "sync"]);
+    let by_hand = git(&remote, &["rev-parse", "sync"]);
+    assert!(matches!(push(&clone, Some(&main)), Err(PushError::StaleLease(_))));
+    assert_eq!(git(&remote, &["rev-parse", "sync"]), by_hand);
+}
+
+#[test]
+fn pushes_trust_the_custom_ca() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = tempfile::tempdir().unwrap();
+    let (_, clone) = setup(dir.path());
+
+    // A remote helper that writes down the CA git would verify the server
+    // with, and then gives up.
+    let bin = dir.path().join("bin");
+    std::fs::create_dir(&bin).unwrap();
+    let helper = bin.join("git-remote-fake");
+    let seen = dir.path().join("ca");
+    std::fs::write(
+        &helper,
+        format!("#!/bin/sh\ngit config http.sslCAInfo > {}\nexit 1\n", seen.display()),
+    )
+    .unwrap();
+    std::fs::set_permissions(&helper, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let path = format!("{}:{}", bin.display(), std::env::var("PATH").unwrap());
+    std::env::set_var("PATH", path);
+    std::env::set_var("GITHUB_CA_CERT", "/etc/docsbot/ca.pem");
+    git(&clone, &["remote", "set-url", "origin", "fake::docs/website"]);
+
+    assert!(matches!(push(&clone, None), Err(PushError::Failed { .. })));
+    assert_eq!(std::fs::read_to_string(&seen).unwrap().trim(), "/etc/docsbot/ca.pem");
+}
@@ -0,0 +1,43 @@
+mod support;
+
+use std::process::Command;
+
+use support::fake_github::FakeGithub;
+
+/// `docsbot replay` with an environment that has no GitHub token.
+fn replay(dir: &std::path::Path, args: &[&str]) -> std::process::Output {
+    let payload = dir.join("payload.json");
+    std::fs::write(&payload, "{}").unwrap();
+
+    Command::new(env!("CARGO_BIN_EXE_docsbot"))
+        .current_dir(dir)
+        .env_remove("GITHUB_API_TOKEN")
+        .env("HOME", dir)
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("DATABASE", dir.join("docsbot.store"))
+        .args(["replay", "--event", "issue_comment"])
+        .args(args)
+        .arg(&payload)
+        .output()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn replaying_against_a_fake_api_needs_no_token() {
+    let dir = tempfile::tempdir().unwrap();
+    let github = FakeGithub::start(dir.path().to_path_buf()).await;
+
+    let output = replay(dir.path(), &["--github-url", &github.url]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+#[tokio::test]
+async fn replaying_without_a_token_fails_cleanly() {
+    let dir = tempfile::tempdir().unwrap();
+
+    let output = replay(dir.path(), &[]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("could not find token"), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use docsbot::git::PushError;
+use docsbot::handlers::retry::{is_transient, RetryPolicy};
+
+fn git_error(code: git2::ErrorCode, class: git2::ErrorClass) -> anyhow::Error {
+    git2::Error::new(code, class, "push failed").into()
+}
+
+#[test]
+fn pushes_racing_a_moving_base_are_retried() {
+    let rejected = git_error(git2::ErrorCode::NotFastForward, git2::ErrorClass::Reference);
+    assert!(is_transient(&rejected.context("failed to push main")));
+
+    let network = git_error(git2::ErrorCode::GenericError, git2::ErrorClass::Net);
+    assert!(is_transient(&network));
+}
+
+#[test]
+fn pushes_refused_for_good_are_not_retried() {
+    let declined = git_error(git2::ErrorCode::GenericError, git2::ErrorClass::Reference);
+    assert!(!is_transient(&declined));
+}
+
+#[test]
+fn pushes_over_someone_elses_are_not_retried() {
+    let stale = anyhow::Error::from(PushError::StaleLease("docsbot/1-docs".to_string()));
+    assert!(!is_transient(&stale));
+
+    let unreachable = anyhow::Error::from(PushError::Failed {
+        branch: "docsbot/1-docs".to_string(),
+        message: "Could not resolve host".to_string(),
+    });
+    assert!(is_transient(&unreachable));
+}
+
+#[test]
+fn retries_wait_as_long_as_the_forge_asks() {
+    let policy = RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::from_millis(10),
+        max_delay: Duration::from_secs(1),
+    };
+    assert!(policy.delay(1, None) <= Duration::from_millis(10));
+    assert_eq!(policy.delay(1, Some(Duration::from_secs(60))), Duration::from_secs(60));
+}
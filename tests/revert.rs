@@ -0,0 +1,73 @@
+mod support;
+
+use docsbot::webhook::{self, EventName};
+use serde_json::Value;
+use tokio::sync::mpsc;
+use support::{
+    changed_file, pull_request_payload, read_file, sync_branch, Harness, TestConfig, BASE_BRANCH, LABEL, REPO,
+};
+
+fn config() -> String {
+    TestConfig::with_label().build()
+}
+
+/// The payload of merging the pull request GitHub's "Revert" button opens
+/// for `reverted`.
+fn revert_payload(number: u64, sha: &str, reverted: u64) -> String {
+    let mut payload: Value = serde_json::from_str(&pull_request_payload("closed", number, true, sha, &[])).unwrap();
+    payload["pull_request"]["title"] = "Revert \"Update the docs\"".into();
+    payload["pull_request"]["body"] = format!("Reverts {}#{}", REPO, reverted).into();
+    payload.to_string()
+}
+
+async fn deliver(h: &Harness, payload: String) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    webhook::webhook(EventName::PullRequest, payload, &h.ctx, tx)
+        .await
+        .unwrap();
+    h.process(rx).await;
+}
+
+#[tokio::test]
+async fn reverting_a_synced_pull_request_syncs_the_revert() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "# New intro\n"),
+            ("versioned_docs/version-2.0.4/intro.md", "# Intro\n"),
+        ],
+    );
+    let intro = vec![changed_file("docs/intro.md", "@@ -1 +1 @@")];
+    h.github.set_pull_request_files(REPO, 80, intro.clone());
+    h.github.set_pull_request_files(REPO, 81, intro);
+
+    let sha = "8080808080808080808080808080808080808080";
+    deliver(&h, pull_request_payload("closed", 80, true, sha, &[LABEL])).await;
+    // The sync pull request is merged, then the original is reverted.
+    h.commit_to_branch(REPO, BASE_BRANCH, &[("versioned_docs/version-2.0.4/intro.md", "# New intro\n")]);
+    h.commit_to_branch(REPO, BASE_BRANCH, &[("docs/intro.md", "# Intro\n")]);
+    deliver(&h, revert_payload(81, "8181818181818181818181818181818181818181", 80)).await;
+
+    let pulls = h.github.pull_requests(REPO);
+    assert_eq!(pulls.len(), 2);
+    assert_eq!(pulls[1]["head"]["ref"], sync_branch(81));
+    assert_eq!(pulls[1]["title"], format!("revert docs sync of #80 to {}", LABEL));
+    assert_eq!(
+        read_file(&h.remote(REPO), &sync_branch(81), "versioned_docs/version-2.0.4/intro.md").as_deref(),
+        Some("# Intro\n"),
+    );
+}
+
+#[tokio::test]
+async fn reverting_a_pull_request_that_was_not_synced_does_nothing() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(REPO, BASE_BRANCH, &[("docs/intro.md", "# Intro\n")]);
+    h.github
+        .set_pull_request_files(REPO, 83, vec![changed_file("docs/intro.md", "@@ -1 +1 @@")]);
+
+    deliver(&h, revert_payload(83, "8383838383838383838383838383838383838383", 82)).await;
+
+    assert!(h.github.pull_requests(REPO).is_empty());
+}
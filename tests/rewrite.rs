@@ -0,0 +1,81 @@
+mod support;
+
+use docsbot::webhook::{self, EventName};
+use serde_json::json;
+use tokio::sync::mpsc;
+use support::{changed_file, pull_request_payload, read_file, Harness, TestConfig, BASE_BRANCH, REPO};
+
+const REWRITE_LABEL: &str = "docs/rewrite-version-1.9.0";
+const MERGE_SHA: &str = "c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0c0";
+const SOURCE: &str = "# Intro\n\nSee [usage](/docs/next/usage) for {{ version }}.\n\n![Diagram](/img/next/diagram.png)\n";
+const REWRITTEN: &str = "# Intro\n\nSee [usage](/docs/1.9.0/usage) for 1.9.0.\n\n![Diagram](/img/v1.9/diagram.png)\n";
+
+fn config() -> String {
+    TestConfig::new()
+        .label(REWRITE_LABEL, "1.9.0", "")
+        .raw(
+            r#"version = "1.9.0"
+
+[[repos.labels.sync_paths.rewrites]]
+pattern = '/img/next/(\w+)'
+replacement = '/img/v1.9/$1'"#,
+        )
+        .build()
+}
+
+#[tokio::test]
+async fn synced_docs_are_rewritten_for_their_version() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", SOURCE),
+            ("versioned_docs/version-1.9.0/intro.md", "# Intro\n"),
+        ],
+    );
+    h.merge(120, &[REWRITE_LABEL], &["docs/intro.md"]).await;
+
+    let pulls = h.github.pull_requests(REPO);
+    assert_eq!(pulls.len(), 1);
+    let branch = pulls[0]["head"]["ref"].as_str().unwrap();
+    assert_eq!(
+        read_file(&h.remote(REPO), branch, "versioned_docs/version-1.9.0/intro.md").as_deref(),
+        Some(REWRITTEN),
+    );
+}
+
+#[tokio::test]
+async fn preview_matches_hunks_against_the_rewritten_docs() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", SOURCE),
+            ("versioned_docs/version-1.9.0/intro.md", REWRITTEN),
+        ],
+    );
+    h.github.set_pull_request_files(
+        REPO,
+        121,
+        vec![changed_file(
+            "docs/intro.md",
+            "@@ -1,5 +1,5 @@\n-# Intro\n+# Introduction\n \n See [usage](/docs/next/usage) for {{ version }}.\n \n ![Diagram](/img/next/diagram.png)",
+        )],
+    );
+
+    let mut payload: serde_json::Value =
+        serde_json::from_str(&pull_request_payload("labeled", 121, false, MERGE_SHA, &[REWRITE_LABEL])).unwrap();
+    payload["label"] = json!({ "name": REWRITE_LABEL });
+    let (tx, _rx) = mpsc::unbounded_channel();
+    webhook::webhook(EventName::PullRequest, payload.to_string(), &h.ctx, tx)
+        .await
+        .unwrap();
+    h.ctx.background.wait().await;
+
+    let comments = h.github.comments(REPO, 121);
+    assert_eq!(comments.len(), 1);
+    let body = comments[0]["body"].as_str().unwrap();
+    assert!(body.contains("| applies cleanly |"), "{}", body);
+}
@@ -0,0 +1,76 @@
+mod support;
+
+use docsbot::webhook::{self, EventName};
+use tokio::sync::mpsc;
+use support::{
+    changed_file, pull_request_payload, sync_branch, Harness, TestConfig, BASE_BRANCH, LABEL, REPO,
+};
+
+/// Synced keeping the commits of the pull request
+const HISTORY_LABEL: &str = "docs/history-version-2.0.2";
+const MERGE_SHA: &str = "a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0a0";
+
+fn config() -> String {
+    TestConfig::with_label().label(HISTORY_LABEL, "2.0.2", "preserve_commits = true").build()
+}
+
+#[tokio::test]
+async fn statuses_report_the_sync_to_each_label() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "# Intro\n"),
+            ("versioned_docs/version-2.0.4/intro.md", "# Old intro\n"),
+        ],
+    );
+    h.github.set_pull_request_files(
+        REPO,
+        100,
+        vec![changed_file("docs/intro.md", "@@ -1 +1 @@"), changed_file("README.md", "@@ -1 +1 @@")],
+    );
+
+    // Syncing to HISTORY_LABEL fails, the commits of the pull request are
+    // not there to replay.
+    let (tx, rx) = mpsc::unbounded_channel();
+    let payload = pull_request_payload("closed", 100, true, MERGE_SHA, &[LABEL, HISTORY_LABEL]);
+    webhook::webhook(EventName::PullRequest, payload, &h.ctx, tx)
+        .await
+        .unwrap();
+    h.process(rx).await;
+
+    let statuses = h.github.statuses(REPO, MERGE_SHA);
+    assert_eq!(statuses.len(), 2);
+    let status = |label: &str| {
+        statuses
+            .iter()
+            .find(|status| status["context"] == format!("docsbot/sync ({})", label))
+            .unwrap_or_else(|| panic!("no status for {} in {:?}", label, statuses))
+    };
+
+    let synced = status(LABEL);
+    assert_eq!(synced["state"], "success");
+    assert_eq!(
+        synced["description"],
+        format!("Synced to `{}`: 1 applied, 1 skipped", BASE_BRANCH)
+    );
+    let pulls = h.github.pull_requests(REPO);
+    let pull = pulls.iter().find(|pr| pr["head"]["ref"] == *sync_branch(100)).unwrap();
+    assert_eq!(synced["target_url"], pull["html_url"]);
+
+    let failed = status(HISTORY_LABEL);
+    assert_eq!(failed["state"], "failure");
+    assert!(failed["description"].as_str().unwrap().starts_with("Failed: "), "{}", failed);
+    assert!(failed["description"].as_str().unwrap().chars().count() <= 140, "{}", failed);
+
+    // Pending while queued and syncing, then done.
+    let set = h
+        .github
+        .calls()
+        .iter()
+        .filter(|c| c.path == format!("/repos/{}/statuses/{}", REPO, MERGE_SHA))
+        .count();
+    assert_eq!(set, 6);
+    assert!(h.github.check_runs(REPO).is_empty());
+}
@@ -0,0 +1,700 @@
+//! A minimal in-process stand-in for the GitHub REST API.
+//!
+//! Only the endpoints docsbot talks to are implemented. Every request is
+//! recorded so that tests can assert on what the bot did.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use serde_json::{json, Value};
+
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub method: Method,
+    pub path: String,
+    pub query: Option<String>,
+    pub body: Option<Value>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Directory holding the bare `<owner>/<repo>` remotes, which branches
+    /// are looked up in
+    remotes: PathBuf,
+    calls: Vec<Call>,
+    next_number: u64,
+    /// Commits by (repo, pull request number)
+    commits: HashMap<(String, u64), Vec<Value>>,
+    /// Changed files by (repo, pull request number)
+    files: HashMap<(String, u64), Vec<Value>>,
+    /// Pull requests by repo
+    pulls: HashMap<String, Vec<Value>>,
+    /// Comments by (repo, issue number)
+    comments: HashMap<(String, u64), Vec<Value>>,
+    /// Labels defined in a repo
+    labels: HashMap<String, Vec<String>>,
+    /// Labels set on issues and pull requests, by (repo, number)
+    issue_labels: HashMap<(String, u64), Vec<String>>,
+    /// Responses to fail with, by (method, path)
+    failures: HashMap<(Method, String), Failure>,
+    /// Whether repositories refuse to enable auto-merge
+    auto_merge_disabled: bool,
+    /// Check runs by repo
+    check_runs: HashMap<String, Vec<Value>>,
+    /// Commit statuses by (repo, sha), in the order they were set
+    statuses: HashMap<(String, String), Vec<Value>>,
+    /// Issues opened through the API, by repo
+    issues: HashMap<String, Vec<Value>>,
+}
+
+struct Failure {
+    status: StatusCode,
+    headers: Vec<(&'static str, String)>,
+    /// How many more requests fail
+    times: usize,
+}
+
+#[derive(Clone)]
+pub struct FakeGithub {
+    pub url: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl FakeGithub {
+    pub async fn start(remotes: PathBuf) -> FakeGithub {
+        let state = Arc::new(Mutex::new(State {
+            remotes,
+            next_number: 1000,
+            ..State::default()
+        }));
+
+        let service_state = state.clone();
+        let make_svc = make_service_fn(move |_conn| {
+            let state = service_state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req| {
+                    let state = state.clone();
+                    async move { Ok::<_, Infallible>(handle(state, req).await) }
+                }))
+            }
+        });
+
+        let addr: SocketAddr = ([127, 0, 0, 1], 0).into();
+        let server = Server::bind(&addr).serve(make_svc);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+
+        FakeGithub { url, state }
+    }
+
+    /// Sets the files returned by the pull request files endpoint.
+    pub fn set_pull_request_files(&self, repo: &str, number: u64, files: Vec<Value>) {
+        self.state
+            .lock()
+            .unwrap()
+            .files
+            .insert((repo.to_string(), number), files);
+    }
+
+    /// Sets the commits returned by the pull request commits endpoint.
+    pub fn set_pull_request_commits(&self, repo: &str, number: u64, commits: Vec<Value>) {
+        self.state
+            .lock()
+            .unwrap()
+            .commits
+            .insert((repo.to_string(), number), commits);
+    }
+
+    pub fn add_label(&self, repo: &str, name: &str) {
+        self.state
+            .lock()
+            .unwrap()
+            .labels
+            .entry(repo.to_string())
+            .or_default()
+            .push(name.to_string());
+    }
+
+    /// Makes the next `times` requests to `method path` fail with `status`.
+    pub fn fail(&self, method: Method, path: &str, status: StatusCode, times: usize) {
+        self.fail_with_headers(method, path, status, &[], times);
+    }
+
+    /// Like [`FakeGithub::fail`], with `headers` on the responses, e.g. to
+    /// say that the rate limit ran out.
+    pub fn fail_with_headers(
+        &self,
+        method: Method,
+        path: &str,
+        status: StatusCode,
+        headers: &[(&'static str, &str)],
+        times: usize,
+    ) {
+        let headers = headers.iter().map(|(name, value)| (*name, value.to_string())).collect();
+        self.state
+            .lock()
+            .unwrap()
+            .failures
+            .insert((method, path.to_string()), Failure { status, headers, times });
+    }
+
+    /// Adds a pull request as if someone had opened it from `head`.
+    pub fn open_pull_request(&self, repo: &str, head: &str, base: &str) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.next_number += 1;
+        let number = state.next_number;
+        let pr = json!({
+            "number": number,
+            "state": "open",
+            "title": "",
+            "body": "",
+            "html_url": format!("https://github.com/{}/pull/{}", repo, number),
+            "head": { "ref": head },
+            "base": { "ref": base },
+        });
+        state.pulls.entry(repo.to_string()).or_default().push(pr);
+        number
+    }
+
+    /// Makes enabling auto-merge fail, as in a repository that does not
+    /// allow it.
+    pub fn disable_auto_merge(&self) {
+        self.state.lock().unwrap().auto_merge_disabled = true;
+    }
+
+    /// Sets the `mergeable_state` of a pull request, `blocked` until then.
+    pub fn set_mergeable_state(&self, repo: &str, number: u64, mergeable_state: &str) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(pr) = find_pull(&mut state, repo, number) {
+            pr["mergeable_state"] = json!(mergeable_state);
+        }
+    }
+
+    /// The statuses of the commit `sha` in `repo`, the last one set for
+    /// each context.
+    pub fn statuses(&self, repo: &str, sha: &str) -> Vec<Value> {
+        let state = self.state.lock().unwrap();
+        let mut latest: Vec<Value> = Vec::new();
+        for status in state.statuses.get(&(repo.to_string(), sha.to_string())).into_iter().flatten() {
+            match latest.iter_mut().find(|s| s["context"] == status["context"]) {
+                Some(existing) => *existing = status.clone(),
+                None => latest.push(status.clone()),
+            }
+        }
+        latest
+    }
+
+    /// The check runs of `repo`, as last updated.
+    pub fn check_runs(&self, repo: &str) -> Vec<Value> {
+        self.state
+            .lock()
+            .unwrap()
+            .check_runs
+            .get(repo)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn calls(&self) -> Vec<Call> {
+        self.state.lock().unwrap().calls.clone()
+    }
+
+    /// Pull requests opened through the API.
+    pub fn pull_requests(&self, repo: &str) -> Vec<Value> {
+        self.state
+            .lock()
+            .unwrap()
+            .pulls
+            .get(repo)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn comments(&self, repo: &str, number: u64) -> Vec<Value> {
+        self.state
+            .lock()
+            .unwrap()
+            .comments
+            .get(&(repo.to_string(), number))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Issues opened through the API, as last updated.
+    pub fn issues(&self, repo: &str) -> Vec<Value> {
+        self.state
+            .lock()
+            .unwrap()
+            .issues
+            .get(repo)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    pub fn issue_labels(&self, repo: &str, number: u64) -> Vec<String> {
+        self.state
+            .lock()
+            .unwrap()
+            .issue_labels
+            .get(&(repo.to_string(), number))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+fn respond(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json")
+        .header("X-RateLimit-Remaining", "4999")
+        .header("X-RateLimit-Reset", "0")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn not_found() -> Response<Body> {
+    respond(StatusCode::NOT_FOUND, json!({ "message": "Not Found" }))
+}
+
+fn query_param<'a>(query: Option<&'a str>, key: &str) -> Option<&'a str> {
+    query?
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+}
+
+fn find_pull<'a>(state: &'a mut State, repo: &str, number: u64) -> Option<&'a mut Value> {
+    state
+        .pulls
+        .get_mut(repo)
+        .and_then(|pulls| pulls.iter_mut().find(|pr| pr["number"] == number))
+}
+
+/// The commit `branch` points at in the bare remote at `remote`.
+fn branch_tip(remote: &Path, branch: &str) -> Option<String> {
+    let repo = git2::Repository::open_bare(remote).ok()?;
+    let tip = repo.find_branch(branch, git2::BranchType::Local).ok()?.get().target()?;
+    Some(tip.to_string())
+}
+
+async fn handle(state: Arc<Mutex<State>>, req: Request<Body>) -> Response<Body> {
+    let (parts, body) = req.into_parts();
+    let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+    let body: Option<Value> = serde_json::from_slice(&body).ok();
+    let path = parts.uri.path().to_string();
+    let query = parts.uri.query().map(str::to_string);
+
+    let mut state = state.lock().unwrap();
+    state.calls.push(Call {
+        method: parts.method.clone(),
+        path: path.clone(),
+        query: query.clone(),
+        body: body.clone(),
+    });
+
+    if let Some(failure) = state.failures.get_mut(&(parts.method.clone(), path.clone())) {
+        if failure.times > 0 {
+            failure.times -= 1;
+            // Unless asked for, without rate limit headers, so the client
+            // does not retry by itself.
+            let mut response = Response::builder().status(failure.status);
+            for (name, value) in failure.headers.iter() {
+                response = response.header(*name, value.as_str());
+            }
+            return response
+                .body(Body::from(json!({ "message": "Server Error" }).to_string()))
+                .unwrap();
+        }
+    }
+
+    let segments: Vec<&str> = path.trim_start_matches('/').split('/').collect();
+    // Check runs are for GitHub Apps, whose installation tokens start with
+    // `ghs_`.
+    let app_token = parts
+        .headers
+        .get(hyper::header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.trim_start_matches("token ").starts_with("ghs_"));
+    if segments.contains(&"check-runs") && !app_token {
+        return respond(
+            StatusCode::FORBIDDEN,
+            json!({ "message": "You must authenticate via a GitHub App." }),
+        );
+    }
+    match (&parts.method, segments.as_slice()) {
+        (&Method::GET, ["user"]) => respond(StatusCode::OK, json!({ "login": "docsbot", "id": 1 })),
+        (&Method::GET, ["rate_limit"]) => {
+            let limit = json!({ "limit": 5000, "remaining": 4999, "reset": 0 });
+            respond(
+                StatusCode::OK,
+                json!({ "resources": {
+                    "core": limit,
+                    "search": limit,
+                    "graphql": limit,
+                    "source_import": limit,
+                }}),
+            )
+        }
+        (&Method::GET, ["repos", owner, name, "pulls"]) => {
+            let repo = format!("{}/{}", owner, name);
+            let head = query_param(query.as_deref(), "head");
+            let pr_state = query_param(query.as_deref(), "state");
+            let pulls: Vec<Value> = state
+                .pulls
+                .get(&repo)
+                .into_iter()
+                .flatten()
+                .filter(|pr| match head {
+                    Some(head) => {
+                        let branch = head.split_once(':').map_or(head, |(_, b)| b);
+                        pr["head"]["ref"] == branch
+                    }
+                    None => true,
+                })
+                .filter(|pr| pr_state.is_none_or(|s| s == "all" || pr["state"] == s))
+                .cloned()
+                .collect();
+            respond(StatusCode::OK, Value::from(pulls))
+        }
+        (&Method::POST, ["repos", owner, name, "pulls"]) => {
+            let repo = format!("{}/{}", owner, name);
+            let body = body.unwrap_or_default();
+            let (head, base) = (&body["head"], &body["base"]);
+            let already_open = state
+                .pulls
+                .get(&repo)
+                .into_iter()
+                .flatten()
+                .any(|pr| pr["head"]["ref"] == *head && pr["base"]["ref"] == *base);
+            if already_open {
+                return respond(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    json!({ "message": "Validation Failed" }),
+                );
+            }
+
+            state.next_number += 1;
+            let number = state.next_number;
+            let pr = json!({
+                "number": number,
+                "node_id": format!("PR_{}", number),
+                "state": "open",
+                "title": body["title"],
+                "body": body["body"],
+                "html_url": format!("https://github.com/{}/pull/{}", repo, number),
+                "head": { "ref": head },
+                "base": { "ref": base },
+                "mergeable": true,
+                "mergeable_state": "blocked",
+            });
+            state.pulls.entry(repo).or_default().push(pr.clone());
+            respond(StatusCode::CREATED, pr)
+        }
+        (&Method::GET, ["repos", owner, name, "pulls", number]) => {
+            let number: u64 = number.parse().unwrap_or_default();
+            let remote = state.remotes.join(owner).join(name);
+            match find_pull(&mut state, &format!("{}/{}", owner, name), number) {
+                Some(pr) => {
+                    let mut pr = pr.clone();
+                    pr["head"]["sha"] = json!(branch_tip(&remote, pr["head"]["ref"].as_str().unwrap_or_default()));
+                    respond(StatusCode::OK, pr)
+                }
+                None => not_found(),
+            }
+        }
+        (&Method::PUT, ["repos", owner, name, "pulls", number, "merge"]) => {
+            let number: u64 = number.parse().unwrap_or_default();
+            let remote = state.remotes.join(owner).join(name);
+            let body = body.unwrap_or_default();
+            match find_pull(&mut state, &format!("{}/{}", owner, name), number) {
+                Some(pr) if pr["state"] != "open" || pr["mergeable_state"] == "dirty" => respond(
+                    StatusCode::METHOD_NOT_ALLOWED,
+                    json!({ "message": "Pull Request is not mergeable" }),
+                ),
+                Some(pr) if body["sha"] != json!(branch_tip(&remote, pr["head"]["ref"].as_str().unwrap_or_default())) => {
+                    respond(
+                        StatusCode::CONFLICT,
+                        json!({ "message": "Head branch was modified. Review and try the merge again." }),
+                    )
+                }
+                Some(pr) => {
+                    pr["state"] = json!("closed");
+                    pr["merged"] = json!(true);
+                    pr["merge_method"] = body["merge_method"].clone();
+                    respond(StatusCode::OK, json!({ "merged": true }))
+                }
+                None => not_found(),
+            }
+        }
+        (&Method::POST, ["repos", owner, name, "statuses", sha]) => {
+            let status = body.unwrap_or_default();
+            state
+                .statuses
+                .entry((format!("{}/{}", owner, name), sha.to_string()))
+                .or_default()
+                .push(status.clone());
+            respond(StatusCode::CREATED, status)
+        }
+        (&Method::POST, ["repos", owner, name, "check-runs"]) => {
+            state.next_number += 1;
+            let mut check_run = body.unwrap_or_default();
+            check_run["id"] = json!(state.next_number);
+            state
+                .check_runs
+                .entry(format!("{}/{}", owner, name))
+                .or_default()
+                .push(check_run.clone());
+            respond(StatusCode::CREATED, check_run)
+        }
+        (&Method::PATCH, ["repos", owner, name, "check-runs", id]) => {
+            let id: u64 = id.parse().unwrap_or_default();
+            let changes = body.unwrap_or_default();
+            let check_run = state
+                .check_runs
+                .get_mut(&format!("{}/{}", owner, name))
+                .and_then(|runs| runs.iter_mut().find(|run| run["id"] == id));
+            match (check_run, changes) {
+                (Some(check_run), Value::Object(changes)) => {
+                    for (key, value) in changes {
+                        check_run[key] = value;
+                    }
+                    respond(StatusCode::OK, check_run.clone())
+                }
+                (Some(_), _) => respond(StatusCode::UNPROCESSABLE_ENTITY, json!({ "message": "Invalid request" })),
+                (None, _) => not_found(),
+            }
+        }
+        (&Method::POST, ["graphql"]) => {
+            let body = body.unwrap_or_default();
+            if state.auto_merge_disabled {
+                return respond(
+                    StatusCode::OK,
+                    json!({ "data": null, "errors": [
+                        { "message": "Pull request Auto merge is not allowed for this repository" },
+                    ]}),
+                );
+            }
+            let variables = &body["variables"];
+            let pr = state
+                .pulls
+                .values_mut()
+                .flatten()
+                .find(|pr| pr["node_id"] == variables["pullRequestId"]);
+            match pr {
+                Some(pr) => {
+                    pr["auto_merge"] = json!({ "merge_method": variables["mergeMethod"] });
+                    respond(
+                        StatusCode::OK,
+                        json!({ "data": { "enablePullRequestAutoMerge": { "clientMutationId": null } } }),
+                    )
+                }
+                None => respond(
+                    StatusCode::OK,
+                    json!({ "data": null, "errors": [{ "message": "Could not resolve to a node" }] }),
+                ),
+            }
+        }
+        (&Method::PATCH, ["repos", owner, name, "pulls", number]) => {
+            let number: u64 = number.parse().unwrap_or_default();
+            let changes = body.unwrap_or_default();
+            match find_pull(&mut state, &format!("{}/{}", owner, name), number) {
+                Some(pr) => {
+                    for key in &["title", "body", "state"] {
+                        if !changes[key].is_null() {
+                            pr[key] = changes[key].clone();
+                        }
+                    }
+                    respond(StatusCode::OK, pr.clone())
+                }
+                None => not_found(),
+            }
+        }
+        (&Method::GET, ["repos", owner, name, "branches", branch @ ..]) => {
+            let branch = branch.join("/");
+            let exists = git2::Repository::open_bare(state.remotes.join(owner).join(name))
+                .map(|repo| repo.find_branch(&branch, git2::BranchType::Local).is_ok())
+                .unwrap_or(false);
+            if exists {
+                respond(StatusCode::OK, json!({ "name": branch }))
+            } else {
+                not_found()
+            }
+        }
+        (&Method::GET, ["repos", owner, name, "git", "matching-refs", "heads", prefix @ ..]) => {
+            let prefix = format!("refs/heads/{}", prefix.join("/"));
+            let refs: Vec<Value> = git2::Repository::open_bare(state.remotes.join(owner).join(name))
+                .and_then(|repo| {
+                    let names = repo
+                        .references()?
+                        .filter_map(|r| r.ok()?.name().map(str::to_string))
+                        .filter(|r| r.starts_with(&prefix))
+                        .map(|r| json!({ "ref": r }))
+                        .collect();
+                    Ok(names)
+                })
+                .unwrap_or_default();
+            respond(StatusCode::OK, Value::from(refs))
+        }
+        (&Method::DELETE, ["repos", owner, name, "git", "refs", "heads", branch @ ..]) => {
+            let reference = format!("refs/heads/{}", branch.join("/"));
+            let deleted = git2::Repository::open_bare(state.remotes.join(owner).join(name))
+                .and_then(|repo| repo.find_reference(&reference)?.delete())
+                .is_ok();
+            if deleted {
+                Response::builder()
+                    .status(StatusCode::NO_CONTENT)
+                    .body(Body::empty())
+                    .unwrap()
+            } else {
+                respond(
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    json!({ "message": "Reference does not exist" }),
+                )
+            }
+        }
+        (&Method::GET, ["repos", owner, name, "pulls", number, "commits"]) => {
+            let key = (format!("{}/{}", owner, name), number.parse().unwrap_or_default());
+            match state.commits.get(&key) {
+                Some(commits) => respond(StatusCode::OK, Value::from(commits.clone())),
+                None => not_found(),
+            }
+        }
+        (&Method::GET, ["repos", owner, name, "pulls", number, "files"]) => {
+            let repo = format!("{}/{}", owner, name);
+            let number: u64 = number.parse().unwrap_or_default();
+            let page: usize = query_param(query.as_deref(), "page")
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(1);
+            let per_page: usize = query_param(query.as_deref(), "per_page")
+                .and_then(|p| p.parse().ok())
+                .unwrap_or(30);
+            match state.files.get(&(repo, number)) {
+                Some(files) => {
+                    let page: Vec<Value> = files
+                        .iter()
+                        .skip((page - 1) * per_page)
+                        .take(per_page)
+                        .cloned()
+                        .collect();
+                    respond(StatusCode::OK, Value::from(page))
+                }
+                None => not_found(),
+            }
+        }
+        (&Method::GET, ["repos", owner, name, "labels", label]) => {
+            let repo = format!("{}/{}", owner, name);
+            let exists = state
+                .labels
+                .get(&repo)
+                .is_some_and(|labels| labels.iter().any(|l| l == label));
+            if exists {
+                respond(StatusCode::OK, json!({ "name": label }))
+            } else {
+                not_found()
+            }
+        }
+        (&Method::GET, ["repos", owner, name, "issues", number, "comments"]) => {
+            let key = (format!("{}/{}", owner, name), number.parse().unwrap_or_default());
+            let comments = state.comments.get(&key).cloned().unwrap_or_default();
+            respond(StatusCode::OK, Value::from(comments))
+        }
+        (&Method::POST, ["repos", owner, name, "issues", number, "comments"]) => {
+            let key = (format!("{}/{}", owner, name), number.parse().unwrap_or_default());
+            state.next_number += 1;
+            let comment = json!({
+                "id": state.next_number,
+                "body": body.unwrap_or_default()["body"],
+                "html_url": format!("https://github.com/{}/pull/{}", key.0, key.1),
+                "user": { "login": "docsbot", "id": 1 },
+                "updated_at": "2021-01-01T00:00:00Z",
+            });
+            state.comments.entry(key).or_default().push(comment.clone());
+            respond(StatusCode::CREATED, comment)
+        }
+        (&Method::POST, ["repos", owner, name, "issues"]) => {
+            let body = body.unwrap_or_default();
+            state.next_number += 1;
+            let issue = json!({
+                "number": state.next_number,
+                "title": body["title"],
+                "body": body["body"],
+                "state": "open",
+            });
+            state.issues.entry(format!("{}/{}", owner, name)).or_default().push(issue.clone());
+            respond(StatusCode::CREATED, issue)
+        }
+        (&Method::PATCH, ["repos", owner, name, "issues", number]) => {
+            let number: u64 = number.parse().unwrap_or_default();
+            let body = body.unwrap_or_default();
+            let issue = state
+                .issues
+                .get_mut(&format!("{}/{}", owner, name))
+                .and_then(|issues| issues.iter_mut().find(|issue| issue["number"] == number));
+            match issue {
+                Some(issue) => {
+                    for field in ["body", "state"] {
+                        if !body[field].is_null() {
+                            issue[field] = body[field].clone();
+                        }
+                    }
+                    respond(StatusCode::OK, issue.clone())
+                }
+                None => not_found(),
+            }
+        }
+        (&Method::PATCH, ["repos", owner, name, "issues", "comments", id]) => {
+            let id: u64 = id.parse().unwrap_or_default();
+            let body = body.unwrap_or_default();
+            let comment = state
+                .comments
+                .iter_mut()
+                .filter(|((repo, _), _)| *repo == format!("{}/{}", owner, name))
+                .flat_map(|(_, comments)| comments.iter_mut())
+                .find(|comment| comment["id"] == id);
+            match comment {
+                Some(comment) => {
+                    comment["body"] = body["body"].clone();
+                    respond(StatusCode::OK, comment.clone())
+                }
+                None => not_found(),
+            }
+        }
+        (&Method::GET, ["repos", owner, name, "contents", path @ ..]) => {
+            let reference = query_param(query.as_deref(), "ref").unwrap_or("main");
+            let contents = git2::Repository::open_bare(state.remotes.join(owner).join(name))
+                .ok()
+                .and_then(|repo| {
+                    // A branch or a commit
+                    let tree = repo.revparse_single(reference).ok()?.peel_to_tree().ok()?;
+                    let entry = tree.get_path(Path::new(&path.join("/"))).ok()?;
+                    let blob = entry.to_object(&repo).ok()?.peel_to_blob().ok()?;
+                    Some(blob.content().to_vec())
+                });
+            match contents {
+                // Served raw, as asked for through the Accept header.
+                Some(contents) => Response::builder()
+                    .status(StatusCode::OK)
+                    .body(Body::from(contents))
+                    .unwrap(),
+                None => not_found(),
+            }
+        }
+        (&Method::POST, ["repos", owner, name, "issues", number, "labels"]) => {
+            let key = (format!("{}/{}", owner, name), number.parse().unwrap_or_default());
+            let added: Vec<String> = body
+                .and_then(|b| serde_json::from_value(b["labels"].clone()).ok())
+                .unwrap_or_default();
+            let labels = state.issue_labels.entry(key).or_default();
+            labels.extend(added);
+            let labels: Vec<Value> = labels.iter().map(|l| json!({ "name": l })).collect();
+            respond(StatusCode::OK, Value::from(labels))
+        }
+        _ => not_found(),
+    }
+}
@@ -0,0 +1,339 @@
+// Each test binary uses only some of the helpers.
+#![allow(dead_code)]
+
+pub mod fake_github;
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Once};
+use std::time::Duration;
+
+use docsbot::github::GithubClient;
+use docsbot::handlers::retry::RetryPolicy;
+use docsbot::forge::ChangeRequest;
+use docsbot::handlers::{handle_pr_task, Context, WorkerOptions};
+use docsbot::webhook::{self, EventName};
+use fake_github::FakeGithub;
+use git2::{Repository, RepositoryInitOptions, Signature};
+use serde_json::json;
+use tokio::sync::{mpsc, watch};
+
+pub const REPO: &str = "docsbot-test/website";
+pub const LABEL: &str = "docs/cherry-version-2.0.4";
+pub const BASE_BRANCH: &str = "main";
+
+/// Builds the `docsbot.toml` of a test binary, which all of its tests
+/// share.
+pub struct TestConfig {
+    toml: String,
+}
+
+impl TestConfig {
+    /// A config for [`REPO`], without labels.
+    pub fn new() -> TestConfig {
+        TestConfig {
+            toml: format!("[[repos]]\nname = \"{}\"\nlabels = []\n", REPO),
+        }
+    }
+
+    /// [`TestConfig::new`] with [`LABEL`] syncing to version 2.0.4.
+    pub fn with_label() -> TestConfig {
+        TestConfig::new().label(LABEL, "2.0.4", "")
+    }
+
+    /// Adds a label of the last repository syncing `docs` to the docs of
+    /// `version` on [`BASE_BRANCH`], with `options` set on the label.
+    pub fn label(self, label: &str, version: &str, options: &str) -> TestConfig {
+        self.label_on(label, BASE_BRANCH, version, options)
+    }
+
+    /// [`TestConfig::label`] syncing on `base_branch`.
+    pub fn label_on(mut self, label: &str, base_branch: &str, version: &str, options: &str) -> TestConfig {
+        // A repository with labels lists them as tables instead.
+        if let Some(start) = self.toml.rfind("labels = []\n") {
+            self.toml.replace_range(start..start + "labels = []\n".len(), "");
+        }
+        self.toml.push_str(&format!(
+            "\n[[repos.labels]]\nlabel = \"{label}\"\nbase_branch = \"{base}\"\n{options}\n\n\
+             [[repos.labels.sync_paths]]\nsource_directory = \"docs\"\nsource_sidebars = \"sidebars.js\"\n\
+             target_directory = \"versioned_docs/version-{version}\"\n\
+             target_sidebars = \"versioned_sidebars/version-{version}-sidebars.json\"\n",
+            label = label,
+            base = base_branch,
+            options = options,
+            version = version,
+        ));
+        self
+    }
+
+    /// Appends `toml` as is, e.g. to set options of the last sync path or
+    /// add a repository.
+    pub fn raw(mut self, toml: &str) -> TestConfig {
+        self.toml.push_str(toml);
+        self.toml.push('\n');
+        self
+    }
+
+    pub fn build(self) -> String {
+        self.toml
+    }
+}
+
+static INIT: Once = Once::new();
+
+/// Process-wide setup: docsbot reads its configuration file and git identity
+/// from the environment, which is shared by every test in the binary, so
+/// they must all pass the same `config`.
+pub fn init(config: &str) {
+    INIT.call_once(|| {
+        let dir = std::env::temp_dir().join(format!("docsbot-tests-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("docsbot.toml");
+        std::fs::write(&path, config).unwrap();
+
+        std::env::set_var("DOCSBOT_CONFIG", &path);
+        for var in &["GIT_AUTHOR_NAME", "GIT_COMMITTER_NAME"] {
+            std::env::set_var(var, "docsbot");
+        }
+        for var in &["GIT_AUTHOR_EMAIL", "GIT_COMMITTER_EMAIL"] {
+            std::env::set_var(var, "docsbot@example.com");
+        }
+    });
+}
+
+/// Everything a test needs to run the bot offline: a fake GitHub, a
+/// directory of bare remotes and a scratch working directory.
+pub struct Harness {
+    pub github: FakeGithub,
+    pub ctx: Arc<Context>,
+    pub remotes: tempfile::TempDir,
+    pub workdir: tempfile::TempDir,
+}
+
+impl Harness {
+    /// A harness running docsbot with `config`, built with [`TestConfig`].
+    pub async fn new(config: &str) -> Harness {
+        init(config);
+
+        let remotes = tempfile::tempdir().unwrap();
+        let github = FakeGithub::start(remotes.path().to_path_buf()).await;
+        let workdir = tempfile::tempdir().unwrap();
+
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        docsbot::db::run_migrations(&conn).unwrap();
+
+        let ctx = Arc::new(Context {
+            github: GithubClient::new(reqwest::Client::new(), "token".to_string())
+                .with_api_url(&github.url)
+                .with_git_url(remotes.path().to_str().unwrap()),
+            gitlab: None,
+            gitea: None,
+            db: Mutex::new(conn),
+            username: "docsbot".to_string(),
+            workdir: workdir.path().to_path_buf(),
+            retry: RetryPolicy {
+                max_attempts: 3,
+                base_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(10),
+            },
+            background: Default::default(),
+        });
+
+        Harness {
+            github,
+            ctx,
+            remotes,
+            workdir,
+        }
+    }
+
+    /// Creates the bare remote for `repo` with a single commit on `branch`
+    /// containing `files`.
+    pub fn create_remote(&self, repo: &str, branch: &str, files: &[(&str, &str)]) -> PathBuf {
+        let seed_dir = tempfile::tempdir().unwrap();
+        let mut opts = RepositoryInitOptions::new();
+        opts.initial_head(branch);
+        let seed = Repository::init_opts(seed_dir.path(), &opts).unwrap();
+
+        for (path, content) in files {
+            let path = seed_dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+
+        let mut index = seed.index().unwrap();
+        index
+            .add_all(["."].iter(), git2::IndexAddOption::DEFAULT, None)
+            .unwrap();
+        index.write().unwrap();
+        let tree = seed.find_tree(index.write_tree().unwrap()).unwrap();
+        let sig = Signature::now("docsbot", "docsbot@example.com").unwrap();
+        seed.commit(Some("HEAD"), &sig, &sig, "initial commit", &tree, &[])
+            .unwrap();
+
+        let remote = self.remotes.path().join(repo);
+        git2::build::RepoBuilder::new()
+            .bare(true)
+            .clone(seed_dir.path().to_str().unwrap(), &remote)
+            .unwrap();
+        remote
+    }
+
+    /// Runs the sync jobs for what comes through `receiver` until it is
+    /// closed, and waits for what the webhooks left running.
+    pub async fn process(&self, receiver: mpsc::UnboundedReceiver<ChangeRequest>) {
+        let options = WorkerOptions {
+            workers: 2,
+            shutdown_timeout: Duration::from_secs(5),
+            janitor_interval: None,
+        };
+        handle_pr_task(self.ctx.clone(), receiver, options, watch::channel(false).1)
+            .await
+            .unwrap();
+        self.ctx.background.wait().await;
+    }
+
+    /// Merges pull request `number` of [`REPO`], labeled `labels` and
+    /// changing `files`, and runs the sync jobs it queues.
+    pub async fn merge(&self, number: u64, labels: &[&str], files: &[&str]) {
+        let files = files.iter().map(|file| changed_file(file, "@@ -1 +1 @@")).collect();
+        self.github.set_pull_request_files(REPO, number, files);
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let merge_commit = format!("{:040x}", number);
+        let payload = pull_request_payload("closed", number, true, &merge_commit, labels);
+        webhook::webhook(EventName::PullRequest, payload, &self.ctx, tx)
+            .await
+            .unwrap();
+        self.process(rx).await;
+    }
+
+    /// Commits `files` on top of `branch` in the remote of `repo`.
+    pub fn commit_to_branch(&self, repo: &str, branch: &str, files: &[(&str, &str)]) -> git2::Oid {
+        let reference = format!("refs/heads/{}", branch);
+        let sig = Signature::now("docsbot", "docsbot@example.com").unwrap();
+        self.commit_to_ref(repo, &reference, &reference, files, &sig, "update")
+    }
+
+    /// Commits `files` on top of `parent` in the remote of `repo` and points
+    /// `reference` at the commit, e.g. to make up the commits of a pull
+    /// request.
+    pub fn commit_to_ref(
+        &self,
+        repo: &str,
+        reference: &str,
+        parent: &str,
+        files: &[(&str, &str)],
+        author: &Signature,
+        message: &str,
+    ) -> git2::Oid {
+        let remote = self.remote(repo);
+        let parent = remote.find_reference(parent).unwrap().peel_to_commit().unwrap();
+
+        let mut builder = git2::build::TreeUpdateBuilder::new();
+        for (path, content) in files {
+            let blob = remote.blob(content.as_bytes()).unwrap();
+            builder.upsert(*path, blob, git2::FileMode::Blob);
+        }
+        let tree_id = builder.create_updated(&remote, &parent.tree().unwrap()).unwrap();
+        let tree = remote.find_tree(tree_id).unwrap();
+
+        let committer = Signature::now("docsbot", "docsbot@example.com").unwrap();
+        let oid = remote
+            .commit(None, author, &committer, message, &tree, &[&parent])
+            .unwrap();
+        remote.reference(reference, oid, true, message).unwrap();
+        oid
+    }
+
+    /// Creates `branch` in the remote of `repo`, pointing at the tip of
+    /// `from`.
+    pub fn create_branch(&self, repo: &str, branch: &str, from: &str) {
+        let remote = self.remote(repo);
+        let commit = remote
+            .find_branch(from, git2::BranchType::Local)
+            .unwrap()
+            .get()
+            .peel_to_commit()
+            .unwrap();
+        remote.branch(branch, &commit, false).unwrap();
+    }
+
+    pub fn remote(&self, repo: &str) -> Repository {
+        Repository::open_bare(self.remotes.path().join(repo)).unwrap()
+    }
+}
+
+/// Reads `path` at the tip of `branch` in `repo`.
+pub fn read_file(repo: &Repository, branch: &str, path: &str) -> Option<String> {
+    let tree = repo
+        .find_branch(branch, git2::BranchType::Local)
+        .ok()?
+        .get()
+        .peel_to_tree()
+        .ok()?;
+    let blob = tree.get_path(Path::new(path)).ok()?.to_object(repo).ok()?;
+    Some(String::from_utf8(blob.as_blob()?.content().to_vec()).unwrap())
+}
+
+/// The branch pull request `number` is synced to [`LABEL`] on.
+pub fn sync_branch(number: u64) -> String {
+    format!("docsbot/{}-{}", number, LABEL.replace('/', "-"))
+}
+
+/// A `pull_request` webhook payload.
+pub fn pull_request_payload(
+    action: &str,
+    number: u64,
+    merged: bool,
+    merge_commit_sha: &str,
+    labels: &[&str],
+) -> String {
+    let labels: Vec<_> = labels.iter().map(|l| json!({ "name": l })).collect();
+    json!({
+        "action": action,
+        "number": number,
+        "pull_request": {
+            "number": number,
+            "body": "Update the docs",
+            "created_at": "2021-01-01T00:00:00Z",
+            "updated_at": "2021-01-01T00:00:00Z",
+            "merge_commit_sha": merge_commit_sha,
+            "title": "Update the docs",
+            "html_url": format!("https://github.com/{}/pull/{}", REPO, number),
+            "diff_url": format!("https://github.com/{}/pull/{}.diff", REPO, number),
+            "user": { "login": "contributor", "id": 2 },
+            "labels": labels,
+            "assignees": [],
+            "merged": merged,
+            "comments_url": format!("https://api.github.com/repos/{}/issues/{}/comments", REPO, number),
+        },
+        "repository": { "full_name": REPO },
+    })
+    .to_string()
+}
+
+/// An entry of the pull request commits endpoint.
+pub fn pull_request_commit(sha: git2::Oid, message: &str, author: &Signature) -> serde_json::Value {
+    let date = chrono::DateTime::from_timestamp(author.when().seconds(), 0).unwrap();
+    json!({
+        "sha": sha.to_string(),
+        "commit": {
+            "message": message,
+            "author": {
+                "name": author.name().unwrap(),
+                "email": author.email().unwrap(),
+                "date": date.to_rfc3339(),
+            },
+        },
+        "parents": [],
+    })
+}
+
+/// An entry of the pull request files endpoint.
+pub fn changed_file(filename: &str, patch: &str) -> serde_json::Value {
+    json!({
+        "filename": filename,
+        "status": "modified",
+        "patch": patch,
+    })
+}
@@ -0,0 +1,399 @@
+mod support;
+
+use std::time::Duration;
+
+use docsbot::handlers::{handle_pr_task, WorkerOptions};
+use docsbot::webhook::{self, EventName, WebhookOutcome};
+use hyper::{Method, StatusCode};
+use tokio::sync::{mpsc, watch};
+use support::{
+    changed_file, pull_request_payload, read_file, sync_branch, Harness, TestConfig, BASE_BRANCH, LABEL, REPO,
+};
+
+const MERGE_SHA: &str = "4f0c2a1b9d8e7f6a5b4c3d2e1f0a9b8c7d6e5f4a";
+/// Synced on a release branch
+const RELEASE_LABEL: &str = "docs/release-version-2.0.4";
+const RELEASE_BRANCH: &str = "release-2.0";
+
+fn config() -> String {
+    TestConfig::with_label()
+        .label_on(RELEASE_LABEL, RELEASE_BRANCH, "2.0.4", "")
+        .build()
+}
+
+#[tokio::test]
+async fn merged_pull_request_opens_sync_pr() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "# Intro\n\nUpdated text.\n"),
+            ("versioned_docs/version-2.0.4/intro.md", "# Intro\n\nOld text.\n"),
+        ],
+    );
+    h.github.set_pull_request_files(
+        REPO,
+        42,
+        vec![changed_file(
+            "docs/intro.md",
+            "@@ -1,3 +1,3 @@\n # Intro\n \n-Old text.\n+Updated text.",
+        )],
+    );
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let payload = pull_request_payload("closed", 42, true, MERGE_SHA, &[LABEL]);
+    let outcome = webhook::webhook(EventName::PullRequest, payload, &h.ctx, tx)
+        .await
+        .unwrap();
+    assert!(matches!(outcome, WebhookOutcome::Processed));
+    h.process(rx).await;
+
+    let branch = sync_branch(42);
+    let remote = h.remote(REPO);
+    assert_eq!(
+        read_file(&remote, &branch, "versioned_docs/version-2.0.4/intro.md").as_deref(),
+        Some("# Intro\n\nUpdated text.\n"),
+    );
+
+    let pulls = h.github.pull_requests(REPO);
+    assert_eq!(pulls.len(), 1);
+    assert_eq!(pulls[0]["head"]["ref"], *branch);
+    assert_eq!(pulls[0]["base"]["ref"], BASE_BRANCH);
+    assert_eq!(pulls[0]["title"], format!("sync docs to {}", LABEL));
+}
+
+#[tokio::test]
+async fn labels_on_different_base_branches_sync_apart() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "# Intro\n\nUpdated text.\n"),
+            ("versioned_docs/version-2.0.4/intro.md", "# Intro\n\nOld text.\n"),
+        ],
+    );
+    h.create_branch(REPO, RELEASE_BRANCH, BASE_BRANCH);
+
+    // Both labels land in different lanes, which run at the same time.
+    h.merge(44, &[LABEL, RELEASE_LABEL], &["docs/intro.md"]).await;
+
+    let mut pulls = h.github.pull_requests(REPO);
+    pulls.sort_by_key(|pr| pr["base"]["ref"].as_str().unwrap().to_string());
+    assert_eq!(pulls.len(), 2);
+    assert_eq!(pulls[0]["base"]["ref"], BASE_BRANCH);
+    assert_eq!(pulls[1]["base"]["ref"], RELEASE_BRANCH);
+    assert_ne!(pulls[0]["head"]["ref"], pulls[1]["head"]["ref"]);
+    let remote = h.remote(REPO);
+    for pr in pulls.iter() {
+        assert_eq!(
+            read_file(&remote, pr["head"]["ref"].as_str().unwrap(), "versioned_docs/version-2.0.4/intro.md")
+                .as_deref(),
+            Some("# Intro\n\nUpdated text.\n"),
+        );
+    }
+}
+
+#[tokio::test]
+async fn unmerged_pull_request_is_ignored() {
+    let h = Harness::new(&config()).await;
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let payload = pull_request_payload("closed", 43, false, MERGE_SHA, &[LABEL]);
+    webhook::webhook(EventName::PullRequest, payload, &h.ctx, tx)
+        .await
+        .unwrap();
+    h.process(rx).await;
+
+    assert!(h.github.calls().is_empty());
+    assert!(h.github.pull_requests(REPO).is_empty());
+}
+
+#[tokio::test]
+async fn merges_to_the_same_branch_are_all_synced() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "# Intro\n"),
+            ("docs/usage.md", "# Usage\n"),
+            ("versioned_docs/version-2.0.4/intro.md", "# Old intro\n"),
+            ("versioned_docs/version-2.0.4/usage.md", "# Old usage\n"),
+        ],
+    );
+    let merges = [
+        (44, "1111111111111111111111111111111111111111", "docs/intro.md"),
+        (45, "2222222222222222222222222222222222222222", "docs/usage.md"),
+    ];
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    for (number, sha, file) in &merges {
+        h.github
+            .set_pull_request_files(REPO, *number, vec![changed_file(file, "@@ -1 +1 @@")]);
+        let payload = pull_request_payload("closed", *number, true, sha, &[LABEL]);
+        webhook::webhook(EventName::PullRequest, payload, &h.ctx, tx.clone())
+            .await
+            .unwrap();
+    }
+    drop(tx);
+    h.process(rx).await;
+
+    let heads: Vec<_> = h
+        .github
+        .pull_requests(REPO)
+        .iter()
+        .map(|pr| pr["head"]["ref"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(heads, vec![sync_branch(merges[0].0), sync_branch(merges[1].0)]);
+}
+
+#[tokio::test]
+async fn transient_failures_are_retried() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "# Intro\n"),
+            ("versioned_docs/version-2.0.4/intro.md", "# Old intro\n"),
+        ],
+    );
+    let pulls_path = format!("/repos/{}/pulls", REPO);
+    h.github
+        .fail(Method::POST, &pulls_path, StatusCode::BAD_GATEWAY, 2);
+
+    h.merge(46, &[LABEL], &["docs/intro.md"]).await;
+
+    let attempts = h
+        .github
+        .calls()
+        .iter()
+        .filter(|c| c.method == Method::POST && c.path == pulls_path)
+        .count();
+    assert_eq!(attempts, 3);
+    assert_eq!(h.github.pull_requests(REPO).len(), 1);
+    assert!(h.github.comments(REPO, 46).is_empty());
+}
+
+#[tokio::test]
+async fn rate_limited_requests_are_retried() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "# Intro\n"),
+            ("versioned_docs/version-2.0.4/intro.md", "# Old intro\n"),
+        ],
+    );
+    // GitHub answers 403 once the rate limit runs out, not only 429.
+    let files_path = format!("/repos/{}/pulls/48/files", REPO);
+    h.github
+        .fail_with_headers(Method::GET, &files_path, StatusCode::FORBIDDEN, &[("X-RateLimit-Remaining", "0")], 1);
+    let pulls_path = format!("/repos/{}/pulls", REPO);
+    h.github
+        .fail_with_headers(Method::POST, &pulls_path, StatusCode::FORBIDDEN, &[("Retry-After", "1")], 1);
+
+    h.merge(48, &[LABEL], &["docs/intro.md"]).await;
+
+    let calls = h.github.calls();
+    // Every attempt lists the files again.
+    assert_eq!(calls.iter().filter(|c| c.path == files_path).count(), 3);
+    assert_eq!(calls.iter().filter(|c| c.method == Method::POST && c.path == pulls_path).count(), 2);
+    assert_eq!(h.github.pull_requests(REPO).len(), 1);
+    assert!(h.github.comments(REPO, 48).is_empty());
+}
+
+#[tokio::test]
+async fn permanent_failures_are_reported_on_the_pull_request() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(REPO, BASE_BRANCH, &[("docs/intro.md", "# Intro\n")]);
+    let files_path = format!("/repos/{}/pulls/47/files", REPO);
+    h.github.fail(Method::GET, &files_path, StatusCode::NOT_FOUND, 1);
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    let payload = pull_request_payload("closed", 47, true, MERGE_SHA, &[LABEL]);
+    webhook::webhook(EventName::PullRequest, payload, &h.ctx, tx)
+        .await
+        .unwrap();
+    h.process(rx).await;
+
+    assert_eq!(
+        h.github.calls().iter().filter(|c| c.path == files_path).count(),
+        1
+    );
+    assert!(h.github.pull_requests(REPO).is_empty());
+
+    let comments = h.github.comments(REPO, 47);
+    assert_eq!(comments.len(), 1);
+    let body = comments[0]["body"].as_str().unwrap();
+    assert!(body.contains("failed to sync"), "{}", body);
+    assert!(body.contains("a request to the forge API failed"), "{}", body);
+    assert!(body.contains("404"), "{}", body);
+}
+
+#[tokio::test]
+async fn a_failing_job_does_not_stop_the_next_one() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "# Intro\n"),
+            ("versioned_docs/version-2.0.4/intro.md", "# Old intro\n"),
+        ],
+    );
+    // The first pull request claims to change a file the repository does
+    // not have, so it cannot be applied.
+    let merges = [
+        (48, "3333333333333333333333333333333333333333", "docs/missing.md"),
+        (49, "4444444444444444444444444444444444444444", "docs/intro.md"),
+    ];
+
+    let (tx, rx) = mpsc::unbounded_channel();
+    for (number, sha, file) in &merges {
+        h.github
+            .set_pull_request_files(REPO, *number, vec![changed_file(file, "@@ -1 +1 @@")]);
+        let payload = pull_request_payload("closed", *number, true, sha, &[LABEL]);
+        webhook::webhook(EventName::PullRequest, payload, &h.ctx, tx.clone())
+            .await
+            .unwrap();
+    }
+    drop(tx);
+    h.process(rx).await;
+
+    let comments = h.github.comments(REPO, 48);
+    assert_eq!(comments.len(), 1);
+    let body = comments[0]["body"].as_str().unwrap();
+    assert!(body.contains("`docs/missing.md` could not be applied"), "{}", body);
+
+    let pulls = h.github.pull_requests(REPO);
+    assert_eq!(pulls.len(), 1);
+    assert_eq!(pulls[0]["head"]["ref"], sync_branch(merges[1].0));
+}
+
+#[tokio::test]
+async fn jobs_queued_at_shutdown_run_on_the_next_start() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "# Intro\n"),
+            ("versioned_docs/version-2.0.4/intro.md", "# Old intro\n"),
+        ],
+    );
+    h.github
+        .set_pull_request_files(REPO, 50, vec![changed_file("docs/intro.md", "@@ -1 +1 @@")]);
+
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    shutdown_tx.send(true).unwrap();
+    let (tx, rx) = mpsc::unbounded_channel();
+    let payload = pull_request_payload("closed", 50, true, MERGE_SHA, &[LABEL]);
+    webhook::webhook(EventName::PullRequest, payload, &h.ctx, tx)
+        .await
+        .unwrap();
+    let options = WorkerOptions {
+        workers: 2,
+        shutdown_timeout: Duration::from_secs(5),
+        janitor_interval: None,
+    };
+    handle_pr_task(h.ctx.clone(), rx, options, shutdown_rx)
+        .await
+        .unwrap();
+    assert!(h.github.pull_requests(REPO).is_empty());
+
+    // Nothing new comes in after the restart.
+    let (tx, rx) = mpsc::unbounded_channel();
+    drop(tx);
+    h.process(rx).await;
+
+    let pulls = h.github.pull_requests(REPO);
+    assert_eq!(pulls.len(), 1);
+    assert_eq!(pulls[0]["head"]["ref"], sync_branch(50));
+}
+
+#[tokio::test]
+async fn syncing_a_pull_request_again_updates_its_sync_pr() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "# Intro\n"),
+            ("versioned_docs/version-2.0.4/intro.md", "# Old intro\n"),
+        ],
+    );
+
+    // The same webhook delivered twice, e.g. redelivered by hand.
+    for _ in 0..2 {
+        h.merge(51, &[LABEL], &["docs/intro.md"]).await;
+    }
+
+    let pulls = h.github.pull_requests(REPO);
+    assert_eq!(pulls.len(), 1);
+    assert_eq!(pulls[0]["head"]["ref"], sync_branch(51));
+    let body = pulls[0]["body"].as_str().unwrap();
+    assert!(body.contains(&format!("https://github.com/{}/pull/51", REPO)), "{}", body);
+    assert!(body.contains(&format!("<!-- docsbot-sync: {}#51 {} -->", REPO, LABEL)), "{}", body);
+
+    let pull_path = format!("/repos/{}/pulls/{}", REPO, pulls[0]["number"]);
+    let updates = h
+        .github
+        .calls()
+        .into_iter()
+        .filter(|c| c.method == Method::PATCH && c.path == pull_path)
+        .count();
+    assert_eq!(updates, 1);
+}
+
+#[tokio::test]
+async fn syncing_again_leaves_commits_pushed_onto_the_sync_branch_alone() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "# Intro\n"),
+            ("versioned_docs/version-2.0.4/intro.md", "# Old intro\n"),
+        ],
+    );
+    h.merge(53, &[LABEL], &["docs/intro.md"]).await;
+
+    // A maintainer fixes up the sync PR by hand.
+    let fixup = h.commit_to_branch(REPO, &sync_branch(53), &[("versioned_docs/version-2.0.4/intro.md", "# Fixed\n")]);
+    h.merge(53, &[LABEL], &["docs/intro.md"]).await;
+
+    let remote = h.remote(REPO);
+    let head = remote.refname_to_id(&format!("refs/heads/{}", sync_branch(53))).unwrap();
+    assert_eq!(head, fixup);
+    let comments = h.github.comments(REPO, 53);
+    assert_eq!(comments.len(), 1);
+    let body = comments[0]["body"].as_str().unwrap();
+    assert!(body.contains("has commits docsbot did not push"), "{}", body);
+}
+
+#[tokio::test]
+async fn files_in_new_directories_are_synced() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "# Intro\n"),
+            ("docs/guides/deploy/new.md", "# Deploying\n"),
+            ("versioned_docs/version-2.0.4/intro.md", "# Intro\n"),
+        ],
+    );
+
+    h.merge(54, &[LABEL], &["docs/guides/deploy/new.md"]).await;
+
+    assert!(h.github.comments(REPO, 54).is_empty());
+    let remote = h.remote(REPO);
+    assert_eq!(
+        read_file(&remote, &sync_branch(54), "versioned_docs/version-2.0.4/guides/deploy/new.md").as_deref(),
+        Some("# Deploying\n")
+    );
+}
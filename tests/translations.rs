@@ -0,0 +1,126 @@
+mod support;
+
+use docsbot::webhook::{self, EventName};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use support::{changed_file, pull_request_payload, Harness, TestConfig, BASE_BRANCH};
+
+const TRANSLATED_REPO: &str = "docsbot-test/translated";
+const TRANSLATION: &str = "i18n/zh/docusaurus-plugin-content-docs/current/intro.md";
+
+fn config() -> String {
+    TestConfig::new()
+        .raw(
+            r#"
+[[repos]]
+name = "docsbot-test/translated"
+labels = []
+
+[[repos.translations]]
+language = "zh"
+source_directory = "docs"
+translation_directory = "i18n/zh/docusaurus-plugin-content-docs/current"
+stale_label = "translation/zh-stale""#,
+        )
+        .build()
+}
+
+async fn merge(h: &Harness, number: u64, merge_commit: git2::Oid, files: Vec<Value>) {
+    h.github.set_pull_request_files(TRANSLATED_REPO, number, files);
+    let mut payload: Value =
+        serde_json::from_str(&pull_request_payload("closed", number, true, &merge_commit.to_string(), &[])).unwrap();
+    payload["repository"] = json!({ "full_name": TRANSLATED_REPO });
+
+    let (tx, _rx) = mpsc::unbounded_channel();
+    webhook::webhook(EventName::PullRequest, payload.to_string(), &h.ctx, tx)
+        .await
+        .unwrap();
+    h.ctx.background.wait().await;
+}
+
+#[tokio::test]
+async fn stale_translations_are_tracked_in_an_issue() {
+    let h = Harness::new(&config()).await;
+    h.create_remote(
+        TRANSLATED_REPO,
+        BASE_BRANCH,
+        &[
+            ("docs/intro.md", "# Intro\n\nSome text.\n"),
+            ("docs/usage.md", "# Usage\n"),
+            (TRANSLATION, "# 简介\n\n一些文字。\n"),
+        ],
+    );
+
+    // Only docs with a translation can leave one stale.
+    let first = h.commit_to_branch(
+        TRANSLATED_REPO,
+        BASE_BRANCH,
+        &[("docs/intro.md", "# Intro\n\nBetter text.\n"), ("docs/usage.md", "# Usage\n\nRun it.\n")],
+    );
+    merge(
+        &h,
+        140,
+        first,
+        vec![
+            changed_file("docs/intro.md", "@@ -1,3 +1,3 @@\n # Intro\n \n-Some text.\n+Better text."),
+            changed_file("docs/usage.md", "@@ -1 +1,3 @@\n # Usage\n+\n+Run it."),
+        ],
+    )
+    .await;
+
+    let issues = h.github.issues(TRANSLATED_REPO);
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0]["title"], "Stale `zh` translations");
+    let body = issues[0]["body"].as_str().unwrap();
+    assert!(body.contains(&format!("#### `{}`", TRANSLATION)), "{}", body);
+    assert!(body.contains("Missing changes from #140."), "{}", body);
+    assert!(body.contains("-Some text.\n+Better text."), "{}", body);
+    assert!(!body.contains("usage.md"), "{}", body);
+    assert_eq!(h.github.issue_labels(TRANSLATED_REPO, 140), vec!["translation/zh-stale"]);
+
+    let second = h.commit_to_branch(TRANSLATED_REPO, BASE_BRANCH, &[("docs/intro.md", "# Intro\n\nBest text.\n")]);
+    merge(
+        &h,
+        141,
+        second,
+        vec![changed_file("docs/intro.md", "@@ -1,3 +1,3 @@\n # Intro\n \n-Better text.\n+Best text.")],
+    )
+    .await;
+
+    let issues = h.github.issues(TRANSLATED_REPO);
+    assert_eq!(issues.len(), 1);
+    let body = issues[0]["body"].as_str().unwrap();
+    assert!(body.contains("Missing changes from #140, #141."), "{}", body);
+
+    // The translation catches up.
+    let updated = h.commit_to_branch(TRANSLATED_REPO, BASE_BRANCH, &[(TRANSLATION, "# 简介\n\n最好的文字。\n")]);
+    merge(
+        &h,
+        142,
+        updated,
+        vec![changed_file(TRANSLATION, "@@ -1,3 +1,3 @@\n # 简介\n \n-一些文字。\n+最好的文字。")],
+    )
+    .await;
+
+    let issues = h.github.issues(TRANSLATED_REPO);
+    assert_eq!(issues[0]["state"], "closed");
+    assert!(issues[0]["body"].as_str().unwrap().contains("up to date"));
+    assert!(h.github.issue_labels(TRANSLATED_REPO, 142).is_empty());
+
+    // And falls behind again.
+    let third = h.commit_to_branch(TRANSLATED_REPO, BASE_BRANCH, &[("docs/intro.md", "# Intro\n\nFinal text.\n")]);
+    merge(
+        &h,
+        143,
+        third,
+        vec![changed_file("docs/intro.md", "@@ -1,3 +1,3 @@\n # Intro\n \n-Best text.\n+Final text.")],
+    )
+    .await;
+
+    let issues = h.github.issues(TRANSLATED_REPO);
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0]["state"], "open");
+    let body = issues[0]["body"].as_str().unwrap();
+    assert!(body.contains(&format!("Last updated along with {}.", updated)), "{}", body);
+    assert!(body.contains("Missing changes from #143."), "{}", body);
+}